@@ -0,0 +1,348 @@
+//! Interpreter throughput on a few representative workloads.
+//!
+//! Run with `cargo bench`, or `cargo bench -- fib strings` for the workloads whose names
+//! contain any of the words. Each workload is registered in a fresh interpreter and its `main`
+//! is run a few times; the fastest run is reported, with the instructions it ran and the heap
+//! allocations it made.
+
+extern crate tricone;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tricone::asm;
+use tricone::interpreter::{Instruction, Interpreter, ObjectToken};
+use tricone::lang;
+use tricone::moduledef::ModuleDef;
+
+/// The system allocator, counting what goes through it.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const RUNS: usize = 5;
+
+enum Source {
+    Lang(&'static str),
+    Asm(&'static str),
+}
+
+struct Workload {
+    name: &'static str,
+    source: Source,
+    /// What `main` returns, displayed, so a broken interpreter can't look fast.
+    expected: &'static str,
+}
+
+const WORKLOADS: &[Workload] = &[
+    Workload {
+        name: "fib",
+        source: Source::Lang(
+            "
+fn fib(n) {
+    if n < 2 {
+        return n;
+    }
+    fib(n - 1) + fib(n - 2)
+}
+fn main() {
+    fib(22)
+}
+",
+        ),
+        expected: "17711",
+    },
+    Workload {
+        name: "while_sum",
+        // The loop goes through `builtins.while`, with the state in the module's globals
+        source: Source::Asm(
+            "
+module bench
+fn cond 0
+    get_module_globals bench
+    get_member i
+    get_module_globals bench
+    get_member n
+    call_method lt 1 keep
+end
+fn body 2
+    get_module_globals bench
+    get_module_globals bench
+    get_member total
+    get_module_globals bench
+    get_member i
+    call_method add 1 keep
+    set_member total
+    get_module_globals bench
+    get_module_globals bench
+    get_member i
+    create_int 1
+    call_method add 1 keep
+    set_member i
+end
+fn main 0
+    get_module_globals bench
+    create_int 0
+    set_member i
+    get_module_globals bench
+    create_int 0
+    set_member total
+    get_module_globals builtins
+    get_member while
+    lookup_name cond
+    lookup_name body
+    call_function_object 2 discard
+    get_module_globals bench
+    get_member total
+end
+init
+    create_int 50000
+    assign n
+end
+",
+        ),
+        expected: "1249975000",
+    },
+    Workload {
+        name: "local_sum",
+        source: Source::Lang(
+            "
+fn main() {
+    let total = 0;
+    let i = 0;
+    while i < 100000 {
+        total = total + i;
+        i = i + 1;
+    }
+    total
+}
+",
+        ),
+        expected: "4999950000",
+    },
+    Workload {
+        name: "strings",
+        source: Source::Lang(
+            "
+fn main() {
+    let text = \"\";
+    let i = 0;
+    while i < 2000 {
+        text = text + i.tostring() + \",\";
+        i = i + 1;
+    }
+    let last = \"\";
+    i = 0;
+    while i < 2000 {
+        last = \"item \" + i.tostring();
+        i = i + 1;
+    }
+    last
+}
+",
+        ),
+        expected: "item 1999",
+    },
+    Workload {
+        name: "dispatch",
+        source: Source::Lang(
+            "
+type Square {
+    fn create(self, side) { self.side = side; }
+    fn area(self) { self.side * self.side }
+}
+type Rect {
+    fn create(self, width, height) { self.width = width; self.height = height; }
+    fn area(self) { self.width * self.height }
+}
+type Total {
+    fn create(self) { self.value = 0; }
+    fn add(self, shape) { self.value = self.value + shape.area(); }
+}
+fn main() {
+    let square = new Square(3);
+    let rect = new Rect(2, 5);
+    let total = new Total();
+    let i = 0;
+    while i < 20000 {
+        total.add(square);
+        total.add(rect);
+        i = i + 1;
+    }
+    total.value
+}
+",
+        ),
+        expected: "380000",
+    },
+    Workload {
+        name: "churn",
+        source: Source::Lang(
+            "
+type Drops {
+    fn create(self) { self.count = 0; }
+}
+let drops = new Drops();
+type Node {
+    fn create(self, value) { self.value = value; }
+    fn drop(self) { drops.count = drops.count + 1; }
+}
+fn main() {
+    let i = 0;
+    let node = new Node(0);
+    while i < 20000 {
+        node = new Node(i);
+        i = i + 1;
+    }
+    drops.count
+}
+",
+        ),
+        expected: "20000",
+    },
+];
+
+struct Measurement {
+    elapsed: Duration,
+    instructions: u64,
+    objects: u64,
+    allocations: usize,
+    allocated_bytes: usize,
+}
+
+fn compile(workload: &Workload) -> Result<ModuleDef, String> {
+    match workload.source {
+        Source::Lang(source) => lang::compile_module("bench", source).map_err(|e| e.to_string()),
+        Source::Asm(source) => asm::parse_module(source).map_err(|e| e.to_string()),
+    }
+}
+
+fn display(interpreter: &mut Interpreter, obj: Option<ObjectToken>) -> Result<String, String> {
+    let obj = match obj {
+        Some(obj) => obj,
+        None => return Ok(String::new()),
+    };
+    let text = interpreter.display_object(&obj).map_err(|e| e.to_string());
+    interpreter.drop_token(obj);
+    text
+}
+
+fn run(workload: &Workload) -> Result<Measurement, String> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_tracing(false);
+    compile(workload)?
+        .register(&mut interpreter)
+        .map_err(|e| e.to_string())?;
+    let main = [
+        Instruction::Import {
+            name: interpreter.intern("bench"),
+        },
+        Instruction::GetMember {
+            name: interpreter.intern("main"),
+        },
+        Instruction::CallFunctionObject {
+            num_args: 0,
+            use_result: true,
+        },
+    ];
+
+    interpreter.reset_stats();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let res = interpreter.run_code(&main);
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes;
+    let stats = interpreter.stats();
+
+    let res = res.map_err(|e| e.to_string())?;
+    let text = display(&mut interpreter, res)?;
+    if text != workload.expected {
+        return Err(format!("returned {}, not {}", text, workload.expected));
+    }
+    Ok(Measurement {
+        elapsed,
+        instructions: stats.total_instructions(),
+        objects: stats.types.iter().map(|ty| ty.created).sum(),
+        allocations,
+        allocated_bytes,
+    })
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`, anything else narrows down the workloads
+    let filters: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    println!(
+        "{:<10} {:>12} {:>10} {:>14} {:>10} {:>12} {:>14}",
+        "workload", "instructions", "time", "instructions/s", "objects", "allocations", "bytes"
+    );
+    let mut failed = false;
+    for workload in WORKLOADS {
+        if !filters.is_empty() && !filters.iter().any(|f| workload.name.contains(f.as_str())) {
+            continue;
+        }
+        let mut best: Option<Measurement> = None;
+        for _ in 0..RUNS {
+            match run(workload) {
+                Ok(measurement) => {
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| measurement.elapsed < best.elapsed)
+                    {
+                        best = Some(measurement);
+                    }
+                }
+                Err(err) => {
+                    println!("{:<10} failed: {}", workload.name, err);
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        if let Some(m) = best {
+            let seconds = m.elapsed.as_secs_f64();
+            println!(
+                "{:<10} {:>12} {:>8.2}ms {:>14.0} {:>10} {:>12} {:>14}",
+                workload.name,
+                m.instructions,
+                seconds * 1000.0,
+                m.instructions as f64 / seconds,
+                m.objects,
+                m.allocations,
+                m.allocated_bytes
+            );
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
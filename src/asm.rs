@@ -0,0 +1,658 @@
+//! Text assembly for tricone modules.
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! module hello
+//!
+//! type Hello
+//!     method greet 1
+//!         create_string "hello from method!"
+//!         call_method println 0 discard
+//!     end
+//! end
+//!
+//! fn count 0
+//!     locals n
+//!     create_int 0
+//!     store_local 0
+//! again:
+//!     load_local 0
+//!     create_int 1
+//!     call_method add 1 keep
+//!     store_local 0
+//!     jump again
+//! end
+//!
+//! init
+//!     create_int 42
+//!     assign answer
+//! end
+//!
+//! export answer count
+//! ```
+//!
+//! The optional `init` block runs once when the module is registered, with the module's globals
+//! as its scope. `export` lines restrict which globals code outside the module can see.
+//!
+//! Every instruction is written as the snake_case name of its `Instruction` variant followed by
+//! its operands. `call_method` and `call_function_object` end with `keep` or `discard` for
+//! `use_result`, and `jump` and `jump_if_false` take either a label or an instruction index.
+//! A function body may start with a `locals` line naming the slots `load_local` and
+//! `store_local` refer to, in order.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+
+use debuginfo::DebugInfo;
+use interpreter::Instruction;
+use loader::ASSEMBLY_EXTENSION;
+use moduledef::{BytecodeFunctionDef, FunctionDef, ModuleDef, TypeDef};
+use symbol::Symbol;
+
+#[derive(Debug, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new<S: Into<String>>(line: usize, message: S) -> AsmError {
+        AsmError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+struct Line {
+    number: usize,
+    column: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn word(&self, idx: usize) -> Result<&str, AsmError> {
+        match self.tokens.get(idx) {
+            Some(Token::Word(word)) => Ok(word),
+            Some(Token::Str(_)) => Err(AsmError::new(self.number, "unexpected string literal")),
+            None => Err(AsmError::new(self.number, "missing operand")),
+        }
+    }
+
+    fn string(&self, idx: usize) -> Result<&str, AsmError> {
+        match self.tokens.get(idx) {
+            Some(Token::Str(value)) => Ok(value),
+            Some(Token::Word(_)) => Err(AsmError::new(self.number, "expected a string literal")),
+            None => Err(AsmError::new(self.number, "missing operand")),
+        }
+    }
+
+    fn number<T: ::std::str::FromStr>(&self, idx: usize) -> Result<T, AsmError> {
+        let word = self.word(idx)?;
+        word.parse()
+            .map_err(|_| AsmError::new(self.number, format!("invalid number `{}`", word)))
+    }
+
+    fn use_result(&self, idx: usize) -> Result<bool, AsmError> {
+        match self.word(idx)? {
+            "keep" => Ok(true),
+            "discard" => Ok(false),
+            other => Err(AsmError::new(
+                self.number,
+                format!("expected `keep` or `discard`, found `{}`", other),
+            )),
+        }
+    }
+
+    fn expect_operands(&self, count: usize) -> Result<(), AsmError> {
+        if self.tokens.len() == count + 1 {
+            Ok(())
+        } else {
+            Err(AsmError::new(
+                self.number,
+                format!(
+                    "expected {} operand(s), found {}",
+                    count,
+                    self.tokens.len() - 1
+                ),
+            ))
+        }
+    }
+}
+
+fn tokenize_line(number: usize, text: &str) -> Result<Vec<Token>, AsmError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('\\') => value.push('\\'),
+                        Some('"') => value.push('"'),
+                        other => {
+                            return Err(AsmError::new(
+                                number,
+                                format!("invalid escape sequence `\\{}`", other.unwrap_or(' ')),
+                            ))
+                        }
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(AsmError::new(number, "unterminated string literal")),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ';' || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Line>, AsmError> {
+    let mut lines = vec![];
+    for (idx, text) in source.lines().enumerate() {
+        let tokens = tokenize_line(idx + 1, text)?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: idx + 1,
+                column: text.len() - text.trim_start().len() + 1,
+                tokens,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+enum JumpTarget {
+    Resolved(usize),
+    Label(String, usize),
+}
+
+fn parse_instruction(
+    line: &Line,
+    jumps: &mut Vec<(usize, JumpTarget)>,
+    pos: usize,
+) -> Result<Instruction, AsmError> {
+    use interpreter::Instruction::*;
+
+    let mnemonic = line.word(0)?;
+    let (insn, operands) = match mnemonic {
+        "create_object" => (
+            CreateObject {
+                type_spec: (Symbol::new(line.word(1)?), Symbol::new(line.word(2)?)),
+                num_args: line.number(3)?,
+            },
+            3,
+        ),
+        "assign" => (
+            Assign {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "get_top_scope" => (GetTopScope, 0),
+        "get_module_globals" => (
+            GetModuleGlobals {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "import" => (
+            Import {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "call_method" => (
+            CallMethod {
+                name: Symbol::new(line.word(1)?),
+                num_args: line.number(2)?,
+                use_result: line.use_result(3)?,
+            },
+            3,
+        ),
+        "get_member" => (
+            GetMember {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "lookup_name" => (
+            LookupName {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "call_function_object" => (
+            CallFunctionObject {
+                num_args: line.number(1)?,
+                use_result: line.use_result(2)?,
+            },
+            2,
+        ),
+        "create_string" => (
+            CreateString {
+                value: line.string(1)?.to_owned(),
+            },
+            1,
+        ),
+        "create_int" => (
+            CreateInt {
+                value: line.number(1)?,
+            },
+            1,
+        ),
+        "create_bool" => (
+            CreateBool {
+                value: match line.word(1)? {
+                    "true" => true,
+                    "false" => false,
+                    other => {
+                        return Err(AsmError::new(
+                            line.number,
+                            format!("expected `true` or `false`, found `{}`", other),
+                        ))
+                    }
+                },
+            },
+            1,
+        ),
+        "jump" | "jump_if_false" => {
+            let target = line.word(1)?;
+            jumps.push((
+                pos,
+                match target.parse() {
+                    Ok(idx) => JumpTarget::Resolved(idx),
+                    Err(_) => JumpTarget::Label(target.to_owned(), line.number),
+                },
+            ));
+            if mnemonic == "jump" {
+                (Jump { to: 0 }, 1)
+            } else {
+                (JumpIfFalse { to: 0 }, 1)
+            }
+        }
+        "set_member" => (
+            SetMember {
+                name: Symbol::new(line.word(1)?),
+            },
+            1,
+        ),
+        "load_local" => (
+            LoadLocal {
+                slot: line.number(1)?,
+            },
+            1,
+        ),
+        "store_local" => (
+            StoreLocal {
+                slot: line.number(1)?,
+            },
+            1,
+        ),
+        "pop" => (Pop, 0),
+        "diag" => (Diag, 0),
+        "debug_print_object" => (DebugPrintObject, 0),
+        other => {
+            return Err(AsmError::new(
+                line.number,
+                format!("unknown instruction `{}`", other),
+            ))
+        }
+    };
+    line.expect_operands(operands)?;
+    Ok(insn)
+}
+
+fn label_name(line: &Line) -> Option<&str> {
+    match line.tokens.as_slice() {
+        [Token::Word(word)] if word.len() > 1 && word.ends_with(':') => {
+            Some(&word[..word.len() - 1])
+        }
+        _ => None,
+    }
+}
+
+/// Parses instruction lines until `end` (if `terminated`) or the end of input, recording the line
+/// of each instruction.
+fn parse_body<'a, I>(
+    lines: &mut I,
+    terminated: bool,
+    start_line: usize,
+) -> Result<(Vec<Instruction>, DebugInfo), AsmError>
+where
+    I: Iterator<Item = &'a Line>,
+{
+    let mut instructions = vec![];
+    let mut debug_info = DebugInfo::default();
+    let mut labels = HashMap::new();
+    let mut jumps = vec![];
+    let mut closed = false;
+
+    for line in lines {
+        if terminated && line.tokens == [Token::Word("end".to_owned())] {
+            closed = true;
+            break;
+        }
+        if let Some(label) = label_name(line) {
+            if labels
+                .insert(label.to_owned(), instructions.len())
+                .is_some()
+            {
+                return Err(AsmError::new(
+                    line.number,
+                    format!("duplicate label `{}`", label),
+                ));
+            }
+            continue;
+        }
+        let pos = instructions.len();
+        instructions.push(parse_instruction(line, &mut jumps, pos)?);
+        debug_info.add(pos, line.number, line.column);
+    }
+
+    if terminated && !closed {
+        return Err(AsmError::new(start_line, "missing `end`"));
+    }
+
+    for (pos, target) in jumps {
+        let to = match target {
+            JumpTarget::Resolved(to) => to,
+            JumpTarget::Label(label, line) => *labels
+                .get(&label)
+                .ok_or_else(|| AsmError::new(line, format!("unknown label `{}`", label)))?,
+        };
+        match instructions[pos] {
+            Instruction::Jump { to: ref mut target }
+            | Instruction::JumpIfFalse { to: ref mut target } => *target = to,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok((instructions, debug_info))
+}
+
+/// Parses a function body up to its `end`, after an optional `locals` line.
+fn parse_function<'a, I>(
+    lines: &mut Peekable<I>,
+    start_line: usize,
+    arity: usize,
+    file: &str,
+) -> Result<BytecodeFunctionDef, AsmError>
+where
+    I: Iterator<Item = &'a Line>,
+{
+    let mut locals = vec![];
+    if let Some(line) = lines.next_if(|line| line.word(0).is_ok_and(|word| word == "locals")) {
+        for idx in 1..line.tokens.len() {
+            locals.push(Symbol::new(line.word(idx)?));
+        }
+    }
+    let (instructions, mut debug_info) = parse_body(lines, true, start_line)?;
+    debug_info.file = file.to_owned();
+    Ok(BytecodeFunctionDef {
+        arity,
+        instructions,
+        debug_info: Some(debug_info),
+        locals,
+    })
+}
+
+fn parse_function_header(line: &Line) -> Result<(String, usize), AsmError> {
+    line.expect_operands(2)?;
+    Ok((line.word(1)?.to_owned(), line.number(2)?))
+}
+
+/// Parses a bare sequence of instructions and labels, as found inside a function body.
+pub fn parse_instructions(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    let lines = tokenize(source)?;
+    Ok(parse_body(&mut lines.iter(), false, 1)?.0)
+}
+
+pub fn parse_module(source: &str) -> Result<ModuleDef, AsmError> {
+    let lines = tokenize(source)?;
+    let mut lines = lines.iter().peekable();
+
+    let name = match lines.next() {
+        Some(line) if line.word(0)? == "module" => {
+            line.expect_operands(1)?;
+            line.word(1)?.to_owned()
+        }
+        Some(line) => return Err(AsmError::new(line.number, "expected `module <name>`")),
+        None => return Err(AsmError::new(1, "expected `module <name>`")),
+    };
+
+    // Until the loader tells us the path of the file
+    let file = format!("{}.{}", name, ASSEMBLY_EXTENSION);
+    let mut def = ModuleDef {
+        name,
+        types: HashMap::new(),
+        free_functions: HashMap::new(),
+        init: None,
+        exports: None,
+        access: None,
+    };
+
+    while let Some(line) = lines.next() {
+        match line.word(0)? {
+            "init" => {
+                line.expect_operands(0)?;
+                if def.init.is_some() {
+                    return Err(AsmError::new(line.number, "duplicate `init` block"));
+                }
+                def.init = Some(parse_function(&mut lines, line.number, 0, &file)?);
+            }
+            "export" => {
+                let exports = def.exports.get_or_insert_with(Vec::new);
+                for idx in 1..line.tokens.len() {
+                    exports.push(line.word(idx)?.to_owned());
+                }
+            }
+            "fn" => {
+                let (name, arity) = parse_function_header(line)?;
+                let function =
+                    FunctionDef::Bytecode(parse_function(&mut lines, line.number, arity, &file)?);
+                if def.free_functions.insert(name.clone(), function).is_some() {
+                    return Err(AsmError::new(
+                        line.number,
+                        format!("duplicate function `{}`", name),
+                    ));
+                }
+            }
+            "type" => {
+                line.expect_operands(1)?;
+                let type_name = line.word(1)?.to_owned();
+                let mut methods = HashMap::new();
+                let mut closed = false;
+                while let Some(line) = lines.next() {
+                    match line.word(0)? {
+                        "end" => {
+                            line.expect_operands(0)?;
+                            closed = true;
+                            break;
+                        }
+                        "method" => {
+                            let (name, arity) = parse_function_header(line)?;
+                            let method = FunctionDef::Bytecode(parse_function(
+                                &mut lines,
+                                line.number,
+                                arity,
+                                &file,
+                            )?);
+                            if methods.insert(name.clone(), method).is_some() {
+                                return Err(AsmError::new(
+                                    line.number,
+                                    format!("duplicate method `{}`", name),
+                                ));
+                            }
+                        }
+                        other => {
+                            return Err(AsmError::new(
+                                line.number,
+                                format!("expected `method` or `end`, found `{}`", other),
+                            ))
+                        }
+                    }
+                }
+                if !closed {
+                    return Err(AsmError::new(line.number, "missing `end`"));
+                }
+                if def
+                    .types
+                    .insert(type_name.clone(), TypeDef { methods })
+                    .is_some()
+                {
+                    return Err(AsmError::new(
+                        line.number,
+                        format!("duplicate type `{}`", type_name),
+                    ));
+                }
+            }
+            other => {
+                return Err(AsmError::new(
+                    line.number,
+                    format!(
+                        "expected `fn`, `type`, `init` or `export`, found `{}`",
+                        other
+                    ),
+                ))
+            }
+        }
+    }
+
+    Ok(def)
+}
+
+fn format_string(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+    for c in value.chars() {
+        match c {
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn format_use_result(use_result: bool) -> &'static str {
+    if use_result {
+        "keep"
+    } else {
+        "discard"
+    }
+}
+
+pub fn format_instruction(insn: &Instruction) -> String {
+    use interpreter::Instruction::*;
+    match *insn {
+        CreateObject {
+            type_spec: (ref module, ref type_),
+            num_args,
+        } => format!("create_object {} {} {}", module, type_, num_args),
+        Assign { ref name } => format!("assign {}", name),
+        GetTopScope => "get_top_scope".to_owned(),
+        GetModuleGlobals { ref name } => format!("get_module_globals {}", name),
+        Import { ref name } => format!("import {}", name),
+        CallMethod {
+            ref name,
+            num_args,
+            use_result,
+        } => format!(
+            "call_method {} {} {}",
+            name,
+            num_args,
+            format_use_result(use_result)
+        ),
+        GetMember { ref name } => format!("get_member {}", name),
+        LookupName { ref name } => format!("lookup_name {}", name),
+        CallFunctionObject {
+            num_args,
+            use_result,
+        } => format!(
+            "call_function_object {} {}",
+            num_args,
+            format_use_result(use_result)
+        ),
+        CreateString { ref value } => format!("create_string {}", format_string(value)),
+        CreateInt { value } => format!("create_int {}", value),
+        CreateBool { value } => format!("create_bool {}", value),
+        Jump { to } => format!("jump L{}", to),
+        JumpIfFalse { to } => format!("jump_if_false L{}", to),
+        SetMember { ref name } => format!("set_member {}", name),
+        Pop => "pop".to_owned(),
+        Diag => "diag".to_owned(),
+        DebugPrintObject => "debug_print_object".to_owned(),
+        CreateObjectResolved { type_, num_args } => format!(
+            "create_object_resolved {} {} {}",
+            type_.module().0,
+            type_.position(),
+            num_args
+        ),
+        GetModuleGlobalsResolved { module } => {
+            format!("get_module_globals_resolved {}", module.0)
+        }
+        LoadLocal { slot } => format!("load_local {}", slot),
+        StoreLocal { slot } => format!("store_local {}", slot),
+    }
+}
+
+/// Formats a function body so that `parse_instructions` reads it back, labelling jump targets.
+/// Linked code comes out with module and type indices, which don't read back.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    let mut targets: Vec<usize> = instructions
+        .iter()
+        .filter_map(Instruction::jump_target)
+        .collect();
+    targets.sort();
+    targets.dedup();
+
+    let mut res = String::new();
+    for (pos, insn) in instructions.iter().enumerate() {
+        if targets.binary_search(&pos).is_ok() {
+            res.push_str(&format!("L{}:\n", pos));
+        }
+        res.push_str(&format!("    {:<40} ; {}\n", format_instruction(insn), pos));
+    }
+    if targets.binary_search(&instructions.len()).is_ok() {
+        res.push_str(&format!("L{}:\n", instructions.len()));
+    }
+    res
+}
@@ -1,6 +1,371 @@
 extern crate tricone;
 
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+
+use tricone::asm;
+use tricone::debugger::{Breakpoint, DebugHandler, Pause, PauseReason, Resume};
+use tricone::function::NativeResult;
+use tricone::interpreter::{ErrorKind, Instruction, Interpreter, ObjectToken, Scope, TriconeError};
+use tricone::loader::FileModuleLoader;
+use tricone::moduledef::ModuleDef;
+use tricone::profiler::Profiler;
+use tricone::Symbol;
+
+const USAGE: &str =
+    "usage: tricone [repl | debug FILE [FUNCTION] | profile [--folded] FILE [FUNCTION] | dap]
+set TRICONE_FS_ROOT to a directory to let programs use it through the `fs` module";
+
+const DEBUG_HELP: &str = "\
+s, step                       run one instruction, entering calls
+n, next                       run to the next instruction of this frame
+o, out                        run until this frame returns
+c, continue                   run to the next breakpoint
+b, break MODULE FUNCTION POS  pause before instruction POS of a function (`Type::method`)
+d, delete MODULE FUNCTION POS remove a breakpoint
+breakpoints                   list breakpoints
+bt, backtrace                 show the active frames
+l, list                       disassemble the code of this frame
+stack                         show the operation stack
+scope [FRAME]                 show the variables of a frame, the innermost by default
+p, print NAME                 show a variable of this frame
+x, examine NAME               show the members of a variable of this frame
+q, quit                       stop the program";
+
+struct CliDebugger;
+
+fn display(interpreter: &mut Interpreter, obj: &ObjectToken) -> String {
+    let type_name = interpreter.get_type(obj.type_index()).name().to_owned();
+    match interpreter.display_object(obj) {
+        Ok(text) => format!("{}: {}", text, type_name),
+        Err(err) => format!("<{}: {}>", type_name, err),
+    }
+}
+
+fn drop_scopes(interpreter: &mut Interpreter, scopes: Vec<Scope>) {
+    for scope in scopes {
+        interpreter.drop_token(scope.vars);
+    }
+}
+
+/// Looks `name` up in the scopes of the innermost frame.
+fn lookup(interpreter: &mut Interpreter, name: &str) -> Option<ObjectToken> {
+    let name = Symbol::lookup(name)?;
+    let depth = interpreter.backtrace().len();
+    let scopes = interpreter.frame_scopes(depth.checked_sub(1)?);
+    let found = scopes
+        .iter()
+        .find_map(|scope| scope.vars.obj().members.get(&name).map(ObjectToken::dup));
+    drop_scopes(interpreter, scopes);
+    found
+}
+
+fn show_scopes(interpreter: &mut Interpreter, frame: usize) {
+    let scopes = interpreter.frame_scopes(frame);
+    if scopes.is_empty() {
+        println!("no frame {}", frame);
+    }
+    for (depth, scope) in scopes.iter().enumerate() {
+        println!("scope {}:", depth);
+        let mut members: Vec<_> = scope
+            .vars
+            .obj()
+            .members
+            .iter()
+            .filter(|&(name, _)| !name.starts_with('!'))
+            .map(|(&name, value)| (name, value.dup()))
+            .collect();
+        members.sort_by_key(|&(name, _)| name);
+        for (name, value) in members {
+            println!("  {} = {}", name, display(interpreter, &value));
+            interpreter.drop_token(value);
+        }
+    }
+    drop_scopes(interpreter, scopes);
+}
+
+fn examine(interpreter: &mut Interpreter, name: &str) {
+    let obj = match lookup(interpreter, name) {
+        Some(obj) => obj,
+        None => return println!("`{}` is not defined", name),
+    };
+    println!("{}", display(interpreter, &obj));
+    let mut members: Vec<_> = obj
+        .obj()
+        .members
+        .iter()
+        .map(|(&name, value)| (name, value.dup()))
+        .collect();
+    members.sort_by_key(|&(name, _)| name);
+    for (name, value) in members {
+        println!("  .{} = {}", name, display(interpreter, &value));
+        interpreter.drop_token(value);
+    }
+    interpreter.drop_token(obj);
+}
+
+fn list(interpreter: &Interpreter, pos: usize) {
+    let depth = interpreter.backtrace().len();
+    let bytecode = match depth
+        .checked_sub(1)
+        .and_then(|frame| interpreter.frame_bytecode(frame))
+    {
+        Some(bytecode) => bytecode,
+        None => return println!("this frame runs no bytecode"),
+    };
+    for (idx, insn) in bytecode.instructions.iter().enumerate() {
+        let marker = if idx == pos { "=>" } else { "  " };
+        println!("{} {:>4}  {}", marker, idx, asm::format_instruction(insn));
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> Option<Breakpoint> {
+    match *args {
+        [module, function, pos] => Some(Breakpoint::new(module, function, pos.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn show_location(interpreter: &Interpreter, insn: &Instruction) {
+    match interpreter.backtrace().last() {
+        Some(frame) => println!("{}", frame),
+        None => println!("in host code"),
+    }
+    println!("    {}", asm::format_instruction(insn));
+}
+
+impl DebugHandler for CliDebugger {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> Resume {
+        if let PauseReason::Breakpoint(ref breakpoint) = pause.reason {
+            println!(
+                "breakpoint {} {} {}",
+                breakpoint.module, breakpoint.function, breakpoint.instruction
+            );
+        }
+        show_location(interpreter, pause.instruction);
+
+        let stdin = io::stdin();
+        let mut line = String::new();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush().ok();
+            line.clear();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // Out of input, let the program finish
+                return Resume::Continue;
+            }
+            let words: Vec<_> = line.split_whitespace().collect();
+            let (command, args) = match words.split_first() {
+                Some((command, args)) => (*command, args),
+                None => continue,
+            };
+            match command {
+                "s" | "step" => return Resume::StepInto,
+                "n" | "next" => return Resume::StepOver,
+                "o" | "out" => return Resume::StepOut,
+                "c" | "continue" => return Resume::Continue,
+                "b" | "break" => match parse_breakpoint(args) {
+                    Some(breakpoint) => interpreter.add_breakpoint(breakpoint),
+                    None => println!("usage: break MODULE FUNCTION POS"),
+                },
+                "d" | "delete" => match parse_breakpoint(args) {
+                    Some(breakpoint) => {
+                        if !interpreter.remove_breakpoint(&breakpoint) {
+                            println!("no such breakpoint");
+                        }
+                    }
+                    None => println!("usage: delete MODULE FUNCTION POS"),
+                },
+                "breakpoints" => {
+                    for breakpoint in interpreter.breakpoints() {
+                        println!(
+                            "{} {} {}",
+                            breakpoint.module, breakpoint.function, breakpoint.instruction
+                        );
+                    }
+                }
+                "bt" | "backtrace" => {
+                    for (idx, frame) in interpreter.backtrace().iter().enumerate() {
+                        println!("{:>3} {}", idx, frame);
+                    }
+                }
+                "l" | "list" => list(interpreter, pause.pos),
+                "stack" => {
+                    let stack: Vec<_> = interpreter
+                        .operation_stack()
+                        .iter()
+                        .map(ObjectToken::dup)
+                        .collect();
+                    for (idx, obj) in stack.into_iter().enumerate() {
+                        println!("{:>3} {}", idx, display(interpreter, &obj));
+                        interpreter.drop_token(obj);
+                    }
+                }
+                "scope" => {
+                    let innermost = interpreter.backtrace().len().saturating_sub(1);
+                    match args.first().map(|frame| frame.parse()) {
+                        None => show_scopes(interpreter, innermost),
+                        Some(Ok(frame)) => show_scopes(interpreter, frame),
+                        Some(Err(_)) => println!("usage: scope [FRAME]"),
+                    }
+                }
+                "p" | "print" if args.len() == 1 => match lookup(interpreter, args[0]) {
+                    Some(obj) => {
+                        println!("{}", display(interpreter, &obj));
+                        interpreter.drop_token(obj);
+                    }
+                    None => println!("`{}` is not defined", args[0]),
+                },
+                "x" | "examine" if args.len() == 1 => examine(interpreter, args[0]),
+                "q" | "quit" => return Resume::Abort,
+                "h" | "help" => println!("{}", DEBUG_HELP),
+                _ => println!("unknown command `{}`, try help", line.trim()),
+            }
+        }
+    }
+}
+
+/// Loads the module in `path`, with its directory to import other modules from.
+fn load_program(interpreter: &mut Interpreter, path: &str) -> Result<ModuleDef, String> {
+    interpreter.set_tracing(false);
+    let path = Path::new(path);
+    let mut loader = FileModuleLoader::new();
+    if let Some(dir) = path.parent() {
+        loader.add_search_path(dir);
+    }
+    interpreter.set_module_loader(Box::new(loader));
+    FileModuleLoader::load_file(path).map_err(|err| err.to_string())
+}
+
+fn run_program(interpreter: &mut Interpreter, def: ModuleDef, function: &str) -> NativeResult {
+    let module = interpreter.intern(&def.name);
+    let function = interpreter.intern(function);
+    def.register(interpreter)?;
+    interpreter.run_code(&[
+        Instruction::Import { name: module },
+        Instruction::GetMember { name: function },
+        Instruction::CallFunctionObject {
+            num_args: 0,
+            use_result: true,
+        },
+    ])
+}
+
+fn format_error(err: &TriconeError) -> String {
+    let mut message = err.to_string();
+    for frame in &err.traceback {
+        message.push_str(&format!("\n  {}", frame));
+    }
+    message
+}
+
+fn debug(interpreter: &mut Interpreter, path: &str, function: &str) -> Result<(), String> {
+    let def = load_program(interpreter, path)?;
+    interpreter.set_debug_handler(Some(Box::new(CliDebugger)));
+    interpreter.debug_resume(Resume::StepInto);
+    let res = run_program(interpreter, def, function);
+    interpreter.set_debug_handler(None);
+    match res {
+        Ok(Some(obj)) => {
+            println!("{}", display(interpreter, &obj));
+            interpreter.drop_token(obj);
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(TriconeError {
+            kind: ErrorKind::Aborted,
+            ..
+        }) => Ok(()),
+        Err(err) => Err(format_error(&err)),
+    }
+}
+
+/// Runs a program under the profiler and prints the report, or the folded stacks if `folded`.
+fn profile(
+    interpreter: &mut Interpreter,
+    path: &str,
+    function: &str,
+    folded: bool,
+) -> Result<(), String> {
+    let def = load_program(interpreter, path)?;
+    interpreter.start_profiling(Profiler::new());
+    let res = run_program(interpreter, def, function);
+    let profile = interpreter
+        .stop_profiling()
+        .expect("the profiler went away");
+    let res = res.map_err(|err| format_error(&err))?;
+    if folded {
+        let stdout = io::stdout();
+        profile
+            .write_folded(&mut stdout.lock())
+            .map_err(|err| err.to_string())?;
+    } else {
+        if let Some(obj) = res.as_ref() {
+            println!("{}", display(interpreter, obj));
+        }
+        print!("{}", profile);
+    }
+    if let Some(obj) = res {
+        interpreter.drop_token(obj);
+    }
+    Ok(())
+}
+
 fn main() {
     let mut interpreter = tricone::Interpreter::new();
-    tricone::hello::do_hello(&mut interpreter);
+    // Programs run from the command line can use its streams through `io`
+    tricone::io_::register_io(&mut interpreter).expect("Registering io can't fail");
+    // Scripts only get the `fs` module when they are given a directory to work in
+    if let Some(root) = env::var_os("TRICONE_FS_ROOT") {
+        if let Err(err) = tricone::fs_::register_fs(&mut interpreter, root) {
+            eprintln!("error: TRICONE_FS_ROOT: {}", err);
+            process::exit(1);
+        }
+    }
+    let args: Vec<_> = env::args().skip(1).collect();
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        [] => tricone::hello::do_hello(&mut interpreter),
+        ["repl"] => {
+            let mut repl = tricone::repl::Repl::new(interpreter).expect("Creating the repl failed");
+            let stdin = io::stdin();
+            if let Err(err) = repl.run(stdin.lock(), io::stdout()) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        ["debug", path] | ["debug", path, _] => {
+            let function = args.get(2).cloned().unwrap_or("main");
+            if let Err(err) = debug(&mut interpreter, path, function) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        ["dap"] => {
+            if let Err(err) = tricone::dap::serve(io::stdin(), io::stdout()) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        ["profile", "--folded", path] | ["profile", "--folded", path, _] => {
+            let function = args.get(3).cloned().unwrap_or("main");
+            if let Err(err) = profile(&mut interpreter, path, function, true) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        ["profile", path] | ["profile", path, _] => {
+            let function = args.get(2).cloned().unwrap_or("main");
+            if let Err(err) = profile(&mut interpreter, path, function, false) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,444 @@
+//! Binary bytecode files.
+//!
+//! A file is the magic `TRCN`, a little-endian `u16` format version, then the module: its name,
+//! its types (each a name followed by its methods), its free functions, its optional init
+//! function and its optional export list. Functions are their arity, their instructions, their
+//! optional debug info and the names of their local slots; each instruction is its
+//! `InstructionKind` as a byte followed by its operands, and debug info is a file name followed by
+//! `(instruction, line, column)` entries. Files of any other version are refused. Optional
+//! values are prefixed by a presence byte, strings are a `u32` length followed by UTF-8, and
+//! integers are little-endian.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use debuginfo::{DebugInfo, LineEntry};
+use interpreter::{Instruction, InstructionKind};
+use moduledef::{BytecodeFunctionDef, FunctionDef, ModuleDef, TypeDef};
+use symbol::Symbol;
+
+pub const MAGIC: &[u8; 4] = b"TRCN";
+pub const VERSION: u16 = 1;
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+struct Writer<W> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.inner.write_all(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    fn usize(&mut self, value: usize) -> io::Result<()> {
+        if value > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "value too large for bytecode file",
+            ));
+        }
+        self.u32(value as u32)
+    }
+
+    fn i64(&mut self, value: i64) -> io::Result<()> {
+        self.inner.write_all(&value.to_le_bytes())
+    }
+
+    fn bool(&mut self, value: bool) -> io::Result<()> {
+        self.u8(value as u8)
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.usize(value.len())?;
+        self.inner.write_all(value.as_bytes())
+    }
+
+    fn instruction(&mut self, insn: &Instruction) -> io::Result<()> {
+        use interpreter::Instruction::*;
+
+        self.u8(insn.kind() as u8)?;
+        match *insn {
+            CreateObject {
+                type_spec: (ref module, ref type_),
+                num_args,
+            } => {
+                self.string(module.as_str())?;
+                self.string(type_.as_str())?;
+                self.usize(num_args)
+            }
+            Assign { ref name }
+            | GetModuleGlobals { ref name }
+            | Import { ref name }
+            | GetMember { ref name }
+            | SetMember { ref name }
+            | LookupName { ref name } => self.string(name.as_str()),
+            CallMethod {
+                ref name,
+                num_args,
+                use_result,
+            } => {
+                self.string(name.as_str())?;
+                self.usize(num_args)?;
+                self.bool(use_result)
+            }
+            CallFunctionObject {
+                num_args,
+                use_result,
+            } => {
+                self.usize(num_args)?;
+                self.bool(use_result)
+            }
+            CreateString { ref value } => self.string(value),
+            CreateInt { value } => self.i64(value),
+            CreateBool { value } => self.bool(value),
+            Jump { to } | JumpIfFalse { to } => self.usize(to),
+            LoadLocal { slot } | StoreLocal { slot } => self.usize(slot),
+            GetTopScope | Pop | Diag | DebugPrintObject => Ok(()),
+            // Their indices only mean something to the interpreter that linked them
+            CreateObjectResolved { .. } | GetModuleGlobalsResolved { .. } => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "linked instructions can't be written",
+            )),
+        }
+    }
+
+    fn bytecode_function(&mut self, def: &BytecodeFunctionDef) -> io::Result<()> {
+        self.usize(def.arity)?;
+        self.usize(def.instructions.len())?;
+        for insn in &def.instructions {
+            self.instruction(insn)?;
+        }
+        self.bool(def.debug_info.is_some())?;
+        if let Some(ref debug_info) = def.debug_info {
+            self.debug_info(debug_info)?;
+        }
+        self.usize(def.locals.len())?;
+        for local in &def.locals {
+            self.string(local.as_str())?;
+        }
+        Ok(())
+    }
+
+    fn debug_info(&mut self, debug_info: &DebugInfo) -> io::Result<()> {
+        self.string(&debug_info.file)?;
+        self.usize(debug_info.lines.len())?;
+        for entry in &debug_info.lines {
+            self.usize(entry.instruction)?;
+            self.usize(entry.line)?;
+            self.usize(entry.column)?;
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, def: &FunctionDef) -> io::Result<()> {
+        match *def {
+            FunctionDef::Bytecode(ref def) => self.bytecode_function(def),
+            FunctionDef::Native(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "native functions cannot be written to bytecode files",
+            )),
+        }
+    }
+
+    fn functions(&mut self, functions: &HashMap<String, FunctionDef>) -> io::Result<()> {
+        let mut functions: Vec<_> = functions.iter().collect();
+        functions.sort_by(|a, b| a.0.cmp(b.0));
+        self.usize(functions.len())?;
+        for (name, def) in functions {
+            self.string(name)?;
+            self.function(def)?;
+        }
+        Ok(())
+    }
+}
+
+struct Reader<R> {
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        Ok(u32::from_le_bytes(self.bytes()?) as usize)
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.bytes()?))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(invalid_data(format!("invalid boolean {}", other))),
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.usize()?;
+        let mut buf = vec![];
+        (&mut self.inner).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated string",
+            ));
+        }
+        String::from_utf8(buf).map_err(|_| invalid_data("string is not valid UTF-8"))
+    }
+
+    fn symbol(&mut self) -> io::Result<Symbol> {
+        self.string().map(|name| Symbol::new(&name))
+    }
+
+    fn instruction(&mut self) -> io::Result<Instruction> {
+        use interpreter::Instruction::*;
+
+        let opcode = self.u8()?;
+        let kind = *InstructionKind::ALL
+            .get(opcode as usize)
+            .ok_or_else(|| invalid_data(format!("invalid opcode {}", opcode)))?;
+        Ok(match kind {
+            InstructionKind::CreateObject => CreateObject {
+                type_spec: (self.symbol()?, self.symbol()?),
+                num_args: self.usize()?,
+            },
+            InstructionKind::Assign => Assign {
+                name: self.symbol()?,
+            },
+            InstructionKind::GetTopScope => GetTopScope,
+            InstructionKind::GetModuleGlobals => GetModuleGlobals {
+                name: self.symbol()?,
+            },
+            InstructionKind::Import => Import {
+                name: self.symbol()?,
+            },
+            InstructionKind::CallMethod => CallMethod {
+                name: self.symbol()?,
+                num_args: self.usize()?,
+                use_result: self.bool()?,
+            },
+            InstructionKind::GetMember => GetMember {
+                name: self.symbol()?,
+            },
+            InstructionKind::LookupName => LookupName {
+                name: self.symbol()?,
+            },
+            InstructionKind::CallFunctionObject => CallFunctionObject {
+                num_args: self.usize()?,
+                use_result: self.bool()?,
+            },
+            InstructionKind::CreateString => CreateString {
+                value: self.string()?,
+            },
+            InstructionKind::CreateInt => CreateInt { value: self.i64()? },
+            InstructionKind::CreateBool => CreateBool {
+                value: self.bool()?,
+            },
+            InstructionKind::Jump => Jump { to: self.usize()? },
+            InstructionKind::JumpIfFalse => JumpIfFalse { to: self.usize()? },
+            InstructionKind::SetMember => SetMember {
+                name: self.symbol()?,
+            },
+            InstructionKind::LoadLocal => LoadLocal {
+                slot: self.usize()?,
+            },
+            InstructionKind::StoreLocal => StoreLocal {
+                slot: self.usize()?,
+            },
+            InstructionKind::Pop => Pop,
+            InstructionKind::Diag => Diag,
+            InstructionKind::DebugPrintObject => DebugPrintObject,
+            InstructionKind::CreateObjectResolved | InstructionKind::GetModuleGlobalsResolved => {
+                return Err(invalid_data(format!("linked instruction {:?}", kind)))
+            }
+        })
+    }
+
+    fn bytecode_function(&mut self) -> io::Result<BytecodeFunctionDef> {
+        let arity = self.usize()?;
+        let len = self.usize()?;
+        let mut instructions = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            instructions.push(self.instruction()?);
+        }
+        if let Some(to) = instructions
+            .iter()
+            .filter_map(Instruction::jump_target)
+            .find(|&to| to > len)
+        {
+            return Err(invalid_data(format!("jump target {} out of range", to)));
+        }
+        let debug_info = if self.bool()? {
+            Some(self.debug_info(len)?)
+        } else {
+            None
+        };
+        let count = self.usize()?;
+        let mut locals = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            locals.push(self.symbol()?);
+        }
+        if let Some(slot) = instructions
+            .iter()
+            .filter_map(|insn| match *insn {
+                Instruction::LoadLocal { slot } | Instruction::StoreLocal { slot } => Some(slot),
+                _ => None,
+            })
+            .find(|&slot| slot >= locals.len())
+        {
+            return Err(invalid_data(format!("local slot {} out of range", slot)));
+        }
+        Ok(BytecodeFunctionDef {
+            arity,
+            instructions,
+            debug_info,
+            locals,
+        })
+    }
+
+    fn debug_info(&mut self, num_instructions: usize) -> io::Result<DebugInfo> {
+        let mut debug_info = DebugInfo::new(self.string()?);
+        let len = self.usize()?;
+        for _ in 0..len {
+            let entry = LineEntry {
+                instruction: self.usize()?,
+                line: self.usize()?,
+                column: self.usize()?,
+            };
+            let in_order = debug_info
+                .lines
+                .last()
+                .is_none_or(|last| last.instruction < entry.instruction);
+            if !in_order || entry.instruction >= num_instructions {
+                return Err(invalid_data("bad debug info"));
+            }
+            debug_info.lines.push(entry);
+        }
+        Ok(debug_info)
+    }
+
+    fn functions(&mut self) -> io::Result<HashMap<String, FunctionDef>> {
+        let len = self.usize()?;
+        let mut functions = HashMap::new();
+        for _ in 0..len {
+            let name = self.string()?;
+            let def = FunctionDef::Bytecode(self.bytecode_function()?);
+            if functions.insert(name.clone(), def).is_some() {
+                return Err(invalid_data(format!("duplicate function `{}`", name)));
+            }
+        }
+        Ok(functions)
+    }
+}
+
+/// Writes `def` as a bytecode file. Fails if the module contains native functions.
+pub fn write_module<W: Write>(def: &ModuleDef, out: W) -> io::Result<()> {
+    let mut writer = Writer { inner: out };
+    writer.inner.write_all(MAGIC)?;
+    writer.u16(VERSION)?;
+    writer.string(&def.name)?;
+
+    let mut types: Vec<_> = def.types.iter().collect();
+    types.sort_by(|a, b| a.0.cmp(b.0));
+    writer.usize(types.len())?;
+    for (name, tydef) in types {
+        writer.string(name)?;
+        writer.functions(&tydef.methods)?;
+    }
+
+    writer.functions(&def.free_functions)?;
+
+    writer.bool(def.init.is_some())?;
+    if let Some(ref init) = def.init {
+        writer.bytecode_function(init)?;
+    }
+
+    writer.bool(def.exports.is_some())?;
+    if let Some(ref exports) = def.exports {
+        writer.usize(exports.len())?;
+        for name in exports {
+            writer.string(name)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_module<R: Read>(input: R) -> io::Result<ModuleDef> {
+    let mut reader = Reader { inner: input };
+    if &reader.bytes::<4>()? != MAGIC {
+        return Err(invalid_data("not a tricone bytecode file"));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported bytecode version {}",
+            version
+        )));
+    }
+
+    let name = reader.string()?;
+    let num_types = reader.usize()?;
+    let mut types = HashMap::new();
+    for _ in 0..num_types {
+        let type_name = reader.string()?;
+        let methods = reader.functions()?;
+        if types
+            .insert(type_name.clone(), TypeDef { methods })
+            .is_some()
+        {
+            return Err(invalid_data(format!("duplicate type `{}`", type_name)));
+        }
+    }
+    let free_functions = reader.functions()?;
+
+    let init = if reader.bool()? {
+        Some(reader.bytecode_function()?)
+    } else {
+        None
+    };
+
+    let exports = if reader.bool()? {
+        let len = reader.usize()?;
+        let mut exports = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            exports.push(reader.string()?);
+        }
+        Some(exports)
+    } else {
+        None
+    };
+
+    Ok(ModuleDef {
+        name,
+        types,
+        free_functions,
+        init,
+        exports,
+        access: None,
+    })
+}
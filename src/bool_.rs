@@ -1,11 +1,29 @@
-use generic;
-use interpreter::{consts, Interpreter, Module, ObjectToken};
+use interpreter::{
+    consts, ErrorKind, Immediate, Interpreter, Module, ObjectToken, TriconeError,
+};
+use string;
 
+pub fn bool_value(token: &ObjectToken) -> Option<bool> {
+    match *token {
+        ObjectToken::Immediate(Immediate::Bool(value)) => Some(value),
+        _ => None,
+    }
+}
+
+// Bools are immediates: there is no object to create or drop, so the type has neither method
 pub fn register_bool_type(interpreter: &mut Interpreter, module: &mut Module) {
-    generic::create_type_for::<bool, _>(interpreter, module, "Bool", |_, _, ty| {
-        generic::impl_display_for::<bool>(ty);
+    module.create_type(interpreter, "Bool", |_, _, ty| {
+        assert_eq!(ty.index, consts::BOOL_TYPE_ID);
+        ty.register_native_method("tostring", 1, |itrp, args| {
+            let value = bool_value(&args[0]).ok_or_else(|| {
+                TriconeError::with_message(ErrorKind::TypeError, "expected a `Bool`")
+            })?;
+            string::create_string(itrp, value.to_string()).map(Some)
+        });
     });
 }
 
-define_core_creator!{create_bool, bool, "Bool"}
+pub fn create_bool(_interpreter: &mut Interpreter, value: bool) -> Result<ObjectToken, TriconeError> {
+    Ok(Immediate::Bool(value).into())
+}
 define_into_native!{from_object, bool, "Bool"}
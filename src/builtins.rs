@@ -2,46 +2,63 @@ use std::collections::HashMap;
 use std::iter::FromIterator;
 
 use bool_;
-use function::function_from_function_object;
-use interpreter::{Interpreter, ObjectToken};
+use function::{function_from_function_object, NativeResult};
+use interpreter::{consts, ErrorKind, Interpreter, ObjectToken, TriconeError};
 use moduledef::{FunctionDef, ModuleDef, NativeFunctionDef};
 
-fn builtin_if(interpreter: &mut Interpreter, args: &[ObjectToken]) -> Option<ObjectToken> {
-    let bool_obj = args[0].obj();
+fn type_error(message: &str) -> TriconeError {
+    TriconeError::with_message(ErrorKind::TypeError, message)
+}
 
-    if *bool_::from_object(interpreter, &bool_obj) {
-        let then = args[1].obj();
-        function_from_function_object(&then)
-            .call(interpreter, &[])
-            .unwrap()
-    } else {
-        let else_ = args[2].obj();
-        function_from_function_object(&else_)
-            .call(interpreter, &[])
-            .unwrap()
+fn condition(value: &ObjectToken) -> Result<bool, TriconeError> {
+    bool_::bool_value(value).ok_or_else(|| type_error("the condition must be a `Bool`"))
+}
+
+fn check_functions(args: &[ObjectToken], what: &str) -> Result<(), TriconeError> {
+    if args
+        .iter()
+        .any(|arg| arg.type_index() != consts::FUNCTION_TYPE_ID)
+    {
+        return Err(type_error(&format!("{} must be functions", what)));
     }
+    Ok(())
+}
+
+fn builtin_if(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let branch = if condition(&args[0])? {
+        &args[1]
+    } else {
+        &args[2]
+    };
+    check_functions(&args[1..], "the branches")?;
+    let branch = branch.obj();
+    function_from_function_object(&branch).call(interpreter, &[])
 }
 
-fn builtin_while(interpreter: &mut Interpreter, args: &[ObjectToken]) -> Option<ObjectToken> {
+fn builtin_while(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    check_functions(args, "the condition and the body")?;
     let cond_obj = args[0].obj();
     let cond = function_from_function_object(&cond_obj);
     let body_obj = args[1].obj();
     let body = function_from_function_object(&body_obj);
 
     loop {
-        let res_obj = cond.call_in_frame(interpreter, &[]).unwrap().unwrap();
-        let res = res_obj.obj();
+        let res_obj = cond
+            .call_in_frame(interpreter, &[])?
+            .ok_or_else(|| type_error("the condition returned nothing"))?;
+        let keep_going = condition(&res_obj);
+        interpreter.drop_token(res_obj);
 
-        if !*bool_::from_object(interpreter, &res) {
+        if !keep_going? {
             break;
         }
 
-        if let Some(res) = body.call_in_frame(interpreter, args).unwrap() {
+        if let Some(res) = body.call_in_frame(interpreter, args)? {
             interpreter.drop_token(res);
-            panic!("While body should return nothing!");
+            return Err(type_error("the body of a `while` must return nothing"));
         }
     }
-    None
+    Ok(None)
 }
 
 pub fn register_builtins(interpreter: &mut Interpreter) {
@@ -51,20 +68,18 @@ pub fn register_builtins(interpreter: &mut Interpreter) {
         free_functions: HashMap::from_iter(vec![
             (
                 "if".to_owned(),
-                FunctionDef::Native(NativeFunctionDef {
-                    arity: 3,
-                    code: Box::new(builtin_if),
-                }),
+                FunctionDef::Native(NativeFunctionDef::new(3, builtin_if)),
             ),
             (
                 "while".to_owned(),
-                FunctionDef::Native(NativeFunctionDef {
-                    arity: 2,
-                    code: Box::new(builtin_while),
-                }),
+                FunctionDef::Native(NativeFunctionDef::new(2, builtin_while)),
             ),
         ]),
+        init: None,
+        exports: None,
+        access: None,
     };
 
-    def.register(interpreter);
+    def.register(interpreter)
+        .expect("Registering builtins can't fail");
 }
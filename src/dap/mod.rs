@@ -0,0 +1,726 @@
+//! A Debug Adapter Protocol server, so editors can debug programs.
+//!
+//! `launch` takes the `program` to run (a source, assembly or bytecode file), the `function` to
+//! call once its module is registered (`main` by default) and `stopOnEntry`. The program runs
+//! after `configurationDone`; breakpoints are set by source line and hit in any module with
+//! debug info for the file, including ones imported later. There is a single thread, and the
+//! frames, scopes and variables it reports are only valid while it is stopped. What the program
+//! prints reaches the client as `output` events.
+
+pub mod json;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use self::json::Value;
+use debugger::{DebugHandler, Pause, PauseReason, Resume, SourceBreakpoint};
+use debuginfo::DebugInfo;
+use interpreter::*;
+use io_;
+use loader::FileModuleLoader;
+use output::LineSink;
+use symbol::Symbol;
+
+const THREAD_ID: usize = 1;
+
+/// Reads one `Content-Length`-framed message, or `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid_data("message is not UTF-8"))?;
+    json::parse(&body).map(Some).map_err(invalid_data)
+}
+
+pub fn write_message<W: Write>(out: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+struct Session<W> {
+    out: W,
+    seq: usize,
+    requests: Receiver<Value>,
+    // Objects shown as variables while stopped; a variables reference is an index plus one
+    handles: Vec<ObjectToken>,
+    // The debug info of the launched module, to check breakpoints before it is registered
+    program_debug_info: Vec<DebugInfo>,
+    stop_on_entry: bool,
+    disconnected: bool,
+    // What the program printed while the session was busy, by category
+    pending_output: Rc<RefCell<Vec<(&'static str, String)>>>,
+}
+
+impl<W: Write> Session<W> {
+    fn send(&mut self, kind: &str, mut members: Vec<(&str, Value)>) -> io::Result<()> {
+        let pending = mem::take(&mut *self.pending_output.borrow_mut());
+        for (category, text) in pending {
+            self.output(category, &text)?;
+        }
+        self.seq += 1;
+        members.insert(0, ("seq", self.seq.into()));
+        members.insert(1, ("type", kind.into()));
+        write_message(&mut self.out, &Value::object(members))
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request.field("seq").clone()),
+                ("success", true.into()),
+                ("command", request.field("command").clone()),
+                ("body", body),
+            ],
+        )
+    }
+
+    fn respond_error(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request.field("seq").clone()),
+                ("success", false.into()),
+                ("command", request.field("command").clone()),
+                ("message", message.into()),
+            ],
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send("event", vec![("event", event.into()), ("body", body)])
+    }
+
+    fn output(&mut self, category: &str, text: &str) -> io::Result<()> {
+        self.event(
+            "output",
+            Value::object(vec![("category", category.into()), ("output", text.into())]),
+        )
+    }
+
+    fn handle(&mut self, obj: ObjectToken) -> usize {
+        self.handles.push(obj);
+        self.handles.len()
+    }
+
+    fn release_handles(&mut self, interpreter: &mut Interpreter) {
+        for obj in self.handles.drain(..) {
+            interpreter.drop_token(obj);
+        }
+    }
+
+    fn set_breakpoints(
+        &mut self,
+        interpreter: &mut Interpreter,
+        request: &Value,
+    ) -> io::Result<()> {
+        let args = request.field("arguments");
+        let path = match args.field("source").field("path").as_str() {
+            Some(path) => normalize_path(path),
+            None => return self.respond_error(request, "missing source path"),
+        };
+        interpreter.clear_source_breakpoints(&path);
+        let lines = args
+            .field("breakpoints")
+            .as_array()
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|breakpoint| breakpoint.field("line").as_usize());
+
+        let mut breakpoints = vec![];
+        for line in lines {
+            let breakpoint = SourceBreakpoint::new(&path, line);
+            let verified = !interpreter
+                .resolve_source_breakpoint(&breakpoint)
+                .is_empty()
+                || self.program_debug_info.iter().any(|debug_info| {
+                    debug_info.file == path && debug_info.line_start(line).is_some()
+                });
+            interpreter.add_source_breakpoint(breakpoint);
+            breakpoints.push(Value::object(vec![
+                ("verified", verified.into()),
+                ("line", line.into()),
+            ]));
+        }
+        self.respond(
+            request,
+            Value::object(vec![("breakpoints", breakpoints.into())]),
+        )
+    }
+
+    fn stack_trace(&mut self, interpreter: &mut Interpreter, request: &Value) -> io::Result<()> {
+        let frames: Vec<_> = interpreter
+            .backtrace()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                let mut members = vec![
+                    ("id", id.into()),
+                    (
+                        "name",
+                        frame.function.as_deref().unwrap_or("<anonymous>").into(),
+                    ),
+                ];
+                match frame.location {
+                    Some(location) => {
+                        members.push((
+                            "source",
+                            Value::object(vec![("path", location.file.into())]),
+                        ));
+                        members.push(("line", location.line.into()));
+                        members.push(("column", location.column.into()));
+                    }
+                    None => {
+                        members.push(("line", 0usize.into()));
+                        members.push(("column", 0usize.into()));
+                    }
+                }
+                Value::object(members)
+            })
+            .collect();
+        let total = frames.len();
+        self.respond(
+            request,
+            Value::object(vec![
+                ("stackFrames", frames.into()),
+                ("totalFrames", total.into()),
+            ]),
+        )
+    }
+
+    fn scopes(&mut self, interpreter: &mut Interpreter, request: &Value) -> io::Result<()> {
+        let frame = match request.field("arguments").field("frameId").as_usize() {
+            Some(frame) => frame,
+            None => return self.respond_error(request, "missing frameId"),
+        };
+        let mut scopes = vec![];
+        for (depth, scope) in interpreter.frame_scopes(frame).into_iter().enumerate() {
+            let name = if depth == 0 {
+                "Locals".to_owned()
+            } else {
+                format!("Enclosing scope {}", depth)
+            };
+            let reference = self.handle(scope.vars);
+            scopes.push(Value::object(vec![
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ]));
+        }
+
+        // The operation stack belongs to no frame, show it with the innermost one
+        if frame + 1 == interpreter.backtrace().len() {
+            let stack = Scope::new();
+            let values: Vec<_> = interpreter
+                .operation_stack()
+                .iter()
+                .map(ObjectToken::dup)
+                .collect();
+            for (idx, value) in values.into_iter().enumerate() {
+                // The snapshot isn't accounted for, so this can't run out of memory
+                let _ = stack
+                    .vars
+                    .assign_member(Symbol::new(&idx.to_string()), value, interpreter);
+            }
+            let reference = self.handle(stack.vars);
+            scopes.push(Value::object(vec![
+                ("name", "Operation stack".into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ]));
+        }
+        self.respond(request, Value::object(vec![("scopes", scopes.into())]))
+    }
+
+    fn variables(&mut self, interpreter: &mut Interpreter, request: &Value) -> io::Result<()> {
+        let reference = request
+            .field("arguments")
+            .field("variablesReference")
+            .as_usize();
+        let obj = match reference.and_then(|reference| self.handles.get(reference.checked_sub(1)?))
+        {
+            Some(obj) => obj.dup(),
+            None => return self.respond_error(request, "unknown variables reference"),
+        };
+        let mut members: Vec<_> = obj
+            .obj()
+            .members
+            .iter()
+            .filter(|&(name, _)| !name.starts_with('!'))
+            .map(|(&name, value)| (name, value.dup()))
+            .collect();
+        interpreter.drop_token(obj);
+        // Numeric names, as in the operation stack, in numeric order
+        members.sort_by(|a, b| (a.0.len(), &a.0).cmp(&(b.0.len(), &b.0)));
+
+        let mut variables = vec![];
+        for (name, value) in members {
+            let mut variable = self.variable(interpreter, value);
+            if let Value::Object(ref mut fields) = variable {
+                fields.insert(0, ("name".to_owned(), name.as_str().into()));
+            }
+            variables.push(variable);
+        }
+        self.respond(
+            request,
+            Value::object(vec![("variables", variables.into())]),
+        )
+    }
+
+    /// Describes `value` and keeps a handle to it if it has members to expand.
+    fn variable(&mut self, interpreter: &mut Interpreter, value: ObjectToken) -> Value {
+        let type_name = interpreter.get_type(value.type_index()).name().to_owned();
+        let text = interpreter
+            .display_object(&value)
+            .unwrap_or_else(|err| format!("<{}>", err));
+        let expandable = value
+            .obj()
+            .members
+            .keys()
+            .any(|name| !name.starts_with('!'));
+        let reference = if expandable {
+            self.handle(value)
+        } else {
+            interpreter.drop_token(value);
+            0
+        };
+        Value::object(vec![
+            ("value", text.into()),
+            ("type", type_name.into()),
+            ("variablesReference", reference.into()),
+        ])
+    }
+
+    fn evaluate(&mut self, interpreter: &mut Interpreter, request: &Value) -> io::Result<()> {
+        let args = request.field("arguments");
+        let name = args.field("expression").as_str().unwrap_or("").trim();
+        let frame = args
+            .field("frameId")
+            .as_usize()
+            .or_else(|| interpreter.backtrace().len().checked_sub(1));
+        let scopes = frame.map_or(vec![], |frame| interpreter.frame_scopes(frame));
+        let found = Symbol::lookup(name).and_then(|name| {
+            scopes
+                .iter()
+                .find_map(|scope| scope.vars.obj().members.get(&name).map(ObjectToken::dup))
+        });
+        for scope in scopes {
+            interpreter.drop_token(scope.vars);
+        }
+        match found {
+            Some(value) => {
+                let variable = self.variable(interpreter, value);
+                let body = match variable {
+                    Value::Object(fields) => Value::Object(
+                        fields
+                            .into_iter()
+                            .map(|(name, value)| match name.as_str() {
+                                "value" => ("result".to_owned(), value),
+                                _ => (name, value),
+                            })
+                            .collect(),
+                    ),
+                    other => other,
+                };
+                self.respond(request, body)
+            }
+            None => self.respond_error(request, &format!("`{}` is not defined", name)),
+        }
+    }
+
+    /// Handles the requests that need no running program. Returns `false` for other commands.
+    fn common_request(
+        &mut self,
+        interpreter: &mut Interpreter,
+        request: &Value,
+    ) -> io::Result<bool> {
+        match request.field("command").as_str().unwrap_or("") {
+            "setBreakpoints" => self.set_breakpoints(interpreter, request)?,
+            "setExceptionBreakpoints" => {
+                self.respond(request, Value::object(vec![("breakpoints", vec![].into())]))?
+            }
+            "threads" => self.respond(
+                request,
+                Value::object(vec![(
+                    "threads",
+                    vec![Value::object(vec![
+                        ("id", THREAD_ID.into()),
+                        ("name", "main".into()),
+                    ])]
+                    .into(),
+                )]),
+            )?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+/// The frame depth and source line of the innermost frame.
+fn current_line(interpreter: &Interpreter) -> (usize, Option<(String, usize)>) {
+    let backtrace = interpreter.backtrace();
+    let line = backtrace
+        .last()
+        .and_then(|frame| frame.location.as_ref())
+        .map(|location| (location.file.clone(), location.line));
+    (backtrace.len(), line)
+}
+
+struct Adapter<W> {
+    session: Rc<RefCell<Session<W>>>,
+    entry: bool,
+    // A step by line, with the frame depth and line it started from
+    line_step: Option<(Resume, usize, (String, usize))>,
+}
+
+impl<W: Write> Adapter<W> {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> io::Result<Resume> {
+        if let Some((resume, depth, ref line)) = self.line_step {
+            if pause.reason == PauseReason::Step
+                && current_line(interpreter) == (depth, Some(line.clone()))
+            {
+                return Ok(resume);
+            }
+        }
+        self.line_step = None;
+
+        let mut session = self.session.borrow_mut();
+        let reason = match pause.reason {
+            _ if self.entry => "entry",
+            PauseReason::Breakpoint(_) | PauseReason::SourceBreakpoint(_) => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::Requested => "pause",
+        };
+        self.entry = false;
+        session.event(
+            "stopped",
+            Value::object(vec![
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        )?;
+
+        loop {
+            let request = match session.requests.recv() {
+                Ok(request) => request,
+                Err(_) => {
+                    session.disconnected = true;
+                    return Ok(Resume::Abort);
+                }
+            };
+            if session.common_request(interpreter, &request)? {
+                continue;
+            }
+            let resume = match request.field("command").as_str().unwrap_or("") {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepInto,
+                "stepOut" => Resume::StepOut,
+                "disconnect" | "terminate" => {
+                    session.disconnected = true;
+                    Resume::Abort
+                }
+                "pause" => {
+                    session.respond(&request, Value::object(vec![]))?;
+                    continue;
+                }
+                "stackTrace" => {
+                    session.stack_trace(interpreter, &request)?;
+                    continue;
+                }
+                "scopes" => {
+                    session.scopes(interpreter, &request)?;
+                    continue;
+                }
+                "variables" => {
+                    session.variables(interpreter, &request)?;
+                    continue;
+                }
+                "evaluate" => {
+                    session.evaluate(interpreter, &request)?;
+                    continue;
+                }
+                other => {
+                    session.respond_error(&request, &format!("unsupported request `{}`", other))?;
+                    continue;
+                }
+            };
+            session.release_handles(interpreter);
+            // A pause requested while we were stopped already happened
+            interpreter
+                .debug_pause_handle()
+                .store(false, Ordering::SeqCst);
+            let body = if resume == Resume::Continue {
+                Value::object(vec![("allThreadsContinued", true.into())])
+            } else {
+                Value::object(vec![])
+            };
+            session.respond(&request, body)?;
+
+            let by_instruction =
+                request.field("arguments").field("granularity").as_str() == Some("instruction");
+            if let (Resume::StepOver, false) | (Resume::StepInto, false) = (resume, by_instruction)
+            {
+                if let (depth, Some(line)) = current_line(interpreter) {
+                    self.line_step = Some((resume, depth, line));
+                }
+            }
+            return Ok(resume);
+        }
+    }
+}
+
+impl<W: Write> DebugHandler for Adapter<W> {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> Resume {
+        // Without a connection there is no one to debug for
+        Adapter::paused(self, interpreter, pause).unwrap_or(Resume::Abort)
+    }
+}
+
+struct Launch {
+    def: ::moduledef::ModuleDef,
+    function: String,
+}
+
+fn launch(
+    interpreter: &mut Interpreter,
+    session: &mut Session<impl Write>,
+    request: &Value,
+) -> Result<Launch, String> {
+    let args = request.field("arguments");
+    let program = args.field("program").as_str().ok_or("missing program")?;
+    let path = normalize_path(program);
+    let path = Path::new(&path);
+
+    let mut loader = FileModuleLoader::new();
+    if let Some(dir) = path.parent() {
+        loader.add_search_path(dir);
+    }
+    interpreter.set_module_loader(Box::new(loader));
+    let def = FileModuleLoader::load_file(path).map_err(|err| err.to_string())?;
+
+    session.program_debug_info = def.debug_infos().cloned().collect();
+    session.stop_on_entry = args.field("stopOnEntry").as_bool().unwrap_or(false);
+    Ok(Launch {
+        def,
+        function: args.field("function").as_str().unwrap_or("main").to_owned(),
+    })
+}
+
+fn run_program<W: Write + 'static>(
+    interpreter: &mut Interpreter,
+    session: &Rc<RefCell<Session<W>>>,
+    launch: Launch,
+) -> io::Result<()> {
+    let stop_on_entry = session.borrow().stop_on_entry;
+    interpreter.set_debug_handler(Some(Box::new(Adapter {
+        session: session.clone(),
+        entry: stop_on_entry,
+        line_step: None,
+    })));
+    interpreter.debug_resume(if stop_on_entry {
+        Resume::StepInto
+    } else {
+        Resume::Continue
+    });
+
+    let Launch { def, function } = launch;
+    let module = Symbol::new(&def.name);
+    let res = def.register(interpreter).and_then(|_| {
+        interpreter.run_code(&[
+            Instruction::Import { name: module },
+            Instruction::GetMember {
+                name: Symbol::new(&function),
+            },
+            Instruction::CallFunctionObject {
+                num_args: 0,
+                use_result: true,
+            },
+        ])
+    });
+    interpreter.set_debug_handler(None);
+    // Passes on unfinished lines
+    let _ = interpreter.stdout().flush();
+    let _ = interpreter.stderr().flush();
+
+    let mut session = session.borrow_mut();
+    let exit_code: usize = match res {
+        Ok(value) => {
+            if let Some(value) = value {
+                let text = interpreter
+                    .display_object(&value)
+                    .unwrap_or_else(|err| err.to_string());
+                interpreter.drop_token(value);
+                session.output("console", &format!("{}\n", text))?;
+            }
+            0
+        }
+        // Aborted on the client's request
+        Err(_) if session.disconnected => 1,
+        Err(err) => {
+            let mut text = format!("error: {}\n", err);
+            for frame in &err.traceback {
+                text.push_str(&format!("  {}\n", frame));
+            }
+            session.output("stderr", &text)?;
+            1
+        }
+    };
+    if !session.disconnected {
+        session.event(
+            "exited",
+            Value::object(vec![("exitCode", exit_code.into())]),
+        )?;
+        session.event("terminated", Value::object(vec![]))?;
+    }
+    Ok(())
+}
+
+/// Sends what the program prints to the client as `output` events, a line at a time.
+fn program_output<W: Write + 'static>(
+    session: &Rc<RefCell<Session<W>>>,
+    category: &'static str,
+) -> impl Write {
+    let pending = session.borrow().pending_output.clone();
+    let session = session.clone();
+    LineSink::new(move |line| {
+        let text = format!("{}\n", line);
+        match session.try_borrow_mut() {
+            // A lost connection shows up in the session's own requests
+            Ok(mut session) => {
+                let _ = session.output(category, &text);
+            }
+            // Like while stopped, when evaluating runs code that prints; it goes out before
+            // the session's next message
+            Err(_) => pending.borrow_mut().push((category, text)),
+        }
+    })
+}
+
+/// Serves one debugging session, reading requests from `input` and writing responses and events
+/// to `output`, until the client disconnects or `input` ends.
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write + 'static,
+{
+    let mut interpreter = Interpreter::new();
+    interpreter.set_tracing(false);
+    io_::register_io(&mut interpreter).expect("Registering io can't fail");
+
+    // Requests arrive while the program runs, `pause` has to get through right away
+    let pause = interpreter.debug_pause_handle();
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(request)) = read_message(&mut input) {
+            if request.field("command").as_str() == Some("pause") {
+                pause.store(true, Ordering::SeqCst);
+            }
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let session = Rc::new(RefCell::new(Session {
+        out: output,
+        seq: 0,
+        requests,
+        handles: vec![],
+        program_debug_info: vec![],
+        stop_on_entry: false,
+        disconnected: false,
+        pending_output: Rc::default(),
+    }));
+    interpreter.set_stdout(Box::new(program_output(&session, "stdout")));
+    interpreter.set_stderr(Box::new(program_output(&session, "stderr")));
+    // stdin carries the protocol, so programs read nothing
+    interpreter.set_stdin(Box::new(io::empty()));
+    let mut launched = None;
+
+    loop {
+        let request = match session.borrow().requests.recv() {
+            Ok(request) => request,
+            Err(_) => break,
+        };
+        let mut s = session.borrow_mut();
+        if s.common_request(&mut interpreter, &request)? {
+            continue;
+        }
+        match request.field("command").as_str().unwrap_or("") {
+            "initialize" => {
+                s.respond(
+                    &request,
+                    Value::object(vec![
+                        ("supportsConfigurationDoneRequest", true.into()),
+                        ("supportsEvaluateForHovers", true.into()),
+                        ("supportsTerminateRequest", true.into()),
+                    ]),
+                )?;
+                s.event("initialized", Value::object(vec![]))?;
+            }
+            "launch" => match launch(&mut interpreter, &mut s, &request) {
+                Ok(program) => {
+                    launched = Some(program);
+                    s.respond(&request, Value::object(vec![]))?;
+                }
+                Err(message) => s.respond_error(&request, &message)?,
+            },
+            "configurationDone" => {
+                s.respond(&request, Value::object(vec![]))?;
+                drop(s);
+                if let Some(program) = launched.take() {
+                    run_program(&mut interpreter, &session, program)?;
+                }
+                if session.borrow().disconnected {
+                    break;
+                }
+            }
+            "disconnect" | "terminate" => {
+                s.respond(&request, Value::object(vec![]))?;
+                break;
+            }
+            // Not stopped, so there is nothing to show
+            "stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn"
+            | "stepOut" | "pause" => s.respond_error(&request, "the program is not stopped")?,
+            other => s.respond_error(&request, &format!("unsupported request `{}`", other))?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Breakpoints and stepping through running bytecode.
+//!
+//! A `DebugHandler` set with `Interpreter::set_debug_handler` is called before an instruction
+//! runs whenever a breakpoint is hit, a step finishes or a pause was requested. It gets the
+//! interpreter to inspect and returns how to carry on.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use interpreter::{Instruction, Interpreter};
+
+/// Pauses before instruction `instruction` of the function `function` of `module`. Methods are
+/// named `Type::method`, and `<init>` is the module's init.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub module: String,
+    pub function: String,
+    pub instruction: usize,
+}
+
+impl Breakpoint {
+    pub fn new(module: &str, function: &str, instruction: usize) -> Breakpoint {
+        Breakpoint {
+            module: module.to_owned(),
+            function: function.to_owned(),
+            instruction,
+        }
+    }
+
+    /// The name functions go by in frames and tracebacks.
+    pub fn qualified_function(&self) -> String {
+        format!("{}::{}", self.module, self.function)
+    }
+}
+
+/// Pauses before the first instruction of `line` of `file`, in any function. Code with no debug
+/// info never hits it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceBreakpoint {
+    pub file: String,
+    pub line: usize,
+}
+
+impl SourceBreakpoint {
+    pub fn new(file: &str, line: usize) -> SourceBreakpoint {
+        SourceBreakpoint {
+            file: file.to_owned(),
+            line,
+        }
+    }
+}
+
+/// How to go on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next instruction, wherever it is.
+    StepInto,
+    /// Pause at the next instruction of this frame or an outer one.
+    StepOver,
+    /// Pause at the next instruction of an outer frame.
+    StepOut,
+    /// Stop the program: the instruction fails with `ErrorKind::Aborted`.
+    Abort,
+}
+
+impl Resume {
+    /// Whether to pause at a frame depth of `depth`, after resuming at `from`.
+    pub(crate) fn pauses_at(self, depth: usize, from: usize) -> bool {
+        match self {
+            Resume::Continue | Resume::Abort => false,
+            Resume::StepInto => true,
+            Resume::StepOver => depth <= from,
+            Resume::StepOut => depth < from,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PauseReason {
+    Breakpoint(Breakpoint),
+    SourceBreakpoint(SourceBreakpoint),
+    Step,
+    /// Through `Interpreter::debug_pause_handle`.
+    Requested,
+}
+
+/// Where the interpreter paused. `instruction` is about to run.
+#[derive(Debug)]
+pub struct Pause<'a> {
+    pub reason: PauseReason,
+    pub instruction: &'a Instruction,
+    pub pos: usize,
+}
+
+pub trait DebugHandler {
+    /// The handler isn't called again while it runs, so it may run code on `interpreter`.
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> Resume;
+}
+
+#[derive(Default)]
+pub(crate) struct DebugState {
+    pub handler: Option<Box<dyn DebugHandler>>,
+    // With the qualified names of their functions
+    pub breakpoints: Vec<(Breakpoint, String)>,
+    pub source_breakpoints: Vec<SourceBreakpoint>,
+    // The frame depth the last resume happened at
+    pub resume: Option<(Resume, usize)>,
+    pub pause_requested: Arc<AtomicBool>,
+}
@@ -0,0 +1,101 @@
+//! Maps instructions back to the source they were compiled from.
+
+use std::fmt;
+
+/// Where an instruction came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// The position of a run of instructions: it covers `instruction` and everything after it, up to
+/// the next entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEntry {
+    pub instruction: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The debug-info table of one function.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DebugInfo {
+    pub file: String,
+    /// Sorted by instruction.
+    pub lines: Vec<LineEntry>,
+}
+
+impl DebugInfo {
+    pub fn new<S: Into<String>>(file: S) -> DebugInfo {
+        DebugInfo {
+            file: file.into(),
+            lines: vec![],
+        }
+    }
+
+    /// Records that the instructions from `instruction` on come from `line` and `column`.
+    /// Instructions must be added in order.
+    pub fn add(&mut self, instruction: usize, line: usize, column: usize) {
+        if let Some(last) = self.lines.last_mut() {
+            debug_assert!(last.instruction <= instruction);
+            if last.line == line && last.column == column {
+                return;
+            }
+            if last.instruction == instruction {
+                last.line = line;
+                last.column = column;
+                return;
+            }
+        }
+        self.lines.push(LineEntry {
+            instruction,
+            line,
+            column,
+        });
+    }
+
+    pub fn entry(&self, instruction: usize) -> Option<&LineEntry> {
+        let idx = self
+            .lines
+            .partition_point(|entry| entry.instruction <= instruction);
+        idx.checked_sub(1).map(|idx| &self.lines[idx])
+    }
+
+    /// The line `instruction` starts, if it is the first instruction of a run from a different
+    /// line than the one before it.
+    pub fn starts_line(&self, instruction: usize) -> Option<usize> {
+        let idx = self
+            .lines
+            .binary_search_by_key(&instruction, |entry| entry.instruction)
+            .ok()?;
+        let line = self.lines[idx].line;
+        if idx > 0 && self.lines[idx - 1].line == line {
+            return None;
+        }
+        Some(line)
+    }
+
+    /// The first instruction that starts `line`.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .map(|entry| entry.instruction)
+            .find(|&instruction| self.starts_line(instruction) == Some(line))
+    }
+
+    pub fn location(&self, instruction: usize) -> Option<SourceLocation> {
+        self.entry(instruction).map(|entry| SourceLocation {
+            file: self.file.clone(),
+            line: entry.line,
+            column: entry.column,
+        })
+    }
+}
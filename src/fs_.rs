@@ -0,0 +1,456 @@
+//! The `fs` module: files and directories under a root directory chosen by the host.
+//!
+//! Scripts only get the module if the host registers it with `register_fs`, and then only see
+//! the root: paths are relative to it, and any path that leads out of it, with `..`, as an
+//! absolute path or through a symbolic link, fails with `AccessDenied`. Links can change while
+//! a script uses them, so files are checked again once they are open, and directories are
+//! opened and checked before anything is listed, made or removed in them.
+//!
+//! - `read_to_string(path)`, `write(path, text)` and `append(path, text)` read and write whole
+//!   files.
+//! - `exists(path)` is a `Bool`, `mkdir(path)` creates a directory with its parents, and
+//!   `remove(path)` removes a file or a directory with everything in it.
+//! - `list_dir(path)` returns the names of the directory's entries, sorted, one per line.
+//! - `metadata(path)` returns a `Metadata` with `size`, `is_dir`, `is_file` and `modified`,
+//!   the last in seconds since the Unix epoch.
+//! - `new fs::File(path, mode)` opens a file to read (`"r"`), overwrite (`"w"`) or append to
+//!   (`"a"`). Its `read_line` works like `io.read_line`, `write(text)` writes the text as it is
+//!   and `close` closes it; so does dropping it.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::iter::FromIterator;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use bool_;
+use function::NativeResult;
+use generic;
+use int;
+use interpreter::{
+    consts, ErrorKind, Interpreter, ModuleIndex, ObjectToken, TriconeError, TypeIndex,
+};
+use moduledef::{FunctionDef, ModuleDef, NativeFunctionDef, TypeDef};
+use string;
+use symbol::Symbol;
+
+struct Fs {
+    // Canonical, so that resolved paths can be compared with it
+    root: PathBuf,
+    module: Cell<Option<ModuleIndex>>,
+}
+
+fn denied(path: &str) -> TriconeError {
+    TriconeError::with_message(
+        ErrorKind::AccessDenied,
+        format!("`{}` is outside of the directory scripts can use", path),
+    )
+}
+
+/// Whether `file` is the file at `path`.
+#[cfg(unix)]
+fn is_file_at(file: &File, path: &Path) -> Result<bool, TriconeError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (opened, named) = (file.metadata()?, fs::metadata(path)?);
+    Ok(opened.dev() == named.dev() && opened.ino() == named.ino())
+}
+
+#[cfg(not(unix))]
+fn is_file_at(_file: &File, _path: &Path) -> Result<bool, TriconeError> {
+    Ok(true)
+}
+
+/// A path to the directory `dir`, opened at `path`, that keeps to it however links on the way
+/// change since. Only Linux has one; elsewhere it is `path`, as checked when `dir` was opened.
+#[cfg(target_os = "linux")]
+fn through(dir: &File, _path: &Path) -> PathBuf {
+    use std::os::unix::io::AsRawFd;
+
+    PathBuf::from(format!("/proc/self/fd/{}", dir.as_raw_fd()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn through(_dir: &File, path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+impl Fs {
+    /// The path `path` names under the root, if it stays there.
+    fn resolve(&self, path: &str) -> Result<PathBuf, TriconeError> {
+        let denied = || denied(path);
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(denied());
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(denied()),
+            }
+        }
+        let full = self.root.join(relative);
+        // Whatever part of the path exists may go through links, so follow them. A link that
+        // leads nowhere might be created through, so it is out as well.
+        for ancestor in full.ancestors() {
+            if ancestor.symlink_metadata().is_err() {
+                continue;
+            }
+            match ancestor.canonicalize() {
+                Ok(ref canonical) if canonical.starts_with(&self.root) => break,
+                _ => return Err(denied()),
+            }
+        }
+        Ok(full)
+    }
+
+    /// Opens the file `path` names under the root, creating it if `create` is set. A link on
+    /// the way may be swapped between resolving the path and opening it, so the open file has
+    /// to be the one the path leads to afterwards, under the root. `options` must neither
+    /// create nor truncate: `truncate` does that once the file passed.
+    fn open(
+        &self,
+        path: &str,
+        options: &OpenOptions,
+        create: bool,
+        truncate: bool,
+    ) -> Result<File, TriconeError> {
+        let full = self.resolve(path)?;
+        let file = match options.open(&full) {
+            // Creating only new files doesn't follow a link put at the end of the path
+            Err(ref err) if create && err.kind() == io::ErrorKind::NotFound => {
+                options.clone().create_new(true).open(&full)?
+            }
+            res => res?,
+        };
+        self.check_opened(&file, &full, path)?;
+        if truncate {
+            file.set_len(0)?;
+        }
+        Ok(file)
+    }
+
+    /// Checks that `file`, opened at `full` for `path`, is what the path leads to now, under
+    /// the root.
+    fn check_opened(&self, file: &File, full: &Path, path: &str) -> Result<(), TriconeError> {
+        let canonical = full.canonicalize()?;
+        if !canonical.starts_with(&self.root) || !is_file_at(file, &canonical)? {
+            return Err(denied(path));
+        }
+        Ok(())
+    }
+
+    /// Opens the directory at `full`, a path `resolve` returned for `path`, and checks it like
+    /// `open` checks files.
+    fn open_dir(&self, full: &Path, path: &str) -> Result<File, TriconeError> {
+        let dir = File::open(full)?;
+        self.check_opened(&dir, full, path)?;
+        if !dir.metadata()?.is_dir() {
+            return Err(io_error(format!("`{}` isn't a directory", full.display())));
+        }
+        Ok(dir)
+    }
+
+    fn type_index(&self, interpreter: &Interpreter, name: &str) -> TypeIndex {
+        let module = self
+            .module
+            .get()
+            .expect("fs is used after it is registered");
+        interpreter
+            .lookup_type(module, name)
+            .expect("fs defines its types")
+    }
+}
+
+enum Handle {
+    Reader(BufReader<File>),
+    Writer(BufWriter<File>),
+}
+
+/// The payload of `File` objects; `None` once they are closed.
+type FilePayload = Option<Handle>;
+
+fn io_error<S: Into<String>>(message: S) -> TriconeError {
+    TriconeError::with_message(ErrorKind::IoError, message)
+}
+
+fn string_arg(arg: &ObjectToken, what: &str) -> Result<String, TriconeError> {
+    string::string_value(arg)
+        .map(|value| value.to_owned())
+        .ok_or_else(|| {
+            TriconeError::with_message(ErrorKind::TypeError, format!("{} must be a `String`", what))
+        })
+}
+
+/// Opens the file at the path `arg` to read (`"r"`), overwrite (`"w"`) or append to (`"a"`).
+fn open_arg(fs: &Fs, arg: &ObjectToken, mode: &str) -> Result<File, TriconeError> {
+    let path = string_arg(arg, "the path")?;
+    let mut options = OpenOptions::new();
+    match mode {
+        "r" => options.read(true),
+        "w" => options.write(true),
+        "a" => options.append(true),
+        _ => {
+            return Err(io_error(format!(
+                "unknown mode `{}`, expected `r`, `w` or `a`",
+                mode
+            )))
+        }
+    };
+    fs.open(&path, &options, mode != "r", mode == "w")
+}
+
+/// Runs `function` with the payload of a `File`.
+fn with_file<F, O>(fs: &Fs, interpreter: &Interpreter, file: &ObjectToken, function: F) -> O
+where
+    F: FnOnce(&mut FilePayload) -> O,
+{
+    assert_eq!(file.type_index(), fs.type_index(interpreter, "File"));
+    let mut obj = file.obj_mut();
+    (function)(unsafe { generic::get_unsafe_mut::<FilePayload>(&mut obj) })
+}
+
+fn read_to_string(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let mut contents = String::new();
+    open_arg(fs, &args[0], "r")?.read_to_string(&mut contents)?;
+    string::create_string(itrp, contents).map(Some)
+}
+
+fn write(fs: &Fs, _itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = string_arg(&args[1], "the text")?;
+    open_arg(fs, &args[0], "w")?.write_all(text.as_bytes())?;
+    Ok(None)
+}
+
+fn append(fs: &Fs, _itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = string_arg(&args[1], "the text")?;
+    open_arg(fs, &args[0], "a")?.write_all(text.as_bytes())?;
+    Ok(None)
+}
+
+fn exists(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let path = string_arg(&args[0], "the path")?;
+    let full = fs.resolve(&path)?;
+    // Opening it checks where the links on the way lead by then
+    let exists = match File::open(&full) {
+        Ok(file) => {
+            fs.check_opened(&file, &full, &path)?;
+            true
+        }
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => false,
+        Err(err) => return Err(err.into()),
+    };
+    bool_::create_bool(itrp, exists).map(Some)
+}
+
+fn list_dir(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let path = string_arg(&args[0], "the path")?;
+    let full = fs.resolve(&path)?;
+    let dir = fs.open_dir(&full, &path)?;
+    let mut names = vec![];
+    for entry in fs::read_dir(through(&dir, &full))? {
+        names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    string::create_string(itrp, names.join("\n")).map(Some)
+}
+
+fn mkdir(fs: &Fs, _itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let path = string_arg(&args[0], "the path")?;
+    let full = fs.resolve(&path)?;
+    let relative = full
+        .strip_prefix(&fs.root)
+        .expect("resolved paths are under the root");
+    // One directory at a time, each made in its parent once that is open and checked
+    let mut parent = fs.root.clone();
+    let mut dir = fs.open_dir(&parent, &path)?;
+    for name in relative {
+        match fs::create_dir(through(&dir, &parent).join(name)) {
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            res => res?,
+        }
+        parent.push(name);
+        dir = fs.open_dir(&parent, &path)?;
+    }
+    Ok(None)
+}
+
+fn remove(fs: &Fs, _itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let path = string_arg(&args[0], "the path")?;
+    let full = fs.resolve(&path)?;
+    let (parent, name) = match (full.parent(), full.file_name()) {
+        (Some(parent), Some(name)) if full != fs.root => (parent, name),
+        _ => {
+            return Err(TriconeError::with_message(
+                ErrorKind::AccessDenied,
+                "the root directory can't be removed",
+            ))
+        }
+    };
+    // Removing doesn't follow a link at the end of the path, only on the way to its directory
+    let dir = fs.open_dir(parent, &path)?;
+    let target = through(&dir, parent).join(name);
+    if target.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(target)?;
+    } else {
+        fs::remove_file(target)?;
+    }
+    Ok(None)
+}
+
+fn metadata(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let metadata = open_arg(fs, &args[0], "r")?.metadata()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |age| age.as_secs() as i64);
+    let members = vec![
+        ("size", int::create_int(itrp, metadata.len() as i64)?),
+        ("is_dir", bool_::create_bool(itrp, metadata.is_dir())?),
+        ("is_file", bool_::create_bool(itrp, metadata.is_file())?),
+        ("modified", int::create_int(itrp, modified)?),
+    ];
+    let tyidx = fs.type_index(itrp, "Metadata");
+    let obj = itrp.create_object(tyidx, 0)?;
+    let mut members = members.into_iter();
+    for (name, value) in &mut members {
+        if let Err(err) = obj.assign_member(Symbol::new(name), value, itrp) {
+            itrp.drop_token(obj);
+            for (_, value) in members {
+                itrp.drop_token(value);
+            }
+            return Err(err);
+        }
+    }
+    Ok(Some(obj))
+}
+
+fn file_create(fs: &Fs, _itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let mode = string_arg(&args[2], "the mode")?;
+    let file = open_arg(fs, &args[1], &mode)?;
+    let handle = if mode == "r" {
+        Handle::Reader(BufReader::new(file))
+    } else {
+        Handle::Writer(BufWriter::new(file))
+    };
+    // Only now that nothing can fail does the object get a payload for `drop` to drop
+    let mut target = args[0].obj_mut();
+    unsafe { generic::initialize_object_from_val::<FilePayload>(&mut target, Some(handle)) };
+    Ok(None)
+}
+
+// Scripts may call it too, so it leaves the file closed rather than gone
+fn file_drop(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    with_file(fs, itrp, &args[0], Option::take);
+    Ok(None)
+}
+
+fn file_read_line(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let mut line = String::new();
+    let read = with_file(fs, itrp, &args[0], |file| match *file {
+        Some(Handle::Reader(ref mut reader)) => Ok(reader.read_line(&mut line)?),
+        Some(Handle::Writer(_)) => Err(io_error("the file isn't open for reading")),
+        None => Err(io_error("the file is closed")),
+    })?;
+    if read == 0 {
+        return itrp.get_unit_object().map(Some);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    string::create_string(itrp, line).map(Some)
+}
+
+fn file_write(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = string_arg(&args[1], "the text")?;
+    with_file(fs, itrp, &args[0], |file| match *file {
+        Some(Handle::Writer(ref mut writer)) => Ok(writer.write_all(text.as_bytes())?),
+        Some(Handle::Reader(_)) => Err(io_error("the file isn't open for writing")),
+        None => Err(io_error("the file is closed")),
+    })?;
+    Ok(None)
+}
+
+fn file_close(fs: &Fs, itrp: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    // Flush here, as dropping the writer would swallow the error
+    with_file(fs, itrp, &args[0], |file| match file.take() {
+        Some(Handle::Writer(mut writer)) => writer.flush(),
+        _ => Ok(()),
+    })?;
+    Ok(None)
+}
+
+type FsFn = fn(&Fs, &mut Interpreter, &[ObjectToken]) -> NativeResult;
+
+fn native(fs: &Rc<Fs>, name: &str, arity: usize, code: FsFn) -> (String, FunctionDef) {
+    let fs = Rc::clone(fs);
+    (
+        name.to_owned(),
+        FunctionDef::Native(NativeFunctionDef::new(arity, move |itrp, args| {
+            code(&fs, itrp, args)
+        })),
+    )
+}
+
+/// Registers the `fs` module, giving scripts access to `root` and everything under it.
+pub fn register_fs<P: AsRef<Path>>(
+    interpreter: &mut Interpreter,
+    root: P,
+) -> Result<ModuleIndex, TriconeError> {
+    let root = root.as_ref().canonicalize()?;
+    if !root.is_dir() {
+        return Err(io_error(format!("`{}` isn't a directory", root.display())));
+    }
+    let fs = Rc::new(Fs {
+        root,
+        module: Cell::new(None),
+    });
+    let file = TypeDef {
+        methods: HashMap::from_iter(vec![
+            native(&fs, consts::CREATE_METHOD_NAME, 3, file_create),
+            native(&fs, consts::DROP_METHOD_NAME, 1, file_drop),
+            native(&fs, "read_line", 1, file_read_line),
+            native(&fs, "write", 2, file_write),
+            native(&fs, "close", 1, file_close),
+        ]),
+    };
+    let def = ModuleDef {
+        name: "fs".to_owned(),
+        types: HashMap::from_iter(vec![
+            ("File".to_owned(), file),
+            (
+                "Metadata".to_owned(),
+                TypeDef {
+                    methods: HashMap::new(),
+                },
+            ),
+        ]),
+        free_functions: HashMap::from_iter(vec![
+            native(&fs, "read_to_string", 1, read_to_string),
+            native(&fs, "write", 2, write),
+            native(&fs, "append", 2, append),
+            native(&fs, "exists", 1, exists),
+            native(&fs, "list_dir", 1, list_dir),
+            native(&fs, "mkdir", 1, mkdir),
+            native(&fs, "remove", 1, remove),
+            native(&fs, "metadata", 1, metadata),
+        ]),
+        init: None,
+        exports: None,
+        access: None,
+    };
+
+    let index = def.register(interpreter)?;
+    fs.module.set(Some(index));
+    Ok(index)
+}
@@ -0,0 +1,35 @@
+use interpreter::InstructionKind;
+
+/// How much fuel each unit of work costs while a fuel budget is active
+/// (see `Interpreter::with_fuel`).
+#[derive(Debug, Clone)]
+pub struct FuelCosts {
+    instructions: [u64; InstructionKind::COUNT],
+    /// Charged by `Interpreter::create_object`, on top of the instruction that asked for it.
+    pub object_creation: u64,
+    /// Charged whenever a native function or method is called.
+    pub native_call: u64,
+}
+
+impl FuelCosts {
+    pub fn instruction_cost(&self, kind: InstructionKind) -> u64 {
+        self.instructions[kind as usize]
+    }
+
+    pub fn set_instruction_cost(&mut self, kind: InstructionKind, cost: u64) {
+        self.instructions[kind as usize] = cost;
+    }
+}
+
+impl Default for FuelCosts {
+    fn default() -> FuelCosts {
+        let mut costs = FuelCosts {
+            instructions: [1; InstructionKind::COUNT],
+            object_creation: 1,
+            native_call: 1,
+        };
+        costs.set_instruction_cost(InstructionKind::CallMethod, 2);
+        costs.set_instruction_cost(InstructionKind::CallFunctionObject, 2);
+        costs
+    }
+}
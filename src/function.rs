@@ -1,51 +1,162 @@
+use debuginfo::DebugInfo;
 use generic;
+use inline_cache::InlineCaches;
 use interpreter::*;
+use symbol::Symbol;
 
-use std::rc::Rc;
+use std::ptr;
+use std::rc::{Rc, Weak};
 
-pub struct Code {
-    function: Rc<Fn(&mut Interpreter, &[ObjectToken]) -> Option<ObjectToken>>,
+pub type NativeResult = Result<Option<ObjectToken>, TriconeError>;
+pub type NativeFn = dyn Fn(&mut Interpreter, &[ObjectToken]) -> NativeResult;
+
+pub struct Bytecode {
+    pub instructions: Vec<Instruction>,
+    /// The module the code belongs to, which decides what other modules it may access.
+    /// Code created by the host outside of any module has none.
+    pub module: Option<ModuleIndex>,
+    pub debug_info: Option<DebugInfo>,
+    /// The names of the local slots `LoadLocal` and `StoreLocal` use, by slot.
+    pub locals: Vec<Symbol>,
+    pub(crate) caches: InlineCaches,
+}
+
+impl Bytecode {
+    pub fn new(
+        instructions: Vec<Instruction>,
+        module: Option<ModuleIndex>,
+        debug_info: Option<DebugInfo>,
+    ) -> Bytecode {
+        let caches = InlineCaches::new(&instructions);
+        Bytecode {
+            instructions,
+            module,
+            debug_info,
+            locals: vec![],
+            caches,
+        }
+    }
+
+    pub fn with_locals(mut self, locals: Vec<Symbol>) -> Bytecode {
+        self.locals = locals;
+        self
+    }
+}
+
+#[derive(Clone)]
+pub enum Code {
+    Native(Rc<NativeFn>),
+    Bytecode(Rc<Bytecode>),
 }
 
 impl Code {
     pub fn create(instructions: Vec<Instruction>) -> Code {
-        Code {
-            function: Rc::new(move |interpreter, _args| interpreter.run_code(&instructions)),
+        Code::Bytecode(Rc::new(Bytecode::new(instructions, None, None)))
+    }
+
+    pub fn create_in_module(instructions: Vec<Instruction>, module: ModuleIndex) -> Code {
+        Code::Bytecode(Rc::new(Bytecode::new(instructions, Some(module), None)))
+    }
+
+    pub fn is_native(&self) -> bool {
+        match *self {
+            Code::Native(_) => true,
+            Code::Bytecode(_) => false,
         }
     }
+
+    pub(crate) fn invoke(
+        &self,
+        interpreter: &mut Interpreter,
+        args: &[ObjectToken],
+    ) -> NativeResult {
+        match *self {
+            Code::Native(ref function) => (function)(interpreter, args),
+            Code::Bytecode(ref bytecode) => {
+                // Bytecode finds its arguments on the operation stack, the first one deepest.
+                // Whatever it leaves there is dropped when it returns.
+                let height = interpreter.operation_stack_height();
+                for arg in args {
+                    interpreter.push_operand(arg.dup());
+                }
+                let outer = interpreter.swap_frame_code(Some(bytecode.clone()), None);
+                let outer_locals = interpreter.push_frame_locals(bytecode.locals.len());
+                let res = interpreter.with_module_context(bytecode.module, |interpreter| {
+                    interpreter.run_bytecode(bytecode)
+                });
+                interpreter.pop_frame_locals(outer_locals);
+                interpreter.swap_frame_code(outer.0, outer.1);
+                interpreter.truncate_operation_stack(height);
+                res
+            }
+        }
+    }
+}
+
+// Weak, as code may end up in its own caches
+enum WeakCode {
+    Native(Weak<NativeFn>),
+    Bytecode(Weak<Bytecode>),
+}
+
+/// A `Function` that keeps neither its code nor its closure alive, for inline caches.
+pub(crate) struct WeakFunction {
+    code: WeakCode,
+    arity: usize,
+    variadic: bool,
+    closure: WeakToken,
+    name: Option<Rc<str>>,
+}
+
+impl WeakFunction {
+    pub fn upgrade(&self) -> Option<Function> {
+        let code = match self.code {
+            WeakCode::Native(ref code) => Code::Native(code.upgrade()?),
+            WeakCode::Bytecode(ref code) => Code::Bytecode(code.upgrade()?),
+        };
+        Some(Function {
+            code,
+            arity: self.arity,
+            variadic: self.variadic,
+            closure: Scope {
+                vars: self.closure.upgrade()?,
+            },
+            name: self.name.clone(),
+        })
+    }
 }
 
 pub struct Function {
     code: Code,
     arity: usize,
+    // Takes `arity` or more arguments
+    variadic: bool,
     pub closure: Scope,
+    // Qualified, like `module::function` or `module::Type::method`
+    name: Option<Rc<str>>,
 }
 
 impl Function {
     pub fn new<F>(code: F, arity: usize, closure: Scope) -> Function
     where
-        F: Fn(&mut Interpreter, &[ObjectToken]) -> Option<ObjectToken> + 'static,
+        F: Fn(&mut Interpreter, &[ObjectToken]) -> NativeResult + 'static,
     {
         Function {
-            code: Code {
-                function: Rc::new(code),
-            },
+            code: Code::Native(Rc::new(code)),
             arity,
+            variadic: false,
             closure,
+            name: None,
         }
     }
 
-    pub fn from_boxed_fn(
-        code: Box<Fn(&mut Interpreter, &[ObjectToken]) -> Option<ObjectToken>>,
-        arity: usize,
-        closure: Scope,
-    ) -> Function {
+    pub fn from_boxed_fn(code: Box<NativeFn>, arity: usize, closure: Scope) -> Function {
         Function {
-            code: Code {
-                function: code.into(),
-            },
+            code: Code::Native(code.into()),
             arity,
+            variadic: false,
             closure,
+            name: None,
         }
     }
 
@@ -53,36 +164,101 @@ impl Function {
         Function {
             code,
             arity,
+            variadic: false,
             closure,
+            name: None,
         }
     }
 
     pub fn dup(&self) -> Function {
         Function {
-            code: Code {
-                function: Rc::clone(&self.code.function),
-            },
+            code: self.code.clone(),
             arity: self.arity,
+            variadic: self.variadic,
             closure: self.closure.dup(),
+            name: self.name.clone(),
         }
     }
 
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakFunction {
+        WeakFunction {
+            code: match self.code {
+                Code::Native(ref code) => WeakCode::Native(Rc::downgrade(code)),
+                Code::Bytecode(ref code) => WeakCode::Bytecode(Rc::downgrade(code)),
+            },
+            arity: self.arity,
+            variadic: self.variadic,
+            closure: self.closure.downgrade(),
+            name: self.name.clone(),
+        }
+    }
+
+    pub fn set_name<S: Into<Rc<str>>>(&mut self, name: S) {
+        self.name = Some(name.into());
+    }
+
+    pub fn with_name<S: Into<Rc<str>>>(mut self, name: S) -> Function {
+        self.set_name(name);
+        self
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.code.is_native()
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Lets the function take any number of arguments past its arity, like `io.printf`.
+    pub fn with_variadic_args(mut self) -> Function {
+        self.variadic = true;
+        self
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+
+    fn check_call(
+        &self,
+        interpreter: &mut Interpreter,
+        args: &[ObjectToken],
+    ) -> Result<(), TriconeError> {
+        if args.len() < self.arity || (args.len() > self.arity && !self.variadic) {
+            return Err(TriconeError::new(ErrorKind::WrongArgumentCount));
+        }
+        if self.code.is_native() {
+            let cost = interpreter.fuel_costs().native_call;
+            interpreter.consume_fuel(cost)?;
+        }
+        Ok(())
+    }
+
     pub fn call(
         &self,
         interpreter: &mut Interpreter,
         args: &[ObjectToken],
     ) -> Result<Option<ObjectToken>, TriconeError> {
-        if self.arity == args.len() {
-            Ok(
-                interpreter.with_new_frame(self.closure.dup(), |interpreter| {
-                    (self.code.function)(interpreter, args)
-                }),
-            )
-        } else {
-            Err(TriconeError {
-                kind: ErrorKind::WrongArgumentCount,
-            })
-        }
+        self.check_call(interpreter, args)?;
+        interpreter.with_new_frame(self.closure.dup(), |interpreter| {
+            interpreter.set_frame_function(self.name.clone(), args.len());
+            interpreter.profile_enter(self.name.as_ref());
+            let res = self
+                .code
+                .invoke(interpreter, args)
+                .map_err(|err| interpreter.capture_traceback(err));
+            interpreter.profile_exit();
+            res
+        })
     }
 
     pub fn call_in_frame(
@@ -90,13 +266,8 @@ impl Function {
         interpreter: &mut Interpreter,
         args: &[ObjectToken],
     ) -> Result<Option<ObjectToken>, TriconeError> {
-        if self.arity == args.len() {
-            Ok(interpreter.with_new_scope(|interpreter| (self.code.function)(interpreter, args)))
-        } else {
-            Err(TriconeError {
-                kind: ErrorKind::WrongArgumentCount,
-            })
-        }
+        self.check_call(interpreter, args)?;
+        interpreter.with_new_scope(|interpreter| self.code.invoke(interpreter, args))
     }
 }
 
@@ -111,9 +282,22 @@ impl generic::TriconeDefault for Function {
 }
 
 pub fn register_func_type(interpreter: &mut Interpreter, module: &mut Module) {
-    generic::create_type_for::<Function, _>(interpreter, module, "Function", |_, _, ty| {
+    generic::create_type_for::<Function, _>(interpreter, module, "Function", |itrp, _, ty| {
         // The interpreter needs to know if an object is a function object easily
         assert_eq!(ty.index, consts::FUNCTION_TYPE_ID);
+
+        // The closure is an object token, so it has to go back through the interpreter
+        if let Some(generic_drop) = ty.remove_method(consts::DROP_METHOD_NAME) {
+            itrp.drop_token(generic_drop.closure.vars);
+        }
+        ty.register_native_method(consts::DROP_METHOD_NAME, 1, |itrp, args| {
+            let function = {
+                let mut target = args[0].obj_mut();
+                unsafe { ptr::read(generic::get_unsafe_mut::<Function>(&mut target)) }
+            };
+            itrp.drop_token(function.closure.vars);
+            Ok(None)
+        });
     });
 }
 
@@ -6,20 +6,28 @@ use std::mem;
 use std::ops::Add;
 use std::ptr;
 
+/// # Safety
+/// `obj` must hold an initialized `T`.
 pub unsafe fn get_unsafe_copy<T: Copy>(obj: &Object) -> T {
     *get_unsafe_ref(obj)
 }
 
-pub unsafe fn get_unsafe_ref<'a, T>(obj: &'a Object) -> &'a T {
+/// # Safety
+/// `obj` must hold an initialized `T`.
+pub unsafe fn get_unsafe_ref<T>(obj: &Object) -> &T {
     assert_eq!(obj.data.len(), aligned_allocation_size::<T>());
     &*(align_pointer::<T>(obj.data.as_ptr() as usize) as *const T)
 }
 
+/// # Safety
+/// `obj` must hold an initialized `T`, and the returned reference must not outlive it.
 pub unsafe fn get_unsafe_mut<'a, T>(obj: &mut Object) -> &'a mut T {
     assert_eq!(obj.data.len(), aligned_allocation_size::<T>());
     &mut *(align_pointer::<T>(obj.data.as_mut_ptr() as usize) as *mut T)
 }
 
+/// # Safety
+/// `obj` must have room for a `T`; any previous value is overwritten without being dropped.
 pub unsafe fn put_unsafe<T>(obj: &mut Object, val: T) {
     ptr::write(get_unsafe_mut(obj) as *mut T, val);
 }
@@ -33,17 +41,18 @@ fn aligned_allocation_size<T>() -> usize {
     mem::size_of::<T>() + mem::align_of::<T>() - 1
 }
 
+/// # Safety
+/// The object's type must treat its payload as a `T`.
 pub unsafe fn create_object_from_val<T>(ty_idx: TypeIndex, val: T) -> Object {
     let mut obj = Object::raw_new(ty_idx);
     initialize_object_from_val(&mut obj, val);
     obj
 }
 
+/// # Safety
+/// The object's type must treat its payload as a `T`; any previous payload is leaked.
 pub unsafe fn initialize_object_from_val<T>(obj: &mut Object, val: T) {
-    let mut data = Vec::with_capacity(aligned_allocation_size::<T>());
-
-    data.set_len(aligned_allocation_size::<T>());
-    obj.data = data;
+    obj.data = vec![0; aligned_allocation_size::<T>()];
     put_unsafe(obj, val);
 }
 
@@ -66,7 +75,7 @@ pub fn create_type_for<T: TriconeDefault, F>(
                 )
             };
 
-            None
+            Ok(None)
         });
 
         ty.register_native_method(consts::DROP_METHOD_NAME, 1, move |_itrp, args| {
@@ -74,7 +83,7 @@ pub fn create_type_for<T: TriconeDefault, F>(
             unsafe {
                 ptr::drop_in_place(get_unsafe_mut::<T>(&mut target) as *mut T);
             }
-            None
+            Ok(None)
         });
 
         (with_ty)(interpreter, module, ty);
@@ -86,41 +95,51 @@ pub fn impl_add_for<T: Add + Clone>(ty: &mut Type) {
         let a = args[0].obj();
         let b = args[1].obj();
 
-        assert_eq!(a.type_, b.type_);
+        if a.type_ != b.type_ {
+            return Err(TriconeError::with_message(
+                ErrorKind::TypeError,
+                "add needs two values of the same type",
+            ));
+        }
 
-        let res_obj = itrp.create_object(a.type_, 0);
+        let res_obj = itrp.create_object(a.type_, 0)?;
         unsafe {
             let mut res_ = res_obj.obj_mut();
             let (val_a, val_b): (&T, &T) = (get_unsafe_ref(&a), get_unsafe_ref(&b));
             put_unsafe(&mut res_, Add::add(val_a.clone(), val_b.clone()));
         }
 
-        Some(res_obj)
+        Ok(Some(res_obj))
     });
 }
 
 pub fn impl_display_for<T: fmt::Display>(ty: &mut Type) {
     ty.register_native_method("tostring", 1, move |itrp, args| {
         let obj = args[0].obj();
-        Some(string::create_string(
-            itrp,
-            format!("{}", unsafe { get_unsafe_ref::<T>(&obj) }),
-        ))
+        string::create_string(itrp, format!("{}", unsafe { get_unsafe_ref::<T>(&obj) })).map(Some)
     });
 }
 
 macro_rules! define_core_creator {
     ($def_name:ident, $type:ty, $name:expr) => {
-        pub fn $def_name(interpreter: &mut Interpreter, value: $type) -> ObjectToken {
+        pub fn $def_name(
+            interpreter: &mut Interpreter,
+            value: $type,
+        ) -> Result<ObjectToken, $crate::interpreter::TriconeError> {
             let tyidx = interpreter
                 .lookup_type(consts::CORE_MODULE_ID, $name)
                 .unwrap();
-            let token = interpreter.create_object(tyidx, 0);
+            let token = interpreter.create_object(tyidx, 0)?;
+            let heap_size = $crate::memory::HeapSize::heap_size(&value);
+            if let Err(err) = interpreter.charge_memory(&token, heap_size) {
+                interpreter.drop_token(token);
+                return Err(err);
+            }
             {
                 let mut obj = token.obj_mut();
                 unsafe { $crate::generic::put_unsafe(&mut obj, value) }
             }
-            token
+            Ok(token)
         }
     };
 }
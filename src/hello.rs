@@ -1,6 +1,7 @@
 use function::{Code, Function};
 use interpreter::*;
 use moduledef::*;
+use symbol::Symbol;
 
 use std::collections::HashMap;
 use std::iter::FromIterator;
@@ -15,33 +16,24 @@ fn register_hello(interpreter: &mut Interpreter) {
                 methods: HashMap::from_iter(vec![
                     (
                         "hello".to_owned(),
-                        FunctionDef::Native(NativeFunctionDef {
-                            arity: 1,
-                            code: Box::new(move |_itrp, _args| {
-                                println!("hello from method!!");
-                                None
-                            }),
-                        }),
+                        FunctionDef::Native(NativeFunctionDef::new(1, move |itrp, _args| {
+                            writeln!(itrp.stdout(), "hello from method!!")?;
+                            Ok(None)
+                        })),
                     ),
                     (
                         consts::CREATE_METHOD_NAME.to_owned(),
-                        FunctionDef::Native(NativeFunctionDef {
-                            arity: 1,
-                            code: Box::new(move |_itrp, _args| {
-                                println!("hello from CREATE method!!");
-                                None
-                            }),
-                        }),
+                        FunctionDef::Native(NativeFunctionDef::new(1, move |itrp, _args| {
+                            writeln!(itrp.stdout(), "hello from CREATE method!!")?;
+                            Ok(None)
+                        })),
                     ),
                     (
                         consts::DROP_METHOD_NAME.to_owned(),
-                        FunctionDef::Native(NativeFunctionDef {
-                            arity: 1,
-                            code: Box::new(move |_itrp, _args| {
-                                println!("hello from DROP method!!");
-                                None
-                            }),
-                        }),
+                        FunctionDef::Native(NativeFunctionDef::new(1, move |itrp, _args| {
+                            writeln!(itrp.stdout(), "hello from DROP method!!")?;
+                            Ok(None)
+                        })),
                     ),
                 ]),
             },
@@ -53,11 +45,11 @@ fn register_hello(interpreter: &mut Interpreter) {
                     arity: 0,
                     instructions: vec![
                         CreateObject {
-                            type_spec: ("hello".to_owned(), "Hello".to_owned()),
+                            type_spec: (Symbol::new("hello"), Symbol::new("Hello")),
                             num_args: 0,
                         },
                         CallMethod {
-                            name: "hello".to_owned(),
+                            name: Symbol::new("hello"),
                             num_args: 0,
                             use_result: false,
                         },
@@ -65,19 +57,19 @@ fn register_hello(interpreter: &mut Interpreter) {
                             value: "Hello world!".to_owned(),
                         },
                         CallMethod {
-                            name: "println".to_owned(),
+                            name: Symbol::new("println"),
                             num_args: 0,
                             use_result: false,
                         },
                         Diag,
                         LookupName {
-                            name: "do_add".to_owned(),
+                            name: Symbol::new("do_add"),
                         },
                         Assign {
-                            name: "do_add_2".to_owned(),
+                            name: Symbol::new("do_add_2"),
                         },
                         LookupName {
-                            name: "do_add_2".to_owned(),
+                            name: Symbol::new("do_add_2"),
                         },
                         CallFunctionObject {
                             num_args: 0,
@@ -85,11 +77,13 @@ fn register_hello(interpreter: &mut Interpreter) {
                         },
                         GetTopScope,
                         Assign {
-                            name: "this".to_owned(),
+                            name: Symbol::new("this"),
                         },
                         GetTopScope,
                         DebugPrintObject,
                     ],
+                    debug_info: None,
+                    locals: vec![],
                 }),
             ),
             (
@@ -100,28 +94,33 @@ fn register_hello(interpreter: &mut Interpreter) {
                         CreateInt { value: 20 },
                         CreateInt { value: 22 },
                         CallMethod {
-                            name: "add".to_owned(),
+                            name: Symbol::new("add"),
                             num_args: 1,
                             use_result: true,
                         },
                         CallMethod {
-                            name: "tostring".to_owned(),
+                            name: Symbol::new("tostring"),
                             num_args: 0,
                             use_result: true,
                         },
                         CallMethod {
-                            name: "println".to_owned(),
+                            name: Symbol::new("println"),
                             num_args: 0,
                             use_result: false,
                         },
                         Diag,
                     ],
+                    debug_info: None,
+                    locals: vec![],
                 }),
             ),
         ]),
+        init: None,
+        exports: None,
+        access: None,
     };
 
-    def.register(interpreter);
+    def.register(interpreter).unwrap();
 }
 
 pub fn do_hello(interpreter: &mut Interpreter) {
@@ -131,20 +130,20 @@ pub fn do_hello(interpreter: &mut Interpreter) {
     let func = Function::from_code(
         Code::create(vec![
             GetModuleGlobals {
-                name: "hello".to_owned(),
+                name: Symbol::new("hello"),
             },
             GetMember {
-                name: "hello".to_owned(),
+                name: Symbol::new("hello"),
             },
             CallFunctionObject {
                 num_args: 0,
                 use_result: false,
             },
             GetModuleGlobals {
-                name: "hello".to_owned(),
+                name: Symbol::new("hello"),
             },
             GetMember {
-                name: "do_add".to_owned(),
+                name: Symbol::new("do_add"),
             },
             CallFunctionObject {
                 num_args: 0,
@@ -154,12 +153,12 @@ pub fn do_hello(interpreter: &mut Interpreter) {
             CallMethod {
                 num_args: 0,
                 use_result: true,
-                name: "tostring".to_owned(),
+                name: Symbol::new("tostring"),
             },
             CallMethod {
                 num_args: 0,
                 use_result: false,
-                name: "println".to_owned(),
+                name: Symbol::new("println"),
             },
         ]),
         0,
@@ -0,0 +1,55 @@
+//! Per-instruction caches for the lookups `LookupName`, `GetMember` and `CallMethod` repeat
+//! every time they run.
+//!
+//! Every instruction of a `Bytecode` has a slot that remembers what it found the last time.
+//! Slots only hold weak references, and are checked before they are used:
+//!
+//! - `LookupName` keeps the scope it started from and the scope the name was in. That holds
+//!   until a name is added to or removed from any scope a cached lookup went through.
+//! - `GetMember` keeps the object and the member, until the object's members change.
+//! - `CallMethod` keeps the method for the type of the receiver, until the type's methods
+//!   change.
+
+use std::cell::RefCell;
+
+use function::WeakFunction;
+use interpreter::{Instruction, TypeIndex, WeakToken};
+
+pub(crate) enum InlineCache {
+    Empty,
+    Name {
+        scope: WeakToken,
+        // `Interpreter::names_epoch` when it was filled
+        epoch: u64,
+        holder: WeakToken,
+        // How many scopes up `holder` is
+        depth: usize,
+    },
+    Member {
+        object: WeakToken,
+        members_version: u64,
+        value: WeakToken,
+    },
+    Method {
+        type_: TypeIndex,
+        methods_version: u64,
+        method: WeakFunction,
+    },
+}
+
+pub(crate) struct InlineCaches(Vec<RefCell<InlineCache>>);
+
+impl InlineCaches {
+    pub fn new(instructions: &[Instruction]) -> InlineCaches {
+        InlineCaches(
+            instructions
+                .iter()
+                .map(|_| RefCell::new(InlineCache::Empty))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, pos: usize) -> Option<&RefCell<InlineCache>> {
+        self.0.get(pos)
+    }
+}
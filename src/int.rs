@@ -1,11 +1,61 @@
-use generic;
-use interpreter::{consts, Interpreter, Module, ObjectToken};
+use bool_;
+use string;
+use interpreter::{
+    consts, ErrorKind, Immediate, Interpreter, Module, ObjectToken, TriconeError, Type,
+};
 
+pub fn int_value(token: &ObjectToken) -> Option<i64> {
+    match *token {
+        ObjectToken::Immediate(Immediate::Int(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn int_arg(arg: &ObjectToken) -> Result<i64, TriconeError> {
+    int_value(arg)
+        .ok_or_else(|| TriconeError::with_message(ErrorKind::TypeError, "expected an `Int`"))
+}
+
+fn int_operands(args: &[ObjectToken]) -> Result<(i64, i64), TriconeError> {
+    Ok((int_arg(&args[0])?, int_arg(&args[1])?))
+}
+
+fn register_arithmetic(ty: &mut Type, name: &'static str, op: fn(i64, i64) -> Option<i64>) {
+    ty.register_native_method(name, 2, move |itrp, args| {
+        let (a, b) = int_operands(args)?;
+        let value = op(a, b).ok_or_else(|| {
+            TriconeError::with_message(
+                ErrorKind::Overflow,
+                format!("`{}` of {} and {} overflows", name, a, b),
+            )
+        })?;
+        create_int(itrp, value).map(Some)
+    });
+}
+
+fn register_comparison(ty: &mut Type, name: &str, op: fn(&i64, &i64) -> bool) {
+    ty.register_native_method(name, 2, move |itrp, args| {
+        let (a, b) = int_operands(args)?;
+        bool_::create_bool(itrp, op(&a, &b)).map(Some)
+    });
+}
+
+// Ints are immediates: there is no object to create or drop, so the type has neither method
 pub fn register_int_type(interpreter: &mut Interpreter, module: &mut Module) {
-    generic::create_type_for::<i64, _>(interpreter, module, "Int", |_, _, ty| {
-        generic::impl_add_for::<i64>(ty);
-        generic::impl_display_for::<i64>(ty);
+    module.create_type(interpreter, "Int", |_, _, ty| {
+        assert_eq!(ty.index, consts::INT_TYPE_ID);
+        ty.register_native_method("tostring", 1, |itrp, args| {
+            string::create_string(itrp, int_arg(&args[0])?.to_string()).map(Some)
+        });
+        register_arithmetic(ty, "add", i64::checked_add);
+        register_arithmetic(ty, "sub", i64::checked_sub);
+        register_arithmetic(ty, "mul", i64::checked_mul);
+        register_comparison(ty, "eq", i64::eq);
+        register_comparison(ty, "lt", i64::lt);
+        register_comparison(ty, "gt", i64::gt);
     });
 }
 
-define_core_creator!{create_int, i64, "Int"}
+pub fn create_int(_interpreter: &mut Interpreter, value: i64) -> Result<ObjectToken, TriconeError> {
+    Ok(Immediate::Int(value).into())
+}
@@ -1,56 +1,170 @@
 use arrayvec::ArrayVec;
 use std::cell::{Ref, RefCell, RefMut};
-use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
 use std::mem;
 use std::ops::Deref;
 use std::process::abort;
-use std::rc::Rc;
+use std::ptr;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 
 use bool_;
-use function::{self, Function};
+use builtins;
+use debugger::{
+    Breakpoint, DebugHandler, DebugState, Pause, PauseReason, Resume, SourceBreakpoint,
+};
+use debuginfo::{DebugInfo, SourceLocation};
+use fuel::FuelCosts;
+use function::{self, Bytecode, Code, Function, NativeResult};
+use generic;
+use inline_cache::{InlineCache, InlineCaches};
 use int;
+use link;
+use loader::ModuleLoader;
+use memory::{self, MemoryUsage};
+use moduledef::{self, BytecodeFunctionDef, FunctionDef, ModuleDef, NativeFunctionDef};
+use optimize;
+use profiler::{Profile, Profiler};
+use stats::{Counters, Stats, TypeStats};
 use string;
-use builtins;
+use symbol::{Symbol, SymbolMap, SymbolSet};
 
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
     IndexError,
     WrongArgumentCount,
     TypeError,
+    OutOfFuel,
+    MemoryError,
+    NameError,
+    AccessDenied,
+    ImportError,
+    ImportCycle,
+    StackUnderflow,
+    /// Int arithmetic whose result doesn't fit.
+    Overflow,
+    /// Reading or writing a stream or file failed.
+    IoError,
+    /// A debugger stopped the program.
+    Aborted,
 }
 
 #[derive(Debug, Clone)]
 pub struct TriconeError {
     pub kind: ErrorKind,
+    pub message: Option<String>,
+    /// The source of the instruction that failed, if its code has debug info.
+    pub location: Option<SourceLocation>,
+    /// The frames that were active when the error happened, outermost first.
+    pub traceback: Vec<TracebackEntry>,
+}
+
+impl TriconeError {
+    pub fn new(kind: ErrorKind) -> TriconeError {
+        TriconeError {
+            kind,
+            message: None,
+            location: None,
+            traceback: vec![],
+        }
+    }
+
+    pub fn with_message<S: Into<String>>(kind: ErrorKind, message: S) -> TriconeError {
+        TriconeError {
+            kind,
+            message: Some(message.into()),
+            location: None,
+            traceback: vec![],
+        }
+    }
+}
+
+/// An active frame, as captured in a `TriconeError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracebackEntry {
+    /// The qualified name of the function running in the frame, like `module::Type::method`.
+    /// `None` for anonymous functions and frames the host set up to run code in.
+    pub function: Option<String>,
+    pub num_args: usize,
+    /// The instruction the frame was at; `None` if it wasn't running bytecode.
+    pub instruction: Option<usize>,
+    pub location: Option<SourceLocation>,
+}
+
+impl fmt::Display for TracebackEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "in {} with {} argument{}",
+            self.function.as_deref().unwrap_or("<anonymous>"),
+            self.num_args,
+            if self.num_args == 1 { "" } else { "s" }
+        )?;
+        if let Some(instruction) = self.instruction {
+            write!(f, ", instruction {}", instruction)?;
+        }
+        if let Some(ref location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TriconeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self.kind)?;
+        if let Some(ref message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(ref location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<io::Error> for TriconeError {
+    fn from(err: io::Error) -> TriconeError {
+        TriconeError::with_message(ErrorKind::IoError, err.to_string())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Instruction {
     CreateObject {
-        type_spec: (String, String),
+        type_spec: (Symbol, Symbol),
         num_args: usize,
     },
     Assign {
         // Assign a = pop(), b = pop(), a[name] = `b`
-        name: String,
+        name: Symbol,
     },
     GetTopScope,
+    // Loads the module through the interpreter's `ModuleLoader` if it isn't registered yet but
+    // the loader has it
     GetModuleGlobals {
-        name: String,
+        name: Symbol,
+    },
+    // Like `GetModuleGlobals`, but always asks the loader for a module that isn't registered,
+    // and importing a module that is still initializing is a cycle
+    Import {
+        name: Symbol,
     },
     CallMethod {
-        name: String,
+        name: Symbol,
         num_args: usize,
         use_result: bool,
     },
     GetMember {
-        name: String,
+        name: Symbol,
     },
+    // Searches the scopes, not the locals: linking makes lookups of locals `LoadLocal`s
     LookupName {
-        name: String,
+        name: Symbol,
     },
     CallFunctionObject {
         num_args: usize,
@@ -68,45 +182,238 @@ pub enum Instruction {
     Jump {
         to: usize,
     },
+    // Pops a `Bool` and jumps if it is false
+    JumpIfFalse {
+        to: usize,
+    },
+    SetMember {
+        // value = pop(), target = pop(), target[name] = value
+        name: Symbol,
+    },
+    // Drops the top of the stack
+    Pop,
+    Diag,
+    DebugPrintObject,
+    // `CreateObject` and `GetModuleGlobals` with their targets resolved when the module was
+    // registered
+    CreateObjectResolved {
+        type_: TypeIndex,
+        num_args: usize,
+    },
+    GetModuleGlobalsResolved {
+        module: ModuleIndex,
+    },
+    // Push and pop the local in slot `slot` of the running code
+    LoadLocal {
+        slot: usize,
+    },
+    StoreLocal {
+        slot: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionKind {
+    CreateObject,
+    Assign,
+    GetTopScope,
+    GetModuleGlobals,
+    CallMethod,
+    GetMember,
+    LookupName,
+    CallFunctionObject,
+    CreateString,
+    CreateInt,
+    CreateBool,
+    Jump,
     Diag,
     DebugPrintObject,
+    Import,
+    JumpIfFalse,
+    SetMember,
+    Pop,
+    CreateObjectResolved,
+    GetModuleGlobalsResolved,
+    LoadLocal,
+    StoreLocal,
+}
+
+impl InstructionKind {
+    // Ordered by discriminant, which is also the opcode used in bytecode files
+    pub const ALL: [InstructionKind; 22] = [
+        InstructionKind::CreateObject,
+        InstructionKind::Assign,
+        InstructionKind::GetTopScope,
+        InstructionKind::GetModuleGlobals,
+        InstructionKind::CallMethod,
+        InstructionKind::GetMember,
+        InstructionKind::LookupName,
+        InstructionKind::CallFunctionObject,
+        InstructionKind::CreateString,
+        InstructionKind::CreateInt,
+        InstructionKind::CreateBool,
+        InstructionKind::Jump,
+        InstructionKind::Diag,
+        InstructionKind::DebugPrintObject,
+        InstructionKind::Import,
+        InstructionKind::JumpIfFalse,
+        InstructionKind::SetMember,
+        InstructionKind::Pop,
+        InstructionKind::CreateObjectResolved,
+        InstructionKind::GetModuleGlobalsResolved,
+        InstructionKind::LoadLocal,
+        InstructionKind::StoreLocal,
+    ];
+    pub const COUNT: usize = InstructionKind::ALL.len();
+}
+
+impl Instruction {
+    pub fn kind(&self) -> InstructionKind {
+        use self::Instruction::*;
+        match *self {
+            CreateObject { .. } => InstructionKind::CreateObject,
+            Assign { .. } => InstructionKind::Assign,
+            GetTopScope => InstructionKind::GetTopScope,
+            GetModuleGlobals { .. } => InstructionKind::GetModuleGlobals,
+            Import { .. } => InstructionKind::Import,
+            CallMethod { .. } => InstructionKind::CallMethod,
+            GetMember { .. } => InstructionKind::GetMember,
+            LookupName { .. } => InstructionKind::LookupName,
+            CallFunctionObject { .. } => InstructionKind::CallFunctionObject,
+            CreateString { .. } => InstructionKind::CreateString,
+            CreateInt { .. } => InstructionKind::CreateInt,
+            CreateBool { .. } => InstructionKind::CreateBool,
+            Jump { .. } => InstructionKind::Jump,
+            JumpIfFalse { .. } => InstructionKind::JumpIfFalse,
+            SetMember { .. } => InstructionKind::SetMember,
+            Pop => InstructionKind::Pop,
+            Diag => InstructionKind::Diag,
+            DebugPrintObject => InstructionKind::DebugPrintObject,
+            CreateObjectResolved { .. } => InstructionKind::CreateObjectResolved,
+            GetModuleGlobalsResolved { .. } => InstructionKind::GetModuleGlobalsResolved,
+            LoadLocal { .. } => InstructionKind::LoadLocal,
+            StoreLocal { .. } => InstructionKind::StoreLocal,
+        }
+    }
+
+    pub fn jump_target(&self) -> Option<usize> {
+        match *self {
+            Instruction::Jump { to } | Instruction::JumpIfFalse { to } => Some(to),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TypeIndex(ModuleIndex, usize);
 
+impl TypeIndex {
+    pub(crate) fn new(module: ModuleIndex, pos: usize) -> TypeIndex {
+        TypeIndex(module, pos)
+    }
+
+    pub fn module(self) -> ModuleIndex {
+        self.0
+    }
+
+    /// Where the type is among its module's types.
+    pub fn position(self) -> usize {
+        self.1
+    }
+}
+
+static METHODS_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_methods_version() -> u64 {
+    METHODS_VERSION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
 pub struct Type {
-    name: String,
-    methods: HashMap<String, Function>,
+    name: Symbol,
+    // `module::Type`, the prefix of its methods' names
+    qualified_name: String,
+    methods: SymbolMap<Function>,
     scope: Scope,
     pub index: TypeIndex,
+    live_instances: usize,
+    created: u64,
+    dropped: u64,
+    // Changes with every change to `methods`, and is unique across types and interpreters
+    methods_version: u64,
+    // Set when a reload removed the type; it keeps its index but can't be looked up by name
+    removed: bool,
 }
 
 impl Type {
-    pub fn new(name: &str, index: TypeIndex) -> Type {
+    pub fn new(module: &str, name: &str, index: TypeIndex) -> Type {
         Type {
-            name: name.to_owned(),
-            methods: HashMap::new(),
+            name: Symbol::new(name),
+            qualified_name: format!("{}::{}", module, name),
+            methods: SymbolMap::default(),
             scope: Scope::new(),
             index,
+            live_instances: 0,
+            created: 0,
+            dropped: 0,
+            methods_version: next_methods_version(),
+            removed: false,
         }
     }
 
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn qualified_name(&self) -> &str {
+        &self.qualified_name
+    }
+
     pub fn scope(&self) -> &Scope {
         &self.scope
     }
 
-    fn get_method(&self, name: &str) -> Option<Function> {
-        self.methods.get(name).map(Function::dup)
+    /// Instances created through `Interpreter::create_object` that are still alive.
+    pub fn live_instances(&self) -> usize {
+        self.live_instances
+    }
+
+    /// Whether a reload removed the type.
+    pub fn is_removed(&self) -> bool {
+        self.removed
+    }
+
+    pub fn methods(&self) -> &SymbolMap<Function> {
+        &self.methods
+    }
+
+    fn get_method(&self, name: Symbol) -> Option<Function> {
+        self.methods.get(&name).map(Function::dup)
+    }
+
+    fn replace_methods(&mut self, methods: SymbolMap<Function>) -> SymbolMap<Function> {
+        self.methods_version = next_methods_version();
+        mem::replace(&mut self.methods, methods)
+    }
+
+    /// Registers `func` as a method, naming it after the type unless it already has a name.
+    pub fn register_method(&mut self, name: &str, mut func: Function) {
+        if func.name().is_none() {
+            func.set_name(format!("{}::{}", self.qualified_name, name));
+        }
+        self.methods_version = next_methods_version();
+        self.methods.insert(Symbol::new(name), func);
     }
 
-    pub fn register_method(&mut self, name: &str, func: Function) {
-        self.methods.insert(name.to_owned(), func);
+    /// Hands the method back so its closure can be dropped through the interpreter.
+    pub fn remove_method(&mut self, name: &str) -> Option<Function> {
+        let name = Symbol::lookup(name)?;
+        self.methods_version = next_methods_version();
+        self.methods.remove(&name)
     }
 
     pub fn register_native_method<F>(&mut self, name: &str, arity: usize, code: F)
     where
-        F: Fn(&mut Interpreter, &[ObjectToken]) -> Option<ObjectToken> + 'static,
+        F: Fn(&mut Interpreter, &[ObjectToken]) -> NativeResult + 'static,
     {
         assert!(arity >= 1);
         let scope = self.scope.dup();
@@ -120,7 +427,7 @@ impl Type {
         instructions: Vec<Instruction>,
     ) {
         assert!(arity >= 1);
-        let code = function::Code::create(instructions);
+        let code = function::Code::create_in_module(instructions, self.index.0);
         let scope = self.scope.dup();
         self.register_method(name, Function::from_code(code, arity, scope));
     }
@@ -128,11 +435,86 @@ impl Type {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ModuleIndex(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReloadIncompatibility {
+    /// The type is gone from the new definition but objects of it are still alive, so it keeps
+    /// its old methods.
+    RemovedTypeInUse {
+        type_name: String,
+        live_instances: usize,
+    },
+    /// A method is gone from a type that still has live instances, so the type keeps its old
+    /// one.
+    RemovedMethodInUse {
+        type_name: String,
+        method: String,
+        live_instances: usize,
+    },
+    /// A method (`Type.method`) or free function now takes a different number of arguments.
+    ArityChanged {
+        name: String,
+        old: usize,
+        new: usize,
+    },
+}
+
+/// What `Interpreter::reload_module` changed.
+#[derive(Debug, Clone, Default)]
+pub struct ReloadReport {
+    pub added_types: Vec<String>,
+    pub removed_types: Vec<String>,
+    /// Globals created by the old definition that the new one no longer creates.
+    pub removed_globals: Vec<String>,
+    pub incompatibilities: Vec<ReloadIncompatibility>,
+}
+
+impl ReloadReport {
+    pub fn is_compatible(&self) -> bool {
+        self.incompatibilities.is_empty()
+    }
+}
+
+/// Which modules bytecode may reach through `GetModuleGlobals` and `CreateObject`.
+/// Code can always reach its own module.
+#[derive(Debug, Clone, Default)]
+pub enum ModuleAccess {
+    #[default]
+    Unrestricted,
+    Allow(HashSet<String>),
+}
+
+impl ModuleAccess {
+    pub fn allow<I, S>(names: I) -> ModuleAccess
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ModuleAccess::Allow(names.into_iter().map(Into::into).collect())
+    }
+
+    pub fn permits(&self, name: &str) -> bool {
+        match *self {
+            ModuleAccess::Unrestricted => true,
+            ModuleAccess::Allow(ref names) => names.contains(name),
+        }
+    }
+}
+
 pub struct Module {
     pub name: String,
     pub index: ModuleIndex,
     pub types: Vec<Type>,
     pub globals: Scope,
+    /// Overrides the interpreter's default access policy for code in this module.
+    pub access: Option<ModuleAccess>,
+    /// The globals visible to code outside the module, or `None` if all of them are.
+    pub exports: Option<HashSet<Symbol>>,
+    /// Globals created by the module's definition (free functions and whatever its initializer
+    /// assigned) rather than by other code at runtime.
+    pub defined_names: HashSet<Symbol>,
+    // What code outside the module sees of its globals, once any has looked
+    exports_view: Option<ObjectToken>,
 }
 
 impl Module {
@@ -142,19 +524,29 @@ impl Module {
             index,
             types: vec![],
             globals: Scope::new(),
+            access: None,
+            exports: None,
+            defined_names: HashSet::new(),
+            exports_view: None,
         }
     }
 
     #[allow(unused)]
-    fn lookup_type_mut(&mut self, name: &str) -> Option<&mut Type> {
+    fn lookup_type_mut(&mut self, name: Symbol) -> Option<&mut Type> {
         self.types.iter_mut().find(|ty| ty.name == name)
     }
 
-    fn lookup_type_index(&self, name: &str) -> Option<usize> {
+    fn lookup_type_index(&self, name: Symbol) -> Option<usize> {
         self.types
             .iter()
             .enumerate()
-            .filter_map(|(i, ty)| if ty.name == name { Some(i) } else { None })
+            .filter_map(|(i, ty)| {
+                if ty.name == name && !ty.removed {
+                    Some(i)
+                } else {
+                    None
+                }
+            })
             .next()
     }
 
@@ -168,7 +560,7 @@ impl Module {
         F: FnOnce(&mut Interpreter, &mut Module, &mut Type) -> O,
     {
         let index = TypeIndex(self.index, self.types.len());
-        let mut ty = Type::new(name, index);
+        let mut ty = Type::new(&self.name, name, index);
         let res = (func)(interpreter, self, &mut ty);
         self.types.push(ty);
         (index, res)
@@ -181,19 +573,19 @@ pub struct Scope {
 
 macro_rules! get_internal_member {
     ($target:expr, $name:expr) => {
-        $target.get_member(concat!("!", $name))
+        $target.get_member(sym!(concat!("!", $name)))
     };
 }
 
 macro_rules! assign_member_internal {
     ($target:expr, $name:expr, $val:expr, $interpreter:expr) => {
-        $target.assign_member(concat!("!", $name).to_owned(), $val, $interpreter)
+        $target.assign_member(sym!(concat!("!", $name)), $val, $interpreter)
     };
 }
 
 macro_rules! with_internal_member {
     ($target:expr, $name:expr, $func:expr) => {
-        $target.with_member_ref(concat!("!", $name), $func)
+        $target.with_member_ref(sym!(concat!("!", $name)), $func)
     };
 }
 
@@ -212,17 +604,16 @@ impl Scope {
 
     fn into_child(self, interpreter: &mut Interpreter) -> Scope {
         let child = Scope::new();
-        assign_member_internal!(child.vars, "parent", self.vars, interpreter);
+        // Scopes made here aren't accounted for, so this can't run out of memory
+        let _ = assign_member_internal!(child.vars, "parent", self.vars, interpreter);
         child
     }
 
     fn from_object(obj: ObjectToken) -> Result<Scope, TriconeError> {
-        if obj.obj().type_ == consts::SCOPE_TYPE_ID {
+        if obj.type_index() == consts::SCOPE_TYPE_ID {
             Ok(Scope { vars: obj })
         } else {
-            Err(TriconeError {
-                kind: ErrorKind::TypeError,
-            })
+            Err(TriconeError::new(ErrorKind::TypeError))
         }
     }
 
@@ -237,26 +628,67 @@ impl Scope {
         with_internal_member!(vars, "parent", func)
     }
 
-    fn token_lookup_name(vars: &ObjectToken, name: &str) -> Option<ObjectToken> {
-        println!(
-            "looking for {} in {:?}",
-            name,
-            vars.obj().members.keys().collect::<Vec<_>>()
-        );
+    /// Adds the number of scopes searched in vain to `misses`.
+    fn token_lookup_name(
+        vars: &ObjectToken,
+        name: Symbol,
+        mut trace: Option<&mut (dyn Write + 'static)>,
+        misses: &mut u64,
+    ) -> Option<ObjectToken> {
+        if let Some(ref mut trace) = trace {
+            let _ = writeln!(
+                trace,
+                "looking for {} in {:?}",
+                name,
+                vars.obj().members.keys().collect::<Vec<_>>()
+            );
+        }
         let opt = vars.get_member(name);
         opt.or_else(|| {
+            *misses += 1;
             Scope::with_parent(vars, |parent| {
-                parent.and_then(|p| Scope::token_lookup_name(p, name))
+                parent.and_then(|p| Scope::token_lookup_name(p, name, trace, misses))
             })
         })
     }
 
-    fn lookup_name(&self, name: &str) -> Option<ObjectToken> {
-        let res = Scope::token_lookup_name(&self.vars, name);
-        if res.is_some() {
-            println!("found {}!", name);
-        } else {
-            println!("did not find {}!", name);
+    /// Like `token_lookup_name`, but also returns the scope the name is in. It marks the scopes
+    /// it searches, so that adding or removing their names invalidates cached lookups.
+    fn token_resolve_name(
+        vars: &ObjectToken,
+        name: Symbol,
+        misses: &mut u64,
+    ) -> Option<(ObjectToken, WeakToken)> {
+        let found = {
+            let mut obj = vars.obj_mut();
+            obj.names_cached = true;
+            obj.members.get(&name).map(ObjectToken::dup)
+        };
+        match found {
+            Some(value) => Some((value, vars.downgrade())),
+            None => {
+                *misses += 1;
+                Scope::with_parent(vars, |parent| {
+                    parent.and_then(|p| Scope::token_resolve_name(p, name, misses))
+                })
+            }
+        }
+    }
+
+    /// Logs the scopes it searches to `trace`, if there is one.
+    fn lookup_name(
+        &self,
+        name: Symbol,
+        mut trace: Option<&mut (dyn Write + 'static)>,
+        misses: &mut u64,
+    ) -> Option<ObjectToken> {
+        let res = Scope::token_lookup_name(&self.vars, name, trace.as_deref_mut(), misses);
+        if let Some(trace) = trace {
+            let _ = if res.is_some() {
+                writeln!(trace, "found {}!", name)
+            } else {
+                writeln!(trace, "did not find {}!", name)
+            };
         }
         res
     }
@@ -278,11 +710,46 @@ impl Deref for Scope {
 
 pub struct Frame {
     top_scope: Scope,
+    // Whether `top_scope` is still the closure of the function that runs in the frame. The
+    // function gets a child scope of its own the first time it writes to it.
+    shares_scope: bool,
+    // What runs in the frame, for tracebacks
+    function: Option<Rc<str>>,
+    num_args: usize,
+    bytecode: Option<Rc<Bytecode>>,
+    // The instruction `bytecode` is at
+    pc: Option<usize>,
+    // Where the local slots of `bytecode` start in `Thread::locals`
+    locals_base: usize,
 }
 
 impl Frame {
-    fn new(top_scope: Scope) -> Frame {
-        Frame { top_scope }
+    fn new(top_scope: Scope, shares_scope: bool) -> Frame {
+        Frame {
+            top_scope,
+            shares_scope,
+            function: None,
+            num_args: 0,
+            bytecode: None,
+            pc: None,
+            locals_base: 0,
+        }
+    }
+
+    fn traceback_entry(&self) -> TracebackEntry {
+        let location = match (&self.bytecode, self.pc) {
+            (Some(bytecode), Some(pc)) => bytecode
+                .debug_info
+                .as_ref()
+                .and_then(|debug_info| debug_info.location(pc)),
+            _ => None,
+        };
+        TracebackEntry {
+            function: self.function.as_ref().map(|name| name.to_string()),
+            num_args: self.num_args,
+            instruction: self.pc,
+            location,
+        }
     }
 
     fn push_scope(&mut self, interpreter: &mut Interpreter) {
@@ -307,7 +774,11 @@ impl Deref for Frame {
 
 pub struct Thread {
     operation_stack: Vec<ObjectToken>,
+    // The local slots of all running bytecode, the innermost last
+    locals: Vec<Option<ObjectToken>>,
     frame_stack: Vec<Frame>,
+    // The module of the innermost running bytecode
+    module_context: Option<ModuleIndex>,
 }
 
 impl Thread {
@@ -316,56 +787,267 @@ impl Thread {
     }
 }
 
+/// A value small enough to live in its token: it takes no allocation, has no members and never
+/// needs dropping. Tokens for the same value are the same object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Immediate {
+    Int(i64),
+    Bool(bool),
+    Unit,
+}
+
+impl Immediate {
+    pub fn type_index(self) -> TypeIndex {
+        match self {
+            Immediate::Int(_) => consts::INT_TYPE_ID,
+            Immediate::Bool(_) => consts::BOOL_TYPE_ID,
+            Immediate::Unit => consts::UNIT_TYPE_ID,
+        }
+    }
+
+    /// The value of a new object of type `type_`, if its objects are immediates.
+    pub fn default_for(type_: TypeIndex) -> Option<Immediate> {
+        match type_ {
+            consts::INT_TYPE_ID => Some(Immediate::Int(0)),
+            consts::BOOL_TYPE_ID => Some(Immediate::Bool(false)),
+            consts::UNIT_TYPE_ID => Some(Immediate::Unit),
+            _ => None,
+        }
+    }
+
+    // An object holding the value, for code that reads objects
+    fn to_object(self) -> Object {
+        unsafe {
+            match self {
+                Immediate::Int(value) => {
+                    generic::create_object_from_val(consts::INT_TYPE_ID, value)
+                }
+                Immediate::Bool(value) => {
+                    generic::create_object_from_val(consts::BOOL_TYPE_ID, value)
+                }
+                Immediate::Unit => Object::raw_new(consts::UNIT_TYPE_ID),
+            }
+        }
+    }
+}
+
 #[must_use]
-pub struct ObjectToken(Rc<RefCell<Object>>);
+pub enum ObjectToken {
+    Heap(Rc<RefCell<Object>>),
+    Immediate(Immediate),
+}
+
+/// What `ObjectToken::obj` gives access to: the object itself, or one made up for an immediate.
+pub enum ObjectRef<'a> {
+    Heap(Ref<'a, Object>),
+    Immediate(Object),
+}
+
+impl<'a> Deref for ObjectRef<'a> {
+    type Target = Object;
+
+    fn deref(&self) -> &Object {
+        match *self {
+            ObjectRef::Heap(ref obj) => obj,
+            ObjectRef::Immediate(ref obj) => obj,
+        }
+    }
+}
 
 impl ObjectToken {
     pub fn new(obj: Object) -> ObjectToken {
-        ObjectToken(Rc::new(RefCell::new(obj)))
+        ObjectToken::Heap(Rc::new(RefCell::new(obj)))
+    }
+
+    pub fn type_index(&self) -> TypeIndex {
+        match *self {
+            ObjectToken::Heap(ref obj) => obj.borrow().type_,
+            ObjectToken::Immediate(value) => value.type_index(),
+        }
+    }
+
+    pub fn immediate(&self) -> Option<Immediate> {
+        match *self {
+            ObjectToken::Heap(_) => None,
+            ObjectToken::Immediate(value) => Some(value),
+        }
     }
 
-    fn get_member(&self, name: &str) -> Option<ObjectToken> {
-        self.obj().members.get(name).map(ObjectToken::dup)
+    fn get_member(&self, name: Symbol) -> Option<ObjectToken> {
+        match *self {
+            ObjectToken::Heap(ref obj) => obj.borrow().members.get(&name).map(ObjectToken::dup),
+            ObjectToken::Immediate(_) => None,
+        }
     }
 
-    fn with_member_ref<F, O>(&self, name: &str, func: F) -> O
+    fn with_member_ref<F, O>(&self, name: Symbol, func: F) -> O
     where
         F: FnOnce(Option<&ObjectToken>) -> O,
     {
         let obj = self.obj();
-        (func)(obj.members.get(name))
+        (func)(obj.members.get(&name))
     }
 
-    pub fn assign_member(&self, name: String, obj: ObjectToken, interpreter: &mut Interpreter) {
-        if let Some(token) = self.obj_mut().members.insert(name, obj) {
-            interpreter.drop_token(token);
+    /// Immediates have no members; assigning one panics. Adding a member to an object the
+    /// interpreter accounts for fails with `ErrorKind::MemoryError` past the memory limit, and
+    /// drops `obj`.
+    pub fn assign_member(
+        &self,
+        name: Symbol,
+        obj: ObjectToken,
+        interpreter: &mut Interpreter,
+    ) -> Result<(), TriconeError> {
+        let mut object = self.obj_mut();
+        if object.footprint.is_some() && !object.members.contains_key(&name) {
+            if let Err(err) = interpreter.reserve_memory(memory::MEMBER_SIZE) {
+                drop(object);
+                interpreter.drop_token(obj);
+                return Err(err);
+            }
+        }
+        let mirrored = object
+            .mirror
+            .as_ref()
+            .and_then(|mirror| mirror.view_of(name))
+            .map(|view| (view, obj.dup()));
+        object.members_version += 1;
+        match object.members.insert(name, obj) {
+            Some(token) => {
+                drop(object);
+                interpreter.drop_token(token);
+            }
+            None => {
+                if object.names_cached {
+                    interpreter.names_epoch += 1;
+                }
+                if let Some(ref mut footprint) = object.footprint {
+                    *footprint += memory::MEMBER_SIZE;
+                    interpreter.memory.live_bytes += memory::MEMBER_SIZE;
+                }
+                drop(object);
+            }
+        }
+        match mirrored {
+            Some((view, obj)) => {
+                let res = view.assign_member(name, obj, interpreter);
+                interpreter.drop_token(view);
+                res
+            }
+            None => Ok(()),
         }
     }
 
-    pub fn obj(&self) -> Ref<Object> {
-        self.0.borrow()
+    pub fn obj(&self) -> ObjectRef<'_> {
+        match *self {
+            ObjectToken::Heap(ref obj) => ObjectRef::Heap(obj.borrow()),
+            ObjectToken::Immediate(value) => ObjectRef::Immediate(value.to_object()),
+        }
     }
 
-    pub fn obj_mut(&self) -> RefMut<Object> {
-        self.0.borrow_mut()
+    /// Panics for immediates, which can't be changed.
+    pub fn obj_mut(&self) -> RefMut<'_, Object> {
+        match *self {
+            ObjectToken::Heap(ref obj) => obj.borrow_mut(),
+            ObjectToken::Immediate(value) => panic!("{:?} can't be changed", value),
+        }
     }
 
     pub fn dup(&self) -> ObjectToken {
-        ObjectToken(Rc::clone(&self.0))
+        match *self {
+            ObjectToken::Heap(ref obj) => ObjectToken::Heap(Rc::clone(obj)),
+            ObjectToken::Immediate(value) => ObjectToken::Immediate(value),
+        }
     }
 
-    fn into_rc(mut self) -> Rc<RefCell<Object>> {
-        unsafe {
-            let rc = mem::replace(&mut self.0, mem::uninitialized());
-            mem::forget(self);
-            rc
+    pub(crate) fn downgrade(&self) -> WeakToken {
+        match *self {
+            ObjectToken::Heap(ref obj) => WeakToken::Heap(Rc::downgrade(obj)),
+            ObjectToken::Immediate(value) => WeakToken::Immediate(value),
+        }
+    }
+
+    /// Removes a member, keeping inline caches in sync.
+    pub(crate) fn remove_member(
+        &self,
+        name: Symbol,
+        interpreter: &mut Interpreter,
+    ) -> Option<ObjectToken> {
+        let mut object = self.obj_mut();
+        let removed = object.members.remove(&name)?;
+        object.members_version += 1;
+        if object.names_cached {
+            interpreter.names_epoch += 1;
+        }
+        if let Some(ref mut footprint) = object.footprint {
+            *footprint -= memory::MEMBER_SIZE;
+            interpreter.memory.live_bytes -= memory::MEMBER_SIZE;
+        }
+        let view = object
+            .mirror
+            .as_ref()
+            .and_then(|mirror| mirror.view_of(name));
+        drop(object);
+        if let Some(view) = view {
+            if let Some(obj) = view.remove_member(name, interpreter) {
+                interpreter.drop_token(obj);
+            }
+            interpreter.drop_token(view);
+        }
+        Some(removed)
+    }
+
+    // The object, unless the token is an immediate
+    fn into_rc(self) -> Option<Rc<RefCell<Object>>> {
+        let this = mem::ManuallyDrop::new(self);
+        match *this {
+            ObjectToken::Heap(ref obj) => Some(unsafe { ptr::read(obj) }),
+            ObjectToken::Immediate(_) => None,
+        }
+    }
+}
+
+impl From<Immediate> for ObjectToken {
+    fn from(value: Immediate) -> ObjectToken {
+        ObjectToken::Immediate(value)
+    }
+}
+
+/// A reference that doesn't keep its object alive, so it can be dropped without the
+/// interpreter.
+#[derive(Clone)]
+pub(crate) enum WeakToken {
+    Heap(Weak<RefCell<Object>>),
+    Immediate(Immediate),
+}
+
+impl WeakToken {
+    pub fn upgrade(&self) -> Option<ObjectToken> {
+        match *self {
+            WeakToken::Heap(ref obj) => obj.upgrade().map(ObjectToken::Heap),
+            WeakToken::Immediate(value) => Some(ObjectToken::Immediate(value)),
+        }
+    }
+
+    /// Whether `token` refers to the same object. Only a live object can, as the weak
+    /// reference keeps the allocation from being reused.
+    pub fn refers_to(&self, token: &ObjectToken) -> bool {
+        match (self, token) {
+            (WeakToken::Heap(weak), ObjectToken::Heap(obj)) => {
+                Weak::as_ptr(weak) == Rc::as_ptr(obj)
+            }
+            (WeakToken::Immediate(a), ObjectToken::Immediate(b)) => a == b,
+            _ => false,
         }
     }
 }
 
 impl PartialEq for ObjectToken {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_ptr() == other.0.as_ptr()
+        match (self, other) {
+            (ObjectToken::Heap(a), ObjectToken::Heap(b)) => Rc::ptr_eq(a, b),
+            (ObjectToken::Immediate(a), ObjectToken::Immediate(b)) => a == b,
+            _ => false,
+        }
     }
 }
 
@@ -373,7 +1055,10 @@ impl Eq for ObjectToken {}
 
 impl Hash for ObjectToken {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.0.as_ptr().hash(state)
+        match *self {
+            ObjectToken::Heap(ref obj) => obj.as_ptr().hash(state),
+            ObjectToken::Immediate(value) => value.hash(state),
+        }
     }
 }
 
@@ -383,15 +1068,19 @@ thread_local! {
 
 impl fmt::Debug for ObjectToken {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let obj = match *self {
+            ObjectToken::Heap(ref obj) => obj,
+            ObjectToken::Immediate(value) => return fmt::Debug::fmt(&value, f),
+        };
         OBJECTS_BEING_PRINTED.with(|set| {
-            let didnt_exist = { set.borrow_mut().insert(self.0.as_ptr()) };
+            let didnt_exist = { set.borrow_mut().insert(obj.as_ptr()) };
             if didnt_exist {
-                let res = if let Ok(obj) = self.0.try_borrow() {
+                let res = if let Ok(obj) = obj.try_borrow() {
                     fmt::Debug::fmt(&obj, f)
                 } else {
                     f.write_str("<Object, borrowed>")
                 };
-                set.borrow_mut().remove(&self.0.as_ptr());
+                set.borrow_mut().remove(&obj.as_ptr());
                 res
             } else {
                 f.write_str("{...}")
@@ -402,27 +1091,58 @@ impl fmt::Debug for ObjectToken {
 
 impl Drop for ObjectToken {
     fn drop(&mut self) {
-        let obj = self.obj();
-        println!(
-            "Pass object tokens to the interpreter to destroy them, ty: {:?}, members: {:?}",
-            obj.type_, obj.members
-        );
-        abort();
+        if let ObjectToken::Heap(ref obj) = *self {
+            let obj = obj.borrow();
+            println!(
+                "Pass object tokens to the interpreter to destroy them, ty: {:?}, members: {:?}",
+                obj.type_, obj.members
+            );
+            abort();
+        }
     }
 }
 
 pub struct Object {
-    pub members: HashMap<String, ObjectToken>,
+    /// Change these through `ObjectToken::assign_member`, or inline caches miss the change.
+    pub members: SymbolMap<ObjectToken>,
     pub type_: TypeIndex,
     pub data: Vec<u8>,
+    // Bytes charged to the interpreter for this object, for objects it is accounting for
+    footprint: Option<usize>,
+    // Bumped whenever a member is assigned or removed
+    members_version: u64,
+    // Set once a cached name lookup went through the object, so adding or removing names has
+    // to invalidate name caches
+    names_cached: bool,
+    // For a module's globals, the view of its exports that has to follow their changes
+    mirror: Option<Box<Mirror>>,
+}
+
+struct Mirror {
+    // `None` if every global is exported
+    exported: Option<HashSet<Symbol>>,
+    view: WeakToken,
+}
+
+impl Mirror {
+    fn view_of(&self, name: Symbol) -> Option<ObjectToken> {
+        match self.exported {
+            Some(ref exported) if !exported.contains(&name) => None,
+            _ => self.view.upgrade(),
+        }
+    }
 }
 
 impl Object {
     pub fn raw_new(type_: TypeIndex) -> Object {
         Object {
-            members: HashMap::new(),
+            members: SymbolMap::default(),
             type_,
             data: vec![],
+            footprint: None,
+            members_version: 0,
+            names_cached: false,
+            mirror: None,
         }
     }
 }
@@ -443,6 +1163,10 @@ pub(crate) mod consts {
     pub const SCOPE_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 0);
     pub const UNIT_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 1);
     pub const FUNCTION_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 2);
+    pub const INT_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 3);
+    pub const STRING_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 4);
+    pub const BOOL_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 5);
+    pub const EXPORTS_TYPE_ID: TypeIndex = TypeIndex(CORE_MODULE_ID, 6);
 
     pub const CREATE_METHOD_NAME: &str = "create";
     pub const DROP_METHOD_NAME: &str = "drop";
@@ -450,120 +1174,1143 @@ pub(crate) mod consts {
 
 pub struct Interpreter {
     modules: Vec<Module>,
+    module_indices: SymbolMap<ModuleIndex>,
+    module_loader: Option<Box<dyn ModuleLoader>>,
+    // Names of the modules currently being imported, outermost first
+    importing: Vec<String>,
     thread: Thread,
+    fuel: Option<u64>,
+    fuel_costs: FuelCosts,
+    memory: MemoryUsage,
+    // The names charged to `memory`
+    names: SymbolSet,
+    memory_limit: Option<usize>,
+    module_access: ModuleAccess,
+    trace: bool,
+    strip_diagnostics: bool,
+    debug: DebugState,
+    profiler: Option<Profiler>,
+    stats: Counters,
+    // `None` reads the process's stdin, which is only locked while reading so that the host
+    // can read it too
+    stdin: Option<Box<dyn BufRead>>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    // Bumped whenever a name is added to or removed from a scope a cached lookup went through
+    names_epoch: u64,
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
         let mut interpreter = Interpreter {
             modules: vec![],
+            module_indices: SymbolMap::default(),
+            module_loader: None,
+            importing: vec![],
             thread: Thread {
                 operation_stack: vec![],
+                locals: vec![],
                 frame_stack: vec![],
+                module_context: None,
             },
+            fuel: None,
+            fuel_costs: FuelCosts::default(),
+            memory: MemoryUsage::default(),
+            names: SymbolSet::default(),
+            memory_limit: None,
+            module_access: ModuleAccess::default(),
+            trace: true,
+            strip_diagnostics: false,
+            debug: DebugState::default(),
+            profiler: None,
+            stats: Counters::default(),
+            stdin: None,
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            names_epoch: 0,
         };
 
-        interpreter.create_module("core", move |interpreter, module| {
-            for ty_name in &["Scope", "Unit"] {
-                module.create_type(interpreter, ty_name, move |_interpreter, _module, _ty| {});
-            }
-            function::register_func_type(interpreter, module);
-            int::register_int_type(interpreter, module);
-            string::register_string_type(interpreter, module);
-            bool_::register_bool_type(interpreter, module);
-        });
+        interpreter
+            .create_module("core", move |interpreter, module| {
+                for ty_name in &["Scope", "Unit"] {
+                    module.create_type(interpreter, ty_name, move |_interpreter, _module, _ty| {});
+                }
+                function::register_func_type(interpreter, module);
+                int::register_int_type(interpreter, module);
+                string::register_string_type(interpreter, module);
+                bool_::register_bool_type(interpreter, module);
+                module.create_type(interpreter, "Exports", |_, _, ty| {
+                    assert_eq!(ty.index, consts::EXPORTS_TYPE_ID);
+                });
+            })
+            .expect("core is the first module");
         builtins::register_builtins(&mut interpreter);
 
         interpreter
     }
 
-    pub fn create_module<F, O>(&mut self, name: &str, func: F) -> (ModuleIndex, O)
+    /// Fails with `ErrorKind::NameError` if a module named `name` is registered already.
+    pub fn create_module<F, O>(
+        &mut self,
+        name: &str,
+        func: F,
+    ) -> Result<(ModuleIndex, O), TriconeError>
     where
         F: FnOnce(&mut Interpreter, &mut Module) -> O,
     {
+        if self.lookup_module_index(name).is_some() {
+            return Err(TriconeError::with_message(
+                ErrorKind::NameError,
+                format!("a module named `{}` is registered already", name),
+            ));
+        }
         let mod_idx = ModuleIndex(self.modules.len());
         let mut module = Module::new(mod_idx, name);
         let res = (func)(self, &mut module);
         self.modules.push(module);
-        (mod_idx, res)
+        self.module_indices.insert(Symbol::new(name), mod_idx);
+        Ok((mod_idx, res))
     }
 
-    fn lookup_module_index(&self, name: &str) -> Option<ModuleIndex> {
-        self.modules
-            .iter()
-            .enumerate()
-            .find(|(_, m)| m.name == name)
-            .map(|(i, _)| ModuleIndex(i))
+    pub fn lookup_module_index(&self, name: &str) -> Option<ModuleIndex> {
+        Symbol::lookup(name).and_then(|name| self.module_indices.get(&name).cloned())
     }
 
-    pub fn get_module(&self, idx: ModuleIndex) -> &Module {
-        &self.modules[idx.0]
+    /// The symbol naming `name` in instructions, members and method tables.
+    pub fn intern(&self, name: &str) -> Symbol {
+        Symbol::new(name)
     }
 
-    pub fn get_module_mut(&mut self, idx: ModuleIndex) -> &mut Module {
-        &mut self.modules[idx.0]
+    pub fn set_module_loader(&mut self, loader: Box<dyn ModuleLoader>) {
+        self.module_loader = Some(loader);
     }
 
-    pub fn lookup_type(&self, modidx: ModuleIndex, name: &str) -> Option<TypeIndex> {
-        self.get_module(modidx)
-            .lookup_type_index(name)
-            .map(|idx| TypeIndex(modidx, idx))
+    /// Whether `import_module` could load `name` through the module loader.
+    pub(crate) fn can_load_module(&self, name: &str) -> bool {
+        self.module_loader
+            .as_ref()
+            .is_some_and(|loader| loader.has_module(name))
     }
 
-    pub fn get_type(&self, idx: TypeIndex) -> &Type {
-        let TypeIndex(modidx, tyidx) = idx;
-        &self.get_module(modidx).types[tyidx]
-    }
+    /// Returns the index of the module called `name`, loading and registering it through the
+    /// module loader the first time it is asked for.
+    pub fn import_module(&mut self, name: &str) -> Result<ModuleIndex, TriconeError> {
+        // A module that is still initializing is registered already, so check this first
+        if self.importing.iter().any(|importing| importing == name) {
+            let mut chain = self.importing.clone();
+            chain.push(name.to_owned());
+            return Err(TriconeError::with_message(
+                ErrorKind::ImportCycle,
+                chain.join(" -> "),
+            ));
+        }
 
-    pub fn register_type(&mut self, modidx: ModuleIndex, ty: Type) -> TypeIndex {
-        let module = self.get_module_mut(modidx);
-        module.types.push(ty);
-        TypeIndex(modidx, module.types.len() - 1)
-    }
+        if let Some(idx) = self.lookup_module_index(name) {
+            return Ok(idx);
+        }
 
-    pub fn with_new_frame<F, O>(&mut self, scope: Scope, function: F) -> O
-    where
-        F: FnOnce(&mut Interpreter) -> O,
-    {
-        let mut frame = Frame::new(scope);
-        frame.push_scope(self);
-        self.thread.frame_stack.push(frame);
-        let res = (function)(self);
-        let mut frame = self.thread.frame_stack.pop().unwrap();
-        frame.pop_scope(self);
-        self.drop_token(frame.top_scope.vars);
-        res
-    }
+        let def = match self.module_loader {
+            Some(ref mut loader) => loader.load_module(name)?,
+            None => None,
+        };
+        let def = def.ok_or_else(|| {
+            TriconeError::with_message(
+                ErrorKind::ImportError,
+                format!("no module named `{}`", name),
+            )
+        })?;
 
-    fn with_current_frame<F, O>(&mut self, function: F) -> O
-    where
-        F: FnOnce(&mut Interpreter, &mut Frame) -> O,
-    {
-        // Ugly :(
-        let mut frame = self.thread.frame_stack.pop().unwrap();
-        let res = (function)(self, &mut frame);
-        self.thread.frame_stack.push(frame);
-        res
+        def.register(self)
     }
 
-    pub fn with_new_scope<F, O>(&mut self, function: F) -> O
+    pub(crate) fn with_module_importing<F, O>(&mut self, name: &str, function: F) -> O
     where
         F: FnOnce(&mut Interpreter) -> O,
     {
-        self.with_current_frame(|i, f| f.push_scope(i));
+        self.importing.push(name.to_owned());
         let res = (function)(self);
-        self.with_current_frame(|i, f| f.pop_scope(i));
+        self.importing.pop();
         res
     }
 
-    pub fn create_object(&mut self, tyidx: TypeIndex, num_args: usize) -> ObjectToken {
-        let obj = ObjectToken::new(Object {
-            members: HashMap::new(),
-            type_: tyidx,
-            data: vec![],
+    /// Replaces the code of an already registered module with a new definition of it. Types and
+    /// the module keep their indices, so existing objects pick up the new methods. Globals that
+    /// the old definition created and the new one doesn't are dropped, everything else in the
+    /// module's globals is kept, and the new initializer (if any) runs again.
+    pub fn reload_module(&mut self, mut def: ModuleDef) -> Result<ReloadReport, TriconeError> {
+        moduledef::check_init(&def.name, &def.init)?;
+        let idx = self.lookup_module_index(&def.name).ok_or_else(|| {
+            TriconeError::with_message(
+                ErrorKind::NameError,
+                format!("no module named `{}`", def.name),
+            )
+        })?;
+        link::link_module(self, idx, &mut def)?;
+        optimize::optimize_module(&mut def, self.strip_diagnostics);
+        let ModuleDef {
+            name: _,
+            types,
+            free_functions,
+            init,
+            exports,
+            access,
+        } = def;
+        if access.is_some() {
+            self.get_module_mut(idx).access = access;
+        }
+        let mut report = ReloadReport::default();
+
+        let mut new_types = types;
+        for pos in 0..self.get_module(idx).types.len() {
+            let (type_name, removed, live_instances, scope) = {
+                let ty = &self.get_module(idx).types[pos];
+                (
+                    ty.name.as_str().to_owned(),
+                    ty.removed,
+                    ty.live_instances,
+                    ty.scope.dup(),
+                )
+            };
+            let old_methods = match new_types.remove(&type_name) {
+                Some(tydef) => {
+                    let new_methods: SymbolMap<_> = tydef
+                        .methods
+                        .into_iter()
+                        .map(|(name, def)| {
+                            let function = def.into_function(idx, scope.dup());
+                            (Symbol::new(&name), function)
+                        })
+                        .collect();
+                    let ty = &mut self.get_module_mut(idx).types[pos];
+                    let mut kept = vec![];
+                    for (method, old) in &ty.methods {
+                        match new_methods.get(method) {
+                            Some(new) if new.arity() != old.arity() => report
+                                .incompatibilities
+                                .push(ReloadIncompatibility::ArityChanged {
+                                    name: format!("{}.{}", type_name, method),
+                                    old: old.arity(),
+                                    new: new.arity(),
+                                }),
+                            None if live_instances > 0 => {
+                                kept.push(*method);
+                                report.incompatibilities.push(
+                                    ReloadIncompatibility::RemovedMethodInUse {
+                                        type_name: type_name.clone(),
+                                        method: method.to_string(),
+                                        live_instances,
+                                    },
+                                )
+                            }
+                            _ => {}
+                        }
+                    }
+                    if removed {
+                        ty.removed = false;
+                        report.added_types.push(type_name);
+                    }
+                    let mut old_methods = ty.replace_methods(new_methods);
+                    for method in kept {
+                        if let Some(old) = old_methods.remove(&method) {
+                            ty.methods.insert(method, old);
+                        }
+                    }
+                    old_methods
+                }
+                None if removed => SymbolMap::default(),
+                None if live_instances > 0 => {
+                    report
+                        .incompatibilities
+                        .push(ReloadIncompatibility::RemovedTypeInUse {
+                            type_name,
+                            live_instances,
+                        });
+                    SymbolMap::default()
+                }
+                None => {
+                    let ty = &mut self.get_module_mut(idx).types[pos];
+                    ty.removed = true;
+                    report.removed_types.push(type_name);
+                    ty.replace_methods(SymbolMap::default())
+                }
+            };
+            for (_, method) in old_methods {
+                self.drop_token(method.closure.vars);
+            }
+            self.drop_token(scope.vars);
+        }
+
+        // In the order linking gave them indices in
+        let mut new_types: Vec<_> = new_types.into_iter().collect();
+        new_types.sort_by(|a, b| a.0.cmp(&b.0));
+        for (type_name, tydef) in new_types {
+            let index = TypeIndex(idx, self.get_module(idx).types.len());
+            let mut ty = Type::new(&self.get_module(idx).name, &type_name, index);
+            for (name, def) in tydef.methods {
+                let scope = ty.scope.dup();
+                ty.register_method(&name, def.into_function(idx, scope));
+            }
+            self.get_module_mut(idx).types.push(ty);
+            report.added_types.push(type_name);
+        }
+
+        let globals = self.get_module(idx).globals.dup();
+        let mut defined = HashSet::new();
+        for (name, def) in free_functions {
+            let symbol = Symbol::new(&name);
+            if let Some(old) = globals.get_member(symbol) {
+                let old_arity = {
+                    let old = old.obj();
+                    if old.type_ == consts::FUNCTION_TYPE_ID {
+                        Some(function::function_from_function_object(&old).arity())
+                    } else {
+                        None
+                    }
+                };
+                match (old_arity, &def) {
+                    (Some(old), &FunctionDef::Bytecode(BytecodeFunctionDef { arity, .. }))
+                    | (Some(old), &FunctionDef::Native(NativeFunctionDef { arity, .. }))
+                        if old != arity =>
+                    {
+                        report
+                            .incompatibilities
+                            .push(ReloadIncompatibility::ArityChanged {
+                                name: name.clone(),
+                                old,
+                                new: arity,
+                            })
+                    }
+                    _ => {}
+                }
+                self.drop_token(old);
+            }
+            let function = def.into_function(idx, globals.dup()).with_name(format!(
+                "{}::{}",
+                self.get_module(idx).name,
+                name
+            ));
+            globals.assign_member(
+                symbol,
+                function::function_object_from_function(function),
+                self,
+            )?;
+            defined.insert(symbol);
+        }
+        self.get_module_mut(idx).exports =
+            exports.map(|exports| exports.iter().map(|name| Symbol::new(name)).collect());
+        if let Err(err) = self.sync_exports_view(idx) {
+            self.drop_token(globals.vars);
+            return Err(err);
+        }
+
+        if let Some(init) = init {
+            match self.run_init_code(idx, &init) {
+                Ok(names) => defined.extend(names),
+                Err(err) => {
+                    // Don't forget what the old definition created, it may still be dropped
+                    // by a later reload
+                    self.get_module_mut(idx).defined_names.extend(defined);
+                    self.drop_token(globals.vars);
+                    return Err(err);
+                }
+            }
+        }
+
+        let old_defined = mem::replace(&mut self.get_module_mut(idx).defined_names, defined);
+        for name in old_defined {
+            if self.get_module(idx).defined_names.contains(&name) {
+                continue;
+            }
+            let removed = globals.remove_member(name, self);
+            if let Some(obj) = removed {
+                self.drop_token(obj);
+                report.removed_globals.push(name.to_string());
+            }
+        }
+        self.drop_token(globals.vars);
+
+        report.added_types.sort();
+        report.removed_types.sort();
+        report.removed_globals.sort();
+        Ok(report)
+    }
+
+    /// Runs a module's initializer in a frame whose scope is the module's globals. If it fails,
+    /// the module is unregistered by name so a later import can try again.
+    pub fn run_module_init(
+        &mut self,
+        idx: ModuleIndex,
+        init: &BytecodeFunctionDef,
+    ) -> Result<(), TriconeError> {
+        match self.run_init_code(idx, init) {
+            Ok(names) => {
+                self.get_module_mut(idx).defined_names.extend(names);
+                Ok(())
+            }
+            Err(err) => {
+                self.unregister_module(idx);
+                Err(err)
+            }
+        }
+    }
+
+    /// Forgets the module's name, so a later import can try again. The module keeps its index.
+    pub(crate) fn unregister_module(&mut self, idx: ModuleIndex) {
+        let name = Symbol::new(&self.get_module(idx).name);
+        if self.module_indices.get(&name) == Some(&idx) {
+            self.module_indices.remove(&name);
+        }
+    }
+
+    /// Runs initializer code, returning the names of the globals it assigned.
+    fn run_init_code(
+        &mut self,
+        idx: ModuleIndex,
+        init: &BytecodeFunctionDef,
+    ) -> Result<Vec<Symbol>, TriconeError> {
+        let globals = self.get_module(idx).globals.dup();
+        let before: SymbolMap<WeakToken> = globals
+            .obj()
+            .members
+            .iter()
+            .map(|(&name, obj)| (name, obj.downgrade()))
+            .collect();
+
+        let code = Code::Bytecode(Rc::new(
+            Bytecode::new(
+                init.instructions.clone(),
+                Some(idx),
+                init.debug_info.clone(),
+            )
+            .with_locals(init.locals.clone()),
+        ));
+        let name = format!("{}::<init>", self.get_module(idx).name);
+        let res = self.with_frame_in_scope(globals, |interpreter| {
+            interpreter.set_frame_function(Some(name.into()), 0);
+            code.invoke(interpreter, &[])
+                .map_err(|err| interpreter.capture_traceback(err))
+        });
+        if let Some(obj) = res? {
+            self.drop_token(obj);
+        }
+
+        let globals = self.get_module(idx).globals.obj();
+        Ok(globals
+            .members
+            .iter()
+            .filter(|&(name, obj)| !before.get(name).is_some_and(|old| old.refers_to(obj)))
+            .map(|(&name, _)| name)
+            .collect())
+    }
+
+    /// The globals object of a module as the running code may see it: all of it from inside
+    /// the module, otherwise only its exports. Those are a read-only `Exports` object that is
+    /// made the first time and follows the globals from then on.
+    fn visible_globals(&mut self, idx: ModuleIndex) -> Result<ObjectToken, TriconeError> {
+        let module = self.get_module(idx);
+        if module.exports.is_none() || self.thread.module_context == Some(idx) {
+            return Ok(module.globals.vars.dup());
+        }
+        if let Some(ref view) = module.exports_view {
+            return Ok(view.dup());
+        }
+        let view = self.create_object(consts::EXPORTS_TYPE_ID, 0)?;
+        self.get_module_mut(idx).exports_view = Some(view.dup());
+        if let Err(err) = self.sync_exports_view(idx) {
+            self.drop_exports_view(idx);
+            self.drop_token(view);
+            return Err(err);
+        }
+        Ok(view)
+    }
+
+    // Fills a module's exports view from its globals, after the exports changed
+    fn sync_exports_view(&mut self, idx: ModuleIndex) -> Result<(), TriconeError> {
+        let module = self.get_module(idx);
+        let view = match module.exports_view {
+            Some(ref view) => view.dup(),
+            None => return Ok(()),
+        };
+        let globals = module.globals.dup();
+        let exported = module.exports.clone();
+        let stale: Vec<Symbol> = view.obj().members.keys().cloned().collect();
+        for name in stale {
+            if let Some(obj) = view.remove_member(name, self) {
+                self.drop_token(obj);
+            }
+        }
+        let members: Vec<(Symbol, ObjectToken)> = globals
+            .obj()
+            .members
+            .iter()
+            .filter(|(name, _)| {
+                exported
+                    .as_ref()
+                    .is_none_or(|exported| exported.contains(name))
+            })
+            .map(|(&name, obj)| (name, obj.dup()))
+            .collect();
+        globals.vars.obj_mut().mirror = Some(Box::new(Mirror {
+            exported,
+            view: view.downgrade(),
+        }));
+        self.drop_token(globals.vars);
+        let mut res = Ok(());
+        for (name, obj) in members {
+            match res {
+                Ok(()) => res = view.assign_member(name, obj, self),
+                Err(_) => self.drop_token(obj),
+            }
+        }
+        self.drop_token(view);
+        res
+    }
+
+    fn drop_exports_view(&mut self, idx: ModuleIndex) {
+        let module = self.get_module_mut(idx);
+        module.globals.vars.obj_mut().mirror = None;
+        if let Some(view) = module.exports_view.take() {
+            self.drop_token(view);
+        }
+    }
+
+    /// Sets the access policy for code that belongs to no module, or to a module without
+    /// a policy of its own.
+    pub fn set_default_module_access(&mut self, access: ModuleAccess) {
+        self.module_access = access;
+    }
+
+    pub fn default_module_access(&self) -> &ModuleAccess {
+        &self.module_access
+    }
+
+    /// Only applies to code that runs from now on. To have a module's initializer and linking
+    /// follow a policy too, give it one in its `ModuleDef`.
+    pub fn set_module_access(&mut self, idx: ModuleIndex, access: ModuleAccess) {
+        self.get_module_mut(idx).access = Some(access);
+    }
+
+    pub fn current_module(&self) -> Option<ModuleIndex> {
+        self.thread.module_context
+    }
+
+    pub fn with_module_context<F, O>(&mut self, module: Option<ModuleIndex>, function: F) -> O
+    where
+        F: FnOnce(&mut Interpreter) -> O,
+    {
+        let outer = mem::replace(&mut self.thread.module_context, module);
+        let res = (function)(self);
+        self.thread.module_context = outer;
+        res
+    }
+
+    /// Looks up a module on behalf of the running code, enforcing its access policy.
+    // Imports modules that linking left for the code to load when it first runs
+    fn resolve_module(&mut self, name: Symbol) -> Result<ModuleIndex, TriconeError> {
+        // Check first, so denied code can't tell which modules exist
+        self.check_module_access(name.as_str())?;
+        if let Some(&idx) = self.module_indices.get(&name) {
+            return Ok(idx);
+        }
+        if self.can_load_module(name.as_str()) {
+            return self.import_module(name.as_str());
+        }
+        Err(TriconeError::new(ErrorKind::NameError))
+    }
+
+    fn check_module_index_access(&self, idx: ModuleIndex) -> Result<(), TriconeError> {
+        if self.thread.module_context == Some(idx) {
+            return Ok(());
+        }
+        self.check_module_access(&self.get_module(idx).name)
+    }
+
+    fn check_module_access(&self, name: &str) -> Result<(), TriconeError> {
+        let access = match self.thread.module_context {
+            Some(current) if self.get_module(current).name == name => return Ok(()),
+            Some(current) => self.get_module(current).access.as_ref(),
+            None => None,
+        };
+        if access.unwrap_or(&self.module_access).permits(name) {
+            Ok(())
+        } else {
+            Err(TriconeError::new(ErrorKind::AccessDenied))
+        }
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    /// Whether every instruction and name lookup is logged to the interpreter's stdout.
+    pub fn tracing(&self) -> bool {
+        self.trace
+    }
+
+    pub fn set_tracing(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Whether modules registered from now on lose their `Diag` instructions, and have their
+    /// `DebugPrintObject`s only pop the object. Off unless the host turns it on.
+    pub fn strips_diagnostics(&self) -> bool {
+        self.strip_diagnostics
+    }
+
+    pub fn set_strip_diagnostics(&mut self, strip: bool) {
+        self.strip_diagnostics = strip;
+    }
+
+    /// Runs `function` with the stream programs read from, the process's stdin unless the host
+    /// sets another.
+    pub fn with_stdin<F, O>(&mut self, function: F) -> O
+    where
+        F: FnOnce(&mut dyn BufRead) -> O,
+    {
+        match self.stdin {
+            Some(ref mut stdin) => (function)(&mut **stdin),
+            None => (function)(&mut io::stdin().lock()),
+        }
+    }
+
+    pub fn set_stdin(&mut self, stdin: Box<dyn BufRead>) {
+        self.stdin = Some(stdin);
+    }
+
+    /// Where programs print to, and tracing, `Diag` and `DebugPrintObject` as well. It is the
+    /// process's stdout unless the host sets another; `output` has sinks for capturing it.
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        &mut *self.stdout
+    }
+
+    /// Flushes the previous stdout before replacing it.
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write>) {
+        let _ = self.stdout.flush();
+        self.stdout = stdout;
+    }
+
+    /// Where programs print errors to, the process's stderr unless the host sets another.
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        &mut *self.stderr
+    }
+
+    /// Flushes the previous stderr before replacing it.
+    pub fn set_stderr(&mut self, stderr: Box<dyn Write>) {
+        let _ = self.stderr.flush();
+        self.stderr = stderr;
+    }
+
+    pub fn get_module(&self, idx: ModuleIndex) -> &Module {
+        &self.modules[idx.0]
+    }
+
+    pub fn get_module_mut(&mut self, idx: ModuleIndex) -> &mut Module {
+        &mut self.modules[idx.0]
+    }
+
+    pub fn lookup_type(&self, modidx: ModuleIndex, name: &str) -> Option<TypeIndex> {
+        self.get_module(modidx)
+            .lookup_type_index(Symbol::lookup(name)?)
+            .map(|idx| TypeIndex(modidx, idx))
+    }
+
+    pub fn get_type(&self, idx: TypeIndex) -> &Type {
+        let TypeIndex(modidx, tyidx) = idx;
+        &self.get_module(modidx).types[tyidx]
+    }
+
+    pub(crate) fn get_type_mut(&mut self, idx: TypeIndex) -> &mut Type {
+        let TypeIndex(modidx, tyidx) = idx;
+        &mut self.get_module_mut(modidx).types[tyidx]
+    }
+
+    pub fn register_type(&mut self, modidx: ModuleIndex, ty: Type) -> TypeIndex {
+        let module = self.get_module_mut(modidx);
+        module.types.push(ty);
+        TypeIndex(modidx, module.types.len() - 1)
+    }
+
+    pub fn with_new_frame<F, O>(&mut self, scope: Scope, function: F) -> O
+    where
+        F: FnOnce(&mut Interpreter) -> O,
+    {
+        self.thread.frame_stack.push(Frame::new(scope, true));
+        let res = (function)(self);
+        let mut frame = self.thread.frame_stack.pop().unwrap();
+        if !frame.shares_scope {
+            frame.pop_scope(self);
+        }
+        self.drop_token(frame.top_scope.vars);
+        res
+    }
+
+    /// Like `with_new_frame`, but `Assign` writes directly into `scope` instead of a child.
+    pub fn with_frame_in_scope<F, O>(&mut self, scope: Scope, function: F) -> O
+    where
+        F: FnOnce(&mut Interpreter) -> O,
+    {
+        self.thread.frame_stack.push(Frame::new(scope, false));
+        let res = (function)(self);
+        let frame = self.thread.frame_stack.pop().unwrap();
+        self.drop_token(frame.top_scope.vars);
+        res
+    }
+
+    /// Records what runs in the innermost frame, for tracebacks.
+    pub(crate) fn set_frame_function(&mut self, name: Option<Rc<str>>, num_args: usize) {
+        if let Some(frame) = self.thread.frame_stack.last_mut() {
+            frame.function = name;
+            frame.num_args = num_args;
+        }
+    }
+
+    /// Makes `bytecode` the code the innermost frame runs, returning the code and position it
+    /// ran before so they can be restored.
+    pub(crate) fn swap_frame_code(
+        &mut self,
+        bytecode: Option<Rc<Bytecode>>,
+        pc: Option<usize>,
+    ) -> (Option<Rc<Bytecode>>, Option<usize>) {
+        match self.thread.frame_stack.last_mut() {
+            Some(frame) => (
+                mem::replace(&mut frame.bytecode, bytecode),
+                mem::replace(&mut frame.pc, pc),
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Gives the code about to run in the innermost frame `count` empty local slots. Returns
+    /// where the slots of the code it interrupts start, for `pop_frame_locals`.
+    pub(crate) fn push_frame_locals(&mut self, count: usize) -> usize {
+        let base = self.thread.locals.len();
+        match self.thread.frame_stack.last_mut() {
+            Some(frame) => {
+                self.thread.locals.resize_with(base + count, || None);
+                mem::replace(&mut frame.locals_base, base)
+            }
+            None => 0,
+        }
+    }
+
+    /// Drops the local slots of the code that ran in the innermost frame.
+    pub(crate) fn pop_frame_locals(&mut self, outer_base: usize) {
+        let base = match self.thread.frame_stack.last_mut() {
+            Some(frame) => mem::replace(&mut frame.locals_base, outer_base),
+            None => return,
+        };
+        while self.thread.locals.len() > base {
+            if let Some(token) = self.thread.locals.pop().unwrap() {
+                self.drop_token(token);
+            }
+        }
+    }
+
+    /// Attaches the active frames to `err`, unless it already has a traceback from where it
+    /// happened.
+    pub(crate) fn capture_traceback(&self, mut err: TriconeError) -> TriconeError {
+        if err.traceback.is_empty() {
+            err.traceback = self.backtrace();
+        }
+        err
+    }
+
+    /// The active frames, outermost first.
+    pub fn backtrace(&self) -> Vec<TracebackEntry> {
+        self.thread
+            .frame_stack
+            .iter()
+            .map(Frame::traceback_entry)
+            .collect()
+    }
+
+    /// The scopes of frame `frame` (0 is the outermost), innermost first, following their
+    /// `!parent` members. Give them back with `drop_token(scope.vars)`.
+    ///
+    /// The local slots of the code the frame runs come first, as a scope of their own, unless
+    /// they are empty and the frame already has a scope for its locals.
+    pub fn frame_scopes(&self, frame: usize) -> Vec<Scope> {
+        let mut scopes = vec![];
+        let frame = match self.thread.frame_stack.get(frame) {
+            Some(frame) => frame,
+            None => return scopes,
+        };
+        let names = frame.bytecode.as_ref().map_or(&[][..], |code| &code.locals);
+        if frame.shares_scope || !names.is_empty() {
+            let locals = Scope::new();
+            for (&name, slot) in names.iter().zip(&self.thread.locals[frame.locals_base..]) {
+                if let Some(ref value) = *slot {
+                    locals.vars.obj_mut().members.insert(name, value.dup());
+                }
+            }
+            scopes.push(locals);
+        }
+        let mut scope = Some(frame.top_scope.dup());
+        while let Some(current) = scope {
+            scope = current.parent();
+            scopes.push(current);
+        }
+        scopes
+    }
+
+    /// The bytecode frame `frame` is running, if it is running any.
+    pub fn frame_bytecode(&self, frame: usize) -> Option<Rc<Bytecode>> {
+        self.thread
+            .frame_stack
+            .get(frame)
+            .and_then(|frame| frame.bytecode.clone())
+    }
+
+    pub fn operation_stack(&self) -> &[ObjectToken] {
+        &self.thread.operation_stack
+    }
+
+    /// Calls `handler` whenever a breakpoint is hit or a step finishes; `None` stops debugging.
+    pub fn set_debug_handler(&mut self, handler: Option<Box<dyn DebugHandler>>) {
+        self.debug.handler = handler;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints().any(|other| *other == breakpoint) {
+            let name = breakpoint.qualified_function();
+            self.debug.breakpoints.push((breakpoint, name));
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let before = self.debug.breakpoints.len();
+        self.debug
+            .breakpoints
+            .retain(|(other, _)| other != breakpoint);
+        self.debug.breakpoints.len() != before
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.debug
+            .breakpoints
+            .iter()
+            .map(|(breakpoint, _)| breakpoint)
+    }
+
+    pub fn add_source_breakpoint(&mut self, breakpoint: SourceBreakpoint) {
+        if !self.debug.source_breakpoints.contains(&breakpoint) {
+            self.debug.source_breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes all source breakpoints in `file`.
+    pub fn clear_source_breakpoints(&mut self, file: &str) {
+        self.debug
+            .source_breakpoints
+            .retain(|breakpoint| breakpoint.file != file);
+    }
+
+    pub fn source_breakpoints(&self) -> &[SourceBreakpoint] {
+        &self.debug.source_breakpoints
+    }
+
+    /// The instructions of the registered functions and methods where `breakpoint` would pause.
+    pub fn resolve_source_breakpoint(&self, breakpoint: &SourceBreakpoint) -> Vec<Breakpoint> {
+        let resolve = |function: &Function| -> Option<Breakpoint> {
+            let debug_info = match *function.code() {
+                Code::Bytecode(ref bytecode) => bytecode.debug_info.as_ref()?,
+                Code::Native(_) => return None,
+            };
+            if debug_info.file != breakpoint.file {
+                return None;
+            }
+            let instruction = debug_info.line_start(breakpoint.line)?;
+            let name = function.name()?;
+            let sep = name.find("::")?;
+            Some(Breakpoint::new(&name[..sep], &name[sep + 2..], instruction))
+        };
+
+        let mut resolved = vec![];
+        for module in &self.modules {
+            for ty in module.types.iter().filter(|ty| !ty.removed) {
+                resolved.extend(ty.methods.values().filter_map(resolve));
+            }
+            for member in module.globals.obj().members.values() {
+                let obj = member.obj();
+                if obj.type_ == consts::FUNCTION_TYPE_ID {
+                    resolved.extend(resolve(function::function_from_function_object(&obj)));
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Setting the flag makes the running code pause at its next instruction, if a debug handler
+    /// is set. It may be set from any thread.
+    pub fn debug_pause_handle(&self) -> Arc<AtomicBool> {
+        self.debug.pause_requested.clone()
+    }
+
+    /// Sets when to pause next, as if a pause at the current frame returned `resume`. Stepping
+    /// into before running code pauses at its first instruction.
+    pub fn debug_resume(&mut self, resume: Resume) {
+        self.debug.resume = Some((resume, self.thread.frame_stack.len()));
+    }
+
+    fn pause_reason(&self, pos: usize, depth: usize) -> Option<PauseReason> {
+        let frame = self.thread.frame_stack.last();
+        let function = frame.and_then(|frame| frame.function.as_deref());
+        let hit = self.debug.breakpoints.iter().find(|(breakpoint, name)| {
+            breakpoint.instruction == pos && Some(name.as_str()) == function
+        });
+        if let Some((breakpoint, _)) = hit {
+            return Some(PauseReason::Breakpoint(breakpoint.clone()));
+        }
+
+        if !self.debug.source_breakpoints.is_empty() {
+            let debug_info = frame
+                .and_then(|frame| frame.bytecode.as_ref())
+                .and_then(|bytecode| bytecode.debug_info.as_ref());
+            if let Some(debug_info) = debug_info {
+                let line = debug_info.starts_line(pos);
+                let hit = self.debug.source_breakpoints.iter().find(|breakpoint| {
+                    Some(breakpoint.line) == line && breakpoint.file == debug_info.file
+                });
+                if let Some(breakpoint) = hit {
+                    return Some(PauseReason::SourceBreakpoint(breakpoint.clone()));
+                }
+            }
+        }
+
+        if self.debug.pause_requested.swap(false, Ordering::SeqCst) {
+            return Some(PauseReason::Requested);
+        }
+        match self.debug.resume {
+            Some((resume, from)) if resume.pauses_at(depth, from) => Some(PauseReason::Step),
+            _ => None,
+        }
+    }
+
+    fn debug_hook(&mut self, insn: &Instruction, pos: usize) -> Result<(), TriconeError> {
+        let depth = self.thread.frame_stack.len();
+        let reason = match self.pause_reason(pos, depth) {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+        let mut handler = match self.debug.handler.take() {
+            Some(handler) => handler,
+            None => return Ok(()),
+        };
+        let pause = Pause {
+            reason,
+            instruction: insn,
+            pos,
+        };
+        let resume = handler.paused(self, &pause);
+        // The handler may have replaced itself
+        if self.debug.handler.is_none() {
+            self.debug.handler = Some(handler);
+        }
+        self.debug.resume = Some((resume, depth));
+        if resume == Resume::Abort {
+            return Err(TriconeError::new(ErrorKind::Aborted));
+        }
+        Ok(())
+    }
+
+    fn with_current_frame<F, O>(&mut self, function: F) -> O
+    where
+        F: FnOnce(&mut Interpreter, &mut Frame) -> O,
+    {
+        // Ugly :(
+        let mut frame = self.thread.frame_stack.pop().unwrap();
+        let res = (function)(self, &mut frame);
+        self.thread.frame_stack.push(frame);
+        res
+    }
+
+    pub fn with_new_scope<F, O>(&mut self, function: F) -> O
+    where
+        F: FnOnce(&mut Interpreter) -> O,
+    {
+        let shares_scope = self.with_current_frame(|i, f| {
+            f.push_scope(i);
+            mem::replace(&mut f.shares_scope, false)
+        });
+        let res = (function)(self);
+        self.with_current_frame(|i, f| {
+            f.pop_scope(i);
+            f.shares_scope = shares_scope;
+        });
+        res
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn fuel_costs(&self) -> &FuelCosts {
+        &self.fuel_costs
+    }
+
+    pub fn set_fuel_costs(&mut self, costs: FuelCosts) {
+        self.fuel_costs = costs;
+    }
+
+    /// Runs `function` with at most `fuel` units of fuel available, returning its result and
+    /// the fuel left over. Nested budgets never exceed the enclosing one, and whatever the
+    /// inner run consumed is charged to the enclosing budget as well.
+    pub fn with_fuel<F, O>(&mut self, fuel: u64, function: F) -> (O, u64)
+    where
+        F: FnOnce(&mut Interpreter) -> O,
+    {
+        let outer = self.fuel;
+        let budget = outer.map_or(fuel, |outer| outer.min(fuel));
+        self.fuel = Some(budget);
+        let res = (function)(self);
+        let remaining = self.fuel.unwrap_or(0);
+        self.fuel = outer.map(|outer| outer - (budget - remaining));
+        (res, remaining)
+    }
+
+    /// Profiles everything run from now on, until `stop_profiling`.
+    pub fn start_profiling(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.profiler.take().map(Profiler::finish)
+    }
+
+    pub(crate) fn profile_enter(&mut self, function: Option<&Rc<str>>) {
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.enter(function);
+        }
+    }
+
+    pub(crate) fn profile_exit(&mut self) {
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.exit();
+        }
+    }
+
+    /// Charges `amount` to the active fuel budget, if any. Native functions doing work
+    /// proportional to their input should call this so they can be metered too.
+    pub fn consume_fuel(&mut self, amount: u64) -> Result<(), TriconeError> {
+        if let Some(ref mut fuel) = self.fuel {
+            if *fuel < amount {
+                *fuel = 0;
+                return Err(TriconeError::new(ErrorKind::OutOfFuel));
+            }
+            *fuel -= amount;
+        }
+        Ok(())
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory
+    }
+
+    /// Charges the names among `names` that no code registered before used, failing if that
+    /// would exceed the memory limit.
+    pub(crate) fn charge_names<I>(&mut self, names: I) -> Result<(), TriconeError>
+    where
+        I: IntoIterator<Item = Symbol>,
+    {
+        let mut new: Vec<_> = names
+            .into_iter()
+            .filter(|name| !self.names.contains(name))
+            .collect();
+        new.sort();
+        new.dedup();
+        let bytes = new.iter().map(|name| name.len()).sum();
+        self.reserve_memory(bytes)?;
+        self.memory.name_bytes += bytes;
+        self.names.extend(new);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let types = self
+            .modules
+            .iter()
+            .flat_map(|module| &module.types)
+            .filter(|ty| ty.created > 0 || ty.dropped > 0)
+            .map(|ty| TypeStats {
+                index: ty.index,
+                name: ty.qualified_name.clone(),
+                created: ty.created,
+                dropped: ty.dropped,
+            })
+            .collect();
+        Stats::new(&self.stats, types)
+    }
+
+    /// Zeroes the counters behind `stats`.
+    pub fn reset_stats(&mut self) {
+        self.stats = Counters::default();
+        for ty in self.modules.iter_mut().flat_map(|module| &mut module.types) {
+            ty.created = 0;
+            ty.dropped = 0;
+        }
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// Caps the approximate bytes live objects may hold. Creating objects, adding members or
+    /// charging native payloads past the cap fails with `ErrorKind::MemoryError`.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    fn reserve_memory(&mut self, bytes: usize) -> Result<(), TriconeError> {
+        match self.memory_limit {
+            Some(limit) if self.memory.total_bytes() + bytes > limit => {
+                Err(TriconeError::new(ErrorKind::MemoryError))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Charges `bytes` of native payload to `token`, failing if that would exceed the memory
+    /// limit. The bytes are released when the object is destroyed.
+    pub fn charge_memory(&mut self, token: &ObjectToken, bytes: usize) -> Result<(), TriconeError> {
+        self.reserve_memory(bytes)?;
+        if let Some(ref mut footprint) = token.obj_mut().footprint {
+            *footprint += bytes;
+            self.memory.live_bytes += bytes;
+        }
+        Ok(())
+    }
+
+    pub fn create_object(
+        &mut self,
+        tyidx: TypeIndex,
+        num_args: usize,
+    ) -> Result<ObjectToken, TriconeError> {
+        if let Some(value) = Immediate::default_for(tyidx) {
+            if num_args != 0 {
+                return Err(TriconeError::new(ErrorKind::WrongArgumentCount));
+            }
+            return Ok(value.into());
+        }
+        let cost = self.fuel_costs.object_creation;
+        self.consume_fuel(cost)?;
+        self.reserve_memory(memory::OBJECT_SIZE)?;
+
+        self.memory.live_objects += 1;
+        self.memory.live_bytes += memory::OBJECT_SIZE;
+        {
+            let ty = self.get_type_mut(tyidx);
+            ty.live_instances += 1;
+            ty.created += 1;
+        }
+        let obj = ObjectToken::new(Object {
+            footprint: Some(memory::OBJECT_SIZE),
+            ..Object::raw_new(tyidx)
         });
 
-        if let Some(create) = self.get_type(tyidx).get_method(consts::CREATE_METHOD_NAME) {
+        if let Some(create) = self
+            .get_type(tyidx)
+            .get_method(sym!(consts::CREATE_METHOD_NAME))
+        {
             let mut args = Vec::with_capacity(num_args);
             let op_stack_len = self.thread.operation_stack.len();
             args.push(obj.dup());
@@ -572,18 +2319,26 @@ impl Interpreter {
                     .operation_stack
                     .drain((op_stack_len - num_args)..op_stack_len),
             );
-            let res = self.call_function_with_owned_args(create, args);
-            self.drop_unit(res);
+            match self.call_function_with_owned_args(create, args) {
+                Ok(res) => self.drop_unit(res),
+                Err(err) => {
+                    // The object was never initialized, so it must not see its drop method
+                    self.free_token(obj);
+                    return Err(err);
+                }
+            }
+        }
+
+        let payload_size = obj.obj().data.capacity();
+        if let Err(err) = self.charge_memory(&obj, payload_size) {
+            self.drop_token(obj);
+            return Err(err);
         }
 
-        obj
+        Ok(obj)
     }
 
-    fn call_function_with_owned_args<Args>(
-        &mut self,
-        func: Function,
-        args: Args,
-    ) -> Option<ObjectToken>
+    fn call_function_with_owned_args<Args>(&mut self, func: Function, args: Args) -> NativeResult
     where
         Args: IntoIterator<Item = ObjectToken> + AsRef<[ObjectToken]>,
     {
@@ -592,73 +2347,347 @@ impl Interpreter {
             self.drop_token(arg);
         }
         self.drop_token(func.closure.vars);
-        res.unwrap()
+        res
     }
 
     fn drop_unit(&mut self, unit: Option<ObjectToken>) {
         if let Some(obj) = unit {
-            assert_eq!(consts::UNIT_TYPE_ID, obj.obj().type_);
+            assert_eq!(consts::UNIT_TYPE_ID, obj.type_index());
             self.drop_token(obj);
         }
     }
 
-    fn maybe_call_no_args_no_ret_method(&mut self, token: &ObjectToken, name: &str) {
-        let tyidx = token.obj().type_;
+    fn maybe_call_no_args_no_ret_method(&mut self, token: &ObjectToken, name: Symbol) {
+        let TypeIndex(ModuleIndex(modidx), tyidx) = token.type_index();
+        // Modules are already gone at the very end of the interpreter's own drop
+        let method = self
+            .modules
+            .get(modidx)
+            .and_then(|module| module.types[tyidx].get_method(name));
 
-        if let Some(method) = self.get_type(tyidx).get_method(name) {
+        if let Some(method) = method {
             let args = ArrayVec::from([token.dup()]);
-            let res = method.call(self, &args).unwrap();
+            // Native destructors release host resources and must always run, so they are not
+            // metered. Bytecode ones are, and running out of fuel just cuts them short.
+            let outer_fuel = if method.is_native() {
+                self.fuel.take()
+            } else {
+                self.fuel
+            };
+            let res = method.call(self, &args);
+            if method.is_native() {
+                self.fuel = outer_fuel;
+            }
             for arg in args {
                 self.drop_token(arg);
             }
-            if let Some(obj) = res {
-                assert_eq!(consts::UNIT_TYPE_ID, obj.obj().type_);
-                self.drop_token(obj);
+            match res {
+                Ok(Some(obj)) => {
+                    assert_eq!(consts::UNIT_TYPE_ID, obj.type_index());
+                    self.drop_token(obj);
+                }
+                Ok(None) => {}
+                // Nothing is left to hand the error to, so it is only reported
+                Err(err) => {
+                    let _ = writeln!(
+                        self.stderr,
+                        "error in {}: {}",
+                        method.name().unwrap_or(name.as_str()),
+                        err
+                    );
+                    for frame in &err.traceback {
+                        let _ = writeln!(self.stderr, "  {}", frame);
+                    }
+                }
             }
             self.drop_token(method.closure.vars);
         }
     }
 
-    pub fn get_unit_object(&mut self) -> ObjectToken {
-        self.create_object(consts::UNIT_TYPE_ID, 0)
+    pub fn get_unit_object(&mut self) -> Result<ObjectToken, TriconeError> {
+        Ok(Immediate::Unit.into())
     }
 
-    fn get_method(&self, obj: &Object, name: &str) -> Option<Function> {
-        self.get_type(obj.type_).get_method(name)
+    fn get_method(&self, obj: &ObjectToken, name: Symbol) -> Option<Function> {
+        self.get_type(obj.type_index()).get_method(name)
     }
 
-    fn call_method(&mut self, name: &str, args: &[ObjectToken]) -> Option<ObjectToken> {
-        assert!(args.len() >= 1);
-        let target = args.last().unwrap();
-        let method = self
-            .get_method(&target.obj(), name)
-            .expect("Called nonexistent method. TODO: runtime error");
-        let res = method.call(self, args).unwrap();
+    fn find_method(&self, target: &ObjectToken, name: Symbol) -> Result<Function, TriconeError> {
+        self.get_method(target, name).ok_or_else(|| {
+            let type_name = self.get_type(target.type_index()).name();
+            TriconeError::with_message(
+                ErrorKind::NameError,
+                format!("no method `{}` on `{}`", name, type_name),
+            )
+        })
+    }
+
+    fn call_method(&mut self, name: Symbol, args: &[ObjectToken]) -> NativeResult {
+        let method = self.find_method(&args[0], name)?;
+        let res = method.call(self, args);
         self.drop_token(method.closure.vars);
         res
     }
 
-    pub fn create_scope(&mut self) -> Scope {
-        Scope {
-            vars: self.create_object(consts::SCOPE_TYPE_ID, 0),
+    fn call_method_cached(
+        &mut self,
+        name: Symbol,
+        args: &[ObjectToken],
+        cache: &RefCell<InlineCache>,
+    ) -> NativeResult {
+        let type_ = args[0].type_index();
+        let methods_version = self.get_type(type_).methods_version;
+        let cached = match *cache.borrow() {
+            InlineCache::Method {
+                type_: cached_type,
+                methods_version: cached_version,
+                ref method,
+            } if cached_type == type_ && cached_version == methods_version => method.upgrade(),
+            _ => None,
+        };
+        let method = match cached {
+            Some(method) => {
+                self.stats.inline_cache_hits += 1;
+                method
+            }
+            None => {
+                self.stats.inline_cache_misses += 1;
+                let method = self.find_method(&args[0], name)?;
+                cache.replace(InlineCache::Method {
+                    type_,
+                    methods_version,
+                    method: method.downgrade(),
+                });
+                method
+            }
+        };
+        let res = method.call(self, args);
+        self.drop_token(method.closure.vars);
+        res
+    }
+
+    fn get_member_cached(
+        &mut self,
+        item: &ObjectToken,
+        name: Symbol,
+        cache: &RefCell<InlineCache>,
+    ) -> Option<ObjectToken> {
+        let members_version = match *item {
+            ObjectToken::Heap(ref obj) => obj.borrow().members_version,
+            ObjectToken::Immediate(_) => return None,
+        };
+        if let InlineCache::Member {
+            ref object,
+            members_version: cached_version,
+            ref value,
+        } = *cache.borrow()
+        {
+            if cached_version == members_version && object.refers_to(item) {
+                if let Some(value) = value.upgrade() {
+                    self.stats.inline_cache_hits += 1;
+                    return Some(value);
+                }
+            }
+        }
+        self.stats.inline_cache_misses += 1;
+        let value = item.get_member(name)?;
+        cache.replace(InlineCache::Member {
+            object: item.downgrade(),
+            members_version,
+            value: value.downgrade(),
+        });
+        Some(value)
+    }
+
+    // Gives the innermost frame a scope of its own, if it still shares its function's closure
+    fn own_frame_scope(&mut self) {
+        let shares_scope = self
+            .thread
+            .frame_stack
+            .last()
+            .is_some_and(|frame| frame.shares_scope);
+        if shares_scope {
+            self.with_current_frame(|i, f| {
+                f.push_scope(i);
+                f.shares_scope = false;
+            });
         }
     }
 
-    pub fn run_code(&mut self, instructions: &[Instruction]) -> Option<ObjectToken> {
+    fn local_slot(&mut self, slot: usize) -> Result<&mut Option<ObjectToken>, TriconeError> {
+        let base = self
+            .thread
+            .frame_stack
+            .last()
+            .expect("Must have a frame")
+            .locals_base;
+        self.thread.locals.get_mut(base + slot).ok_or_else(|| {
+            TriconeError::with_message(ErrorKind::NameError, format!("no local slot {}", slot))
+        })
+    }
+
+    fn local_name(&self, slot: usize) -> Option<Symbol> {
+        let frame = self.thread.frame_stack.last()?;
+        frame.bytecode.as_ref()?.locals.get(slot).copied()
+    }
+
+    fn lookup_name_cached(
+        &mut self,
+        name: Symbol,
+        cache: &RefCell<InlineCache>,
+        misses: &mut u64,
+    ) -> Option<ObjectToken> {
+        let hit = {
+            let scope = &self
+                .thread
+                .frame_stack
+                .last()
+                .expect("Must have a frame")
+                .vars;
+            match *cache.borrow() {
+                InlineCache::Name {
+                    scope: ref cached_scope,
+                    epoch,
+                    ref holder,
+                    depth,
+                } if epoch == self.names_epoch && cached_scope.refers_to(scope) => {
+                    holder.upgrade().map(|holder| (holder, depth))
+                }
+                _ => None,
+            }
+        };
+        if let Some((holder, depth)) = hit {
+            // The scope chain keeps the holder alive, this is never the last reference
+            let value = holder.get_member(name);
+            self.drop_token(holder);
+            if value.is_some() {
+                self.stats.inline_cache_hits += 1;
+                *misses += depth as u64;
+                return value;
+            }
+        }
+
+        self.stats.inline_cache_misses += 1;
+        let before = *misses;
+        let scope = &self
+            .thread
+            .frame_stack
+            .last()
+            .expect("Must have a frame")
+            .vars;
+        let (value, holder) = Scope::token_resolve_name(scope, name, misses)?;
+        cache.replace(InlineCache::Name {
+            scope: scope.downgrade(),
+            epoch: self.names_epoch,
+            holder,
+            depth: (*misses - before) as usize,
+        });
+        Some(value)
+    }
+
+    /// Formats an object with its `tostring` method, or as `<Type>` if it has none.
+    pub fn display_object(&mut self, obj: &ObjectToken) -> Result<String, TriconeError> {
+        let method = self.get_method(obj, sym!("tostring"));
+        let method = match method {
+            Some(method) => method,
+            None => return Ok(format!("<{}>", self.get_type(obj.type_index()).name())),
+        };
+        let res = self.call_function_with_owned_args(method, vec![obj.dup()])?;
+        let res = res.ok_or_else(|| {
+            TriconeError::with_message(ErrorKind::TypeError, "tostring returned nothing")
+        })?;
+        let string = string::string_value(&res).map(|value| value.to_owned());
+        self.drop_token(res);
+        string.ok_or_else(|| {
+            TriconeError::with_message(ErrorKind::TypeError, "tostring must return a String")
+        })
+    }
+
+    pub fn create_scope(&mut self) -> Result<Scope, TriconeError> {
+        Ok(Scope {
+            vars: self.create_object(consts::SCOPE_TYPE_ID, 0)?,
+        })
+    }
+
+    pub fn run_code(&mut self, instructions: &[Instruction]) -> NativeResult {
+        self.run_code_with_debug_info(instructions, None)
+    }
+
+    /// Like `run_code`, but errors are tagged with the location `debug_info` gives for the
+    /// failing instruction, unless code called from it already did.
+    pub fn run_code_with_debug_info(
+        &mut self,
+        instructions: &[Instruction],
+        debug_info: Option<&DebugInfo>,
+    ) -> NativeResult {
+        self.run_code_with_caches(instructions, debug_info, None)
+    }
+
+    pub(crate) fn run_bytecode(&mut self, bytecode: &Bytecode) -> NativeResult {
+        self.run_code_with_caches(
+            &bytecode.instructions,
+            bytecode.debug_info.as_ref(),
+            Some(&bytecode.caches),
+        )
+    }
+
+    fn run_code_with_caches(
+        &mut self,
+        instructions: &[Instruction],
+        debug_info: Option<&DebugInfo>,
+        caches: Option<&InlineCaches>,
+    ) -> NativeResult {
+        let stack_base = self.thread.operation_stack.len();
         let mut prev = None;
         let num_instructions = instructions.len();
         let mut pos = 0;
 
         while pos < num_instructions {
             let insn = unsafe { instructions.get_unchecked(pos) };
-            if let Instruction::Jump { to } = insn {
-                pos = *to;
+            if let Some(frame) = self.thread.frame_stack.last_mut() {
+                frame.pc = Some(pos);
+            }
+            let kind = insn.kind();
+            self.stats.instructions[kind as usize] += 1;
+            if let Some(ref mut profiler) = self.profiler {
+                profiler.instruction(kind);
+            }
+            let cost = self.fuel_costs.instruction_cost(kind);
+            let ready = if self.debug.handler.is_some() {
+                self.debug_hook(insn, pos)
             } else {
+                Ok(())
+            };
+            if let Err(err) = ready.and_then(|()| self.consume_fuel(cost)) {
                 if let Some(res) = prev {
-                    self.thread.operation_stack.push(res)
+                    self.drop_token(res);
+                }
+                return Err(self.fail(stack_base, err, debug_info, pos));
+            }
+
+            match *insn {
+                Instruction::Jump { to } => pos = to,
+                Instruction::JumpIfFalse { to } => {
+                    if let Some(res) = prev.take() {
+                        self.thread.operation_stack.push(res)
+                    }
+                    match self.pop_condition() {
+                        Ok(true) => pos += 1,
+                        Ok(false) => pos = to,
+                        Err(err) => return Err(self.fail(stack_base, err, debug_info, pos)),
+                    }
+                }
+                _ => {
+                    if let Some(res) = prev {
+                        self.thread.operation_stack.push(res)
+                    }
+                    let cache = caches.and_then(|caches| caches.get(pos));
+                    prev = match self.execute(insn, cache) {
+                        Ok(res) => res,
+                        Err(err) => return Err(self.fail(stack_base, err, debug_info, pos)),
+                    };
+                    pos += 1;
                 }
-                prev = self.run_instruction(insn);
-                pos += 1;
             }
         }
         // for insn in instructions.iter() {
@@ -667,27 +2696,116 @@ impl Interpreter {
         //     }
         //     prev = self.run_instruction(insn);
         // }
-        prev
+        Ok(prev)
+    }
+
+    /// Drops whatever a failing piece of code left on the operation stack above `height`, and
+    /// locates the error at instruction `pos` if nothing deeper did.
+    fn fail(
+        &mut self,
+        height: usize,
+        mut err: TriconeError,
+        debug_info: Option<&DebugInfo>,
+        pos: usize,
+    ) -> TriconeError {
+        self.truncate_operation_stack(height);
+        if err.location.is_none() {
+            err.location = debug_info.and_then(|debug_info| debug_info.location(pos));
+        }
+        self.capture_traceback(err)
     }
 
     pub fn drop_token(&mut self, token: ObjectToken) {
-        if Rc::strong_count(&token.0) == 1 {
-            if token.obj().type_ != consts::UNIT_TYPE_ID {
-                self.maybe_call_no_args_no_ret_method(&token, consts::DROP_METHOD_NAME);
-                assert_eq!(Rc::strong_count(&token.0), 1);
+        let last = match token {
+            ObjectToken::Heap(ref obj) => Rc::strong_count(obj) == 1,
+            ObjectToken::Immediate(_) => return,
+        };
+        if last && token.type_index() != consts::UNIT_TYPE_ID {
+            self.maybe_call_no_args_no_ret_method(&token, sym!(consts::DROP_METHOD_NAME));
+            if let ObjectToken::Heap(ref obj) = token {
+                assert_eq!(Rc::strong_count(obj), 1);
             }
+        }
+        self.free_token(token);
+    }
 
-            let mut object = Rc::try_unwrap(token.into_rc()).unwrap().into_inner();
+    /// Releases a token without running the drop method of the object it refers to.
+    fn free_token(&mut self, token: ObjectToken) {
+        let obj = match token.into_rc() {
+            Some(obj) => obj,
+            None => return,
+        };
+        // Anything else will drop normally
+        if let Ok(object) = Rc::try_unwrap(obj) {
+            let mut object = object.into_inner();
+
+            if let Some(footprint) = object.footprint {
+                self.memory.live_objects -= 1;
+                self.memory.live_bytes -= footprint;
+                // Modules are already gone while the interpreter itself is being dropped
+                let TypeIndex(ModuleIndex(modidx), tyidx) = object.type_;
+                if let Some(module) = self.modules.get_mut(modidx) {
+                    let ty = &mut module.types[tyidx];
+                    ty.live_instances -= 1;
+                    ty.dropped += 1;
+                }
+            }
 
             for (_, obj) in object.members.drain() {
                 self.drop_token(obj);
             }
+        }
+    }
+
+    fn check_operands(&self, needed: usize) -> Result<(), TriconeError> {
+        let found = self.thread.operation_stack.len();
+        if found < needed {
+            return Err(TriconeError::with_message(
+                ErrorKind::StackUnderflow,
+                format!("needs {} operands, found {}", needed, found),
+            ));
+        }
+        Ok(())
+    }
+
+    fn pop_operand(&mut self) -> Result<ObjectToken, TriconeError> {
+        self.check_operands(1)?;
+        Ok(self.thread.operation_stack.pop().unwrap())
+    }
+
+    fn pop_condition(&mut self) -> Result<bool, TriconeError> {
+        let cond = self.pop_operand()?;
+        let value = if let Some(Immediate::Bool(value)) = cond.immediate() {
+            Ok(value)
         } else {
-            // will drop normally
-            token.into_rc();
+            Err(TriconeError::with_message(
+                ErrorKind::TypeError,
+                format!(
+                    "condition must be a `Bool`, not `{}`",
+                    self.get_type(cond.type_index()).name()
+                ),
+            ))
+        };
+        self.drop_token(cond);
+        value
+    }
+
+    /// Drops whatever is on the operation stack above `height`.
+    pub(crate) fn truncate_operation_stack(&mut self, height: usize) {
+        while self.thread.operation_stack.len() > height {
+            let token = self.thread.operation_stack.pop().unwrap();
+            self.drop_token(token);
         }
     }
 
+    pub(crate) fn operation_stack_height(&self) -> usize {
+        self.thread.operation_stack.len()
+    }
+
+    pub(crate) fn push_operand(&mut self, obj: ObjectToken) {
+        self.thread.operation_stack.push(obj);
+    }
+
     fn get_args_from_stack<O>(&mut self, num_args: usize, container: &mut O)
     where
         O: Extend<ObjectToken>,
@@ -700,128 +2818,211 @@ impl Interpreter {
         )
     }
 
-    pub fn run_instruction(&mut self, insn: &Instruction) -> Option<ObjectToken> {
-        println!("running {:?}", insn);
+    fn finish_call(&mut self, res: Option<ObjectToken>, use_result: bool) -> NativeResult {
+        if use_result {
+            match res {
+                Some(obj) => Ok(Some(obj)),
+                None => self.get_unit_object().map(Some),
+            }
+        } else {
+            if let Some(obj) = res {
+                self.drop_token(obj);
+            }
+            Ok(None)
+        }
+    }
+
+    pub fn run_instruction(&mut self, insn: &Instruction) -> NativeResult {
+        self.execute(insn, None)
+    }
+
+    /// Runs `insn` with `cache` as its inline cache, if it has one.
+    fn execute(
+        &mut self,
+        insn: &Instruction,
+        cache: Option<&RefCell<InlineCache>>,
+    ) -> NativeResult {
+        if self.trace {
+            let _ = writeln!(self.stdout, "running {:?}", insn);
+        }
 
         use self::Instruction::*;
         match *insn {
-            Jump { to: _ } => unreachable!(),
+            Jump { .. } | JumpIfFalse { .. } => unreachable!(),
             CreateObject {
-                type_spec: (ref module, ref type_),
+                type_spec: (module, type_),
                 num_args,
             } => {
-                let mod_idx = self.lookup_module_index(&module).unwrap();
-                let ty_idx = self.lookup_type(mod_idx, &type_).unwrap();
-                Some(self.create_object(ty_idx, num_args))
+                self.check_operands(num_args)?;
+                let mod_idx = self.resolve_module(module)?;
+                let ty_idx = self
+                    .get_module(mod_idx)
+                    .lookup_type_index(type_)
+                    .map(|idx| TypeIndex(mod_idx, idx))
+                    .ok_or_else(|| {
+                        TriconeError::with_message(
+                            ErrorKind::NameError,
+                            format!("module `{}` has no type `{}`", module, type_),
+                        )
+                    })?;
+                self.create_object(ty_idx, num_args).map(Some)
             }
-            Assign { ref name } => {
-                let mut scope = self
+            Assign { name } => {
+                self.own_frame_scope();
+                let scope = self
                     .thread
                     .frame_stack
                     .last()
                     .expect("Must have at least one scope")
                     .vars
                     .dup();
-                let item = self
-                    .thread
-                    .operation_stack
-                    .pop()
-                    .expect("Stack needs 2 items, only 1 found");
-                scope.assign_member(name.clone(), item, self);
+                let item = match self.pop_operand() {
+                    Ok(item) => item,
+                    Err(err) => {
+                        self.drop_token(scope);
+                        return Err(err);
+                    }
+                };
+                let res = scope.assign_member(name, item, self);
                 self.drop_token(scope);
-                None
+                res.map(|()| None)
             }
-            GetTopScope => Some(
-                self.thread
-                    .frame_stack
-                    .last()
-                    .expect("Must have at least one scope")
-                    .vars
-                    .dup(),
-            ),
-            GetModuleGlobals { ref name } => {
-                let idx = self
-                    .lookup_module_index(name)
-                    .expect("Module does not exist! TODO: runtime error");
-                Some(self.get_module(idx).globals.vars.dup())
+            GetTopScope => {
+                self.own_frame_scope();
+                Ok(Some(
+                    self.thread
+                        .frame_stack
+                        .last()
+                        .expect("Must have at least one scope")
+                        .vars
+                        .dup(),
+                ))
+            }
+            GetModuleGlobals { name } => {
+                let idx = self.resolve_module(name)?;
+                self.visible_globals(idx).map(Some)
+            }
+            CreateObjectResolved { type_, num_args } => {
+                self.check_operands(num_args)?;
+                self.check_module_index_access(type_.0)?;
+                // A reload may have removed the type since
+                if self.get_type(type_).removed {
+                    return Err(TriconeError::new(ErrorKind::NameError));
+                }
+                self.create_object(type_, num_args).map(Some)
+            }
+            GetModuleGlobalsResolved { module } => {
+                self.check_module_index_access(module)?;
+                self.visible_globals(module).map(Some)
+            }
+            LoadLocal { slot } => {
+                if let Some(ref value) = *self.local_slot(slot)? {
+                    return Ok(Some(value.dup()));
+                }
+                // Not stored to yet, but the name may be defined further out
+                match self.local_name(slot) {
+                    Some(name) => self.execute(&LookupName { name }, None),
+                    None => Err(TriconeError::with_message(
+                        ErrorKind::NameError,
+                        format!("local slot {} is not set", slot),
+                    )),
+                }
+            }
+            StoreLocal { slot } => {
+                self.local_slot(slot)?;
+                let item = self.pop_operand()?;
+                if let Some(old) = self.local_slot(slot)?.replace(item) {
+                    self.drop_token(old);
+                }
+                Ok(None)
+            }
+            Import { name } => {
+                // Check first, so denied code can't make the loader do any work either
+                self.check_module_access(name.as_str())?;
+                let idx = self.import_module(name.as_str())?;
+                self.visible_globals(idx).map(Some)
             }
             CallMethod {
-                ref name,
+                name,
                 mut num_args,
                 use_result,
             } => {
                 num_args += 1;
 
-                if self.thread.operation_stack.len() < num_args {
-                    panic!("Not enough arguments passed! TODO: runtime error");
-                }
-
+                self.check_operands(num_args)?;
                 let mut args = Vec::with_capacity(num_args);
                 self.get_args_from_stack(num_args, &mut args);
-                let res = self.call_method(name, &args);
+                let res = match cache {
+                    Some(cache) => self.call_method_cached(name, &args, cache),
+                    None => self.call_method(name, &args),
+                };
                 for arg in args {
                     self.drop_token(arg);
                 }
-                if use_result {
-                    Some(res.unwrap_or_else(|| self.get_unit_object()))
-                } else {
-                    if let Some(obj) = res {
-                        self.drop_token(obj);
-                    }
-                    None
-                }
+                let res = res?;
+                self.finish_call(res, use_result)
             }
-            GetMember { ref name } => {
-                let item = self
-                    .thread
-                    .operation_stack
-                    .pop()
-                    .expect("Stack needs 1 item, was empty");
-                let res = {
-                    let temp_obj = &item.obj();
-                    temp_obj
-                        .members
-                        .get(name)
-                        .expect("Requested nonexistent member. TODO: runtime error")
-                        .dup()
+            GetMember { name } => {
+                let item = self.pop_operand()?;
+                let res = match cache {
+                    Some(cache) => self.get_member_cached(&item, name, cache),
+                    None => item.get_member(name),
                 };
                 self.drop_token(item);
-                Some(res)
+                res.map(Some).ok_or_else(|| {
+                    TriconeError::with_message(
+                        ErrorKind::NameError,
+                        format!("no member `{}`", name),
+                    )
+                })
+            }
+            LookupName { name } => {
+                let trace = self.trace;
+                let mut misses = 0;
+                let res = match cache {
+                    // Tracing shows every scope searched, so it goes the long way
+                    Some(cache) if !trace => self.lookup_name_cached(name, cache, &mut misses),
+                    _ => {
+                        let trace = if trace { Some(&mut *self.stdout) } else { None };
+                        self.thread
+                            .top_frame()
+                            .lookup_name(name, trace, &mut misses)
+                    }
+                };
+                self.stats.name_lookups += 1;
+                self.stats.scope_misses += misses;
+                if res.is_none() {
+                    self.stats.failed_lookups += 1;
+                }
+                res.map(Some).ok_or_else(|| {
+                    TriconeError::with_message(
+                        ErrorKind::NameError,
+                        format!("name `{}` is not defined", name),
+                    )
+                })
             }
-            LookupName { ref name } => self
-                .thread
-                .top_frame()
-                .lookup_name(name)
-                .or_else(|| panic!("Object does not exist! TODO: runtime error")),
             CallFunctionObject {
                 num_args,
                 use_result,
             } => {
-                if self.thread.operation_stack.len() < num_args {
-                    panic!("Not enough arguments passed! TODO: runtime error");
-                }
-
+                self.check_operands(num_args + 1)?;
                 let mut args = Vec::with_capacity(num_args);
                 self.get_args_from_stack(num_args, &mut args);
-                let function_obj = self
-                    .thread
-                    .operation_stack
-                    .pop()
-                    .expect("Need a function to call!");
+                let function_obj = self.pop_operand()?;
                 let res = {
                     let function_ref = function_obj.obj();
-
-                    // Should be a runtime error
-                    if function_ref.type_ != consts::FUNCTION_TYPE_ID {
-                        panic!(
-                            "Expected function type ({:?}), got: {:?}",
-                            consts::FUNCTION_TYPE_ID,
-                            function_ref
-                        );
+                    if function_ref.type_ == consts::FUNCTION_TYPE_ID {
+                        let function = function::function_from_function_object(&function_ref);
+                        function.call(self, &args)
+                    } else {
+                        Err(TriconeError::with_message(
+                            ErrorKind::TypeError,
+                            format!(
+                                "`{}` is not a function",
+                                self.get_type(function_ref.type_).name()
+                            ),
+                        ))
                     }
-                    let function = function::function_from_function_object(&function_ref);
-
-                    function.call(self, &args).unwrap()
                 };
 
                 for arg in args {
@@ -829,31 +3030,62 @@ impl Interpreter {
                 }
                 self.drop_token(function_obj);
 
-                if use_result {
-                    Some(res.unwrap_or_else(|| self.get_unit_object()))
-                } else {
-                    if let Some(obj) = res {
-                        self.drop_token(obj);
+                let res = res?;
+                self.finish_call(res, use_result)
+            }
+            CreateString { ref value } => string::create_string(self, value.clone()).map(Some),
+            CreateInt { value } => Ok(Some(Immediate::Int(value).into())),
+            CreateBool { value } => Ok(Some(Immediate::Bool(value).into())),
+            SetMember { name } => {
+                let value = self.pop_operand()?;
+                let target = match self.pop_operand() {
+                    Ok(target) => target,
+                    Err(err) => {
+                        self.drop_token(value);
+                        return Err(err);
                     }
-                    None
+                };
+                if target.immediate().is_some() {
+                    self.drop_token(value);
+                    return Err(TriconeError::with_message(
+                        ErrorKind::TypeError,
+                        format!(
+                            "can't set `{}`, `{}` values have no members",
+                            name,
+                            self.get_type(target.type_index()).name()
+                        ),
+                    ));
+                }
+                if target.type_index() == consts::EXPORTS_TYPE_ID {
+                    self.drop_token(value);
+                    self.drop_token(target);
+                    return Err(TriconeError::with_message(
+                        ErrorKind::TypeError,
+                        format!(
+                            "can't set `{}`, a module's exports can only be changed from inside it",
+                            name
+                        ),
+                    ));
                 }
+                let res = target.assign_member(name, value, self);
+                self.drop_token(target);
+                res.map(|()| None)
+            }
+            Pop => {
+                let item = self.pop_operand()?;
+                self.drop_token(item);
+                Ok(None)
             }
-            CreateString { ref value } => Some(string::create_string(self, value.clone())),
-            CreateInt { value } => Some(int::create_int(self, value)),
-            CreateBool { value } => Some(bool_::create_bool(self, value)),
             Diag => {
-                println!("{:?}", self.thread.operation_stack);
-                None
+                writeln!(self.stdout, "{:?}", self.thread.operation_stack)?;
+                Ok(None)
             }
             DebugPrintObject => {
-                let item = self
-                    .thread
-                    .operation_stack
-                    .pop()
-                    .expect("Stack needs 1 item, was empty");
-                println!("{:?}", &item);
+                let item = self.pop_operand()?;
+                let res = writeln!(self.stdout, "{:?}", &item);
                 self.drop_token(item);
-                None
+                res?;
+                Ok(None)
             }
         }
     }
@@ -867,33 +3099,35 @@ impl Default for Interpreter {
 
 impl Drop for Interpreter {
     fn drop(&mut self) {
-        let unit = self.get_unit_object();
+        // Drop methods may still run while everything is torn down, and call frames need a
+        // scope, so what's taken out is replaced by an empty one
+        let placeholder = Scope::new();
         let mut scopes = vec![];
 
+        let mut views = vec![];
         for module in &mut self.modules {
-            scopes.push(mem::replace(
-                &mut module.globals,
-                Scope { vars: unit.dup() },
-            ));
+            module.globals.vars.obj_mut().mirror = None;
+            views.extend(module.exports_view.take());
+            scopes.push(mem::replace(&mut module.globals, placeholder.dup()));
             for ty in &mut module.types {
-                scopes.push(mem::replace(&mut ty.scope, Scope { vars: unit.dup() }));
+                scopes.push(mem::replace(&mut ty.scope, placeholder.dup()));
 
                 for method in ty.methods.values_mut() {
-                    scopes.push(mem::replace(
-                        &mut method.closure,
-                        Scope { vars: unit.dup() },
-                    ));
+                    scopes.push(mem::replace(&mut method.closure, placeholder.dup()));
                 }
             }
         }
 
+        for view in views {
+            self.drop_token(view);
+        }
         for scope in scopes {
             self.drop_token(scope.vars);
         }
 
-        self.drop_token(unit);
+        self.drop_token(placeholder.vars);
 
-        let modules = mem::replace(&mut self.modules, Vec::new());
+        let modules = mem::take(&mut self.modules);
         for module in modules {
             for ty in module.types {
                 for (_, method) in ty.methods {
@@ -0,0 +1,208 @@
+//! The `io` module: printing any object and reading lines, through the interpreter's streams.
+//!
+//! - `print(obj)` and `println(obj)` write `obj.tostring()` to stdout, `eprintln(obj)` to stderr.
+//! - `read_line()` returns the next line of stdin without its line break, or Unit at the end.
+//! - `read_all()` returns the rest of stdin.
+//! - `format(format, args...)` returns `format` with each conversion replaced by the next
+//!   argument, and `printf(format, args...)` prints it. `%s` is any object's `tostring`, `%d`
+//!   an `Int` and `%%` a `%`. A width pads to that many characters, on the left unless there is
+//!   a `-` flag; with a `0` flag, `%d` pads with zeros.
+//!
+//! Interpreters start without it: hosts that let scripts use their streams call `register_io`.
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use function::NativeResult;
+use int;
+use interpreter::{ErrorKind, Interpreter, ModuleIndex, ObjectToken, TriconeError};
+use moduledef::{FunctionDef, ModuleDef, NativeFunctionDef};
+use string;
+
+fn text(interpreter: &mut Interpreter, obj: &ObjectToken) -> Result<String, TriconeError> {
+    interpreter.display_object(obj)
+}
+
+fn io_print(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = text(interpreter, &args[0])?;
+    write!(interpreter.stdout(), "{}", text)?;
+    Ok(None)
+}
+
+fn io_println(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = text(interpreter, &args[0])?;
+    writeln!(interpreter.stdout(), "{}", text)?;
+    Ok(None)
+}
+
+fn io_eprintln(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = text(interpreter, &args[0])?;
+    writeln!(interpreter.stderr(), "{}", text)?;
+    Ok(None)
+}
+
+fn io_read_line(interpreter: &mut Interpreter, _args: &[ObjectToken]) -> NativeResult {
+    // A prompt printed without a line break should show before waiting for the answer
+    interpreter.stdout().flush()?;
+    let mut line = String::new();
+    if interpreter.with_stdin(|stdin| stdin.read_line(&mut line))? == 0 {
+        return interpreter.get_unit_object().map(Some);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    string::create_string(interpreter, line).map(Some)
+}
+
+fn io_read_all(interpreter: &mut Interpreter, _args: &[ObjectToken]) -> NativeResult {
+    interpreter.stdout().flush()?;
+    let mut contents = String::new();
+    interpreter.with_stdin(|stdin| stdin.read_to_string(&mut contents))?;
+    string::create_string(interpreter, contents).map(Some)
+}
+
+fn format_error<S: Into<String>>(message: S) -> TriconeError {
+    TriconeError::with_message(ErrorKind::TypeError, message)
+}
+
+/// Fills in the conversions of `format` with `args`, in order.
+pub fn format(
+    interpreter: &mut Interpreter,
+    format: &str,
+    args: &[ObjectToken],
+) -> Result<String, TriconeError> {
+    let mut res = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+        let (mut left, mut zero) = (false, false);
+        loop {
+            match chars.peek() {
+                Some('-') => left = true,
+                Some('0') => zero = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        let conversion = match chars.next() {
+            Some('%') => {
+                res.push('%');
+                continue;
+            }
+            Some(conversion) => conversion,
+            None => {
+                return Err(format_error(
+                    "the format ends in the middle of a conversion",
+                ))
+            }
+        };
+        let arg = args.next().ok_or_else(|| {
+            TriconeError::with_message(
+                ErrorKind::WrongArgumentCount,
+                "the format has more conversions than there are arguments",
+            )
+        })?;
+        let converted = match conversion {
+            's' => text(interpreter, arg)?,
+            'd' => match int::int_value(arg) {
+                Some(value) if zero && !left => format!("{:01$}", value, width),
+                Some(value) => value.to_string(),
+                None => {
+                    return Err(format_error(format!(
+                        "`%d` needs an `Int`, not a `{}`",
+                        interpreter.get_type(arg.type_index()).name()
+                    )))
+                }
+            },
+            _ => {
+                return Err(format_error(format!(
+                    "unknown conversion `%{}`",
+                    conversion
+                )))
+            }
+        };
+        if left {
+            res.push_str(&format!("{:1$}", converted, width));
+        } else {
+            res.push_str(&format!("{:>1$}", converted, width));
+        }
+    }
+    if args.next().is_some() {
+        return Err(TriconeError::with_message(
+            ErrorKind::WrongArgumentCount,
+            "there are more arguments than the format has conversions",
+        ));
+    }
+    Ok(res)
+}
+
+fn format_args(
+    interpreter: &mut Interpreter,
+    args: &[ObjectToken],
+) -> Result<String, TriconeError> {
+    let format_string = string::string_value(&args[0])
+        .map(|value| value.to_owned())
+        .ok_or_else(|| format_error("the format must be a `String`"))?;
+    format(interpreter, &format_string, &args[1..])
+}
+
+fn io_format(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = format_args(interpreter, args)?;
+    string::create_string(interpreter, text).map(Some)
+}
+
+fn io_printf(interpreter: &mut Interpreter, args: &[ObjectToken]) -> NativeResult {
+    let text = format_args(interpreter, args)?;
+    write!(interpreter.stdout(), "{}", text)?;
+    Ok(None)
+}
+
+fn native(
+    name: &str,
+    arity: usize,
+    variadic: bool,
+    code: fn(&mut Interpreter, &[ObjectToken]) -> NativeResult,
+) -> (String, FunctionDef) {
+    (
+        name.to_owned(),
+        FunctionDef::Native(if variadic {
+            NativeFunctionDef::new(arity, code).variadic()
+        } else {
+            NativeFunctionDef::new(arity, code)
+        }),
+    )
+}
+
+/// Registers the `io` module, giving scripts the interpreter's stdin, stdout and stderr.
+pub fn register_io(interpreter: &mut Interpreter) -> Result<ModuleIndex, TriconeError> {
+    let def = ModuleDef {
+        name: "io".to_owned(),
+        types: HashMap::new(),
+        free_functions: HashMap::from_iter(vec![
+            native("print", 1, false, io_print),
+            native("println", 1, false, io_println),
+            native("eprintln", 1, false, io_eprintln),
+            native("read_line", 0, false, io_read_line),
+            native("read_all", 0, false, io_read_all),
+            native("format", 1, true, io_format),
+            native("printf", 1, true, io_printf),
+        ]),
+        init: None,
+        exports: None,
+        access: None,
+    };
+
+    def.register(interpreter)
+}
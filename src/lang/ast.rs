@@ -0,0 +1,167 @@
+use lang::Span;
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Function(FunctionDecl),
+    Type(TypeDecl),
+    Export(Vec<(String, Span)>),
+    /// Runs in the module's init.
+    Stmt(Box<Stmt>),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDecl {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeDecl {
+    pub name: String,
+    pub methods: Vec<FunctionDecl>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    /// The block's value.
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Let {
+        name: String,
+        value: Expr,
+    },
+    /// `target` is a `Name` or a `Member`.
+    Assign {
+        target: Expr,
+        value: Expr,
+    },
+    Import {
+        name: String,
+    },
+    While {
+        cond: Expr,
+        body: Block,
+    },
+    Return {
+        value: Option<Expr>,
+    },
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Less,
+    Greater,
+    Equal,
+}
+
+impl BinaryOp {
+    /// The method of the left operand that implements the operator.
+    pub fn method_name(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Less => "lt",
+            BinaryOp::Greater => "gt",
+            BinaryOp::Equal => "eq",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Name(String),
+    /// `module::name`
+    Path {
+        module: String,
+        name: String,
+    },
+    Member {
+        target: Box<Expr>,
+        name: String,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    MethodCall {
+        target: Box<Expr>,
+        name: String,
+        args: Vec<Expr>,
+    },
+    New {
+        module: Option<String>,
+        type_: String,
+        args: Vec<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    If {
+        cond: Box<Expr>,
+        then: Block,
+        else_: Option<Block>,
+    },
+    Block(Block),
+}
+
+impl Expr {
+    /// Whether the expression produces a value; only blocks without a trailing expression and
+    /// `if`s with such a branch (or without `else`) don't.
+    pub fn has_value(&self) -> bool {
+        match self.kind {
+            ExprKind::Block(ref block) => block.has_value(),
+            ExprKind::If {
+                ref then,
+                ref else_,
+                ..
+            } => then.has_value() && else_.as_ref().is_some_and(Block::has_value),
+            _ => true,
+        }
+    }
+
+    /// Whether the expression ends in a block, so it needs no `;` as a statement.
+    pub fn is_block_like(&self) -> bool {
+        matches!(self.kind, ExprKind::Block(_) | ExprKind::If { .. })
+    }
+}
+
+impl Block {
+    pub fn has_value(&self) -> bool {
+        self.tail.as_ref().is_some_and(|tail| tail.has_value())
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use debuginfo::DebugInfo;
+use interpreter::{consts, Instruction};
+use lang::ast::*;
+use lang::{CompileError, Span};
+use moduledef::{BytecodeFunctionDef, FunctionDef, ModuleDef, TypeDef};
+use symbol::Symbol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Init,
+    Function,
+    Method,
+}
+
+struct FunctionGen<'a> {
+    module: &'a str,
+    context: Context,
+    locals: HashSet<String>,
+    // The locals of function code by slot; init code keeps its locals in the module's globals
+    slots: Vec<Symbol>,
+    code: Vec<Instruction>,
+    // `Jump`s to the end of the function, patched once its length is known
+    returns: Vec<usize>,
+    debug_info: DebugInfo,
+    // The innermost statement or expression being compiled
+    span: Span,
+}
+
+impl<'a> FunctionGen<'a> {
+    fn new(module: &'a str, file: &str, context: Context, span: Span) -> FunctionGen<'a> {
+        FunctionGen {
+            module,
+            context,
+            locals: HashSet::new(),
+            slots: vec![],
+            code: vec![],
+            returns: vec![],
+            debug_info: DebugInfo::new(file),
+            span,
+        }
+    }
+
+    fn emit(&mut self, insn: Instruction) -> usize {
+        self.debug_info
+            .add(self.code.len(), self.span.line, self.span.column);
+        self.code.push(insn);
+        self.code.len() - 1
+    }
+
+    /// Runs `f` with the instructions it emits attributed to `span`.
+    fn at<T, F>(&mut self, span: Span, f: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let outer = mem::replace(&mut self.span, span);
+        let res = f(self);
+        self.span = outer;
+        res
+    }
+
+    fn patch_jump(&mut self, pos: usize, target: usize) {
+        match self.code[pos] {
+            Instruction::Jump { ref mut to } | Instruction::JumpIfFalse { ref mut to } => {
+                *to = target
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn finish(mut self, arity: usize) -> BytecodeFunctionDef {
+        let end = self.code.len();
+        for pos in mem::take(&mut self.returns) {
+            self.patch_jump(pos, end);
+        }
+        BytecodeFunctionDef {
+            arity,
+            instructions: self.code,
+            debug_info: Some(self.debug_info),
+            locals: self.slots,
+        }
+    }
+
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|&slot| slot == name)
+    }
+
+    /// Pops the top of the stack into local `name`, declaring it if it is new.
+    fn store(&mut self, name: &str) {
+        self.locals.insert(name.to_owned());
+        if self.context == Context::Init {
+            self.emit(Instruction::Assign {
+                name: Symbol::new(name),
+            });
+            return;
+        }
+        let slot = self.slot(name).unwrap_or_else(|| {
+            self.slots.push(Symbol::new(name));
+            self.slots.len() - 1
+        });
+        self.emit(Instruction::StoreLocal { slot });
+    }
+
+    /// Arguments arrive on the operation stack with the first one deepest.
+    fn params(&mut self, params: &[String]) {
+        // Parameter `n` gets slot `n`
+        self.slots
+            .extend(params.iter().map(|param| Symbol::new(param)));
+        for param in params.iter().rev() {
+            self.store(param);
+        }
+    }
+
+    fn name(&mut self, name: &str) {
+        if let Some(slot) = self.slot(name) {
+            self.emit(Instruction::LoadLocal { slot });
+        } else if self.context == Context::Method {
+            // Methods are closed over their type's scope, not the module's globals
+            self.emit(Instruction::GetModuleGlobals {
+                name: Symbol::new(self.module),
+            });
+            self.emit(Instruction::GetMember {
+                name: Symbol::new(name),
+            });
+        } else {
+            self.emit(Instruction::LookupName {
+                name: Symbol::new(name),
+            });
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        self.at(stmt.span, |gen| gen.stmt_kind(stmt))
+    }
+
+    fn stmt_kind(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt.kind {
+            StmtKind::Let {
+                ref name,
+                ref value,
+            } => {
+                self.expr(value)?;
+                self.store(name);
+            }
+            StmtKind::Assign {
+                ref target,
+                ref value,
+            } => match target.kind {
+                ExprKind::Name(ref name) => {
+                    if !self.locals.contains(name) {
+                        return Err(CompileError::new(
+                            target.span,
+                            format!("`{}` is not a local, declare it with `let`", name),
+                        ));
+                    }
+                    self.expr(value)?;
+                    self.store(name);
+                }
+                ExprKind::Member {
+                    target: ref object,
+                    ref name,
+                } => {
+                    self.expr(object)?;
+                    self.expr(value)?;
+                    self.emit(Instruction::SetMember {
+                        name: Symbol::new(name),
+                    });
+                }
+                _ => unreachable!("the parser only accepts names and members"),
+            },
+            StmtKind::Import { ref name } => {
+                self.emit(Instruction::Import {
+                    name: Symbol::new(name),
+                });
+                self.store(name);
+            }
+            StmtKind::While { ref cond, ref body } => {
+                let start = self.code.len();
+                self.expr(cond)?;
+                let exit = self.emit(Instruction::JumpIfFalse { to: 0 });
+                self.block(body, false)?;
+                self.emit(Instruction::Jump { to: start });
+                let end = self.code.len();
+                self.patch_jump(exit, end);
+            }
+            StmtKind::Return { ref value } => {
+                if self.context == Context::Init {
+                    return Err(CompileError::new(
+                        stmt.span,
+                        "`return` outside of a function",
+                    ));
+                }
+                if let Some(ref value) = *value {
+                    self.expr(value)?;
+                }
+                let jump = self.emit(Instruction::Jump { to: 0 });
+                self.returns.push(jump);
+            }
+            StmtKind::Expr(ref expr) => self.expr_discard(expr)?,
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &Block, want_value: bool) -> Result<(), CompileError> {
+        for stmt in &block.stmts {
+            self.stmt(stmt)?;
+        }
+        match block.tail {
+            Some(ref tail) if want_value => self.expr(tail),
+            Some(ref tail) => self.expr_discard(tail),
+            None if want_value => Err(CompileError::new(block.span, "this block has no value")),
+            None => Ok(()),
+        }
+    }
+
+    fn if_expr(
+        &mut self,
+        cond: &Expr,
+        then: &Block,
+        else_: Option<&Block>,
+        span: Span,
+        want_value: bool,
+    ) -> Result<(), CompileError> {
+        if want_value && else_.is_none() {
+            return Err(CompileError::new(span, "`if` without `else` has no value"));
+        }
+        self.expr(cond)?;
+        let skip_then = self.emit(Instruction::JumpIfFalse { to: 0 });
+        self.block(then, want_value)?;
+        match else_ {
+            Some(else_) => {
+                let skip_else = self.emit(Instruction::Jump { to: 0 });
+                let else_start = self.code.len();
+                self.patch_jump(skip_then, else_start);
+                self.block(else_, want_value)?;
+                let end = self.code.len();
+                self.patch_jump(skip_else, end);
+            }
+            None => {
+                let end = self.code.len();
+                self.patch_jump(skip_then, end);
+            }
+        }
+        Ok(())
+    }
+
+    fn args(&mut self, args: &[Expr]) -> Result<(), CompileError> {
+        for arg in args {
+            self.expr(arg)?;
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, expr: &Expr, use_result: bool) -> Result<(), CompileError> {
+        match expr.kind {
+            ExprKind::Call {
+                ref callee,
+                ref args,
+            } => {
+                self.expr(callee)?;
+                self.args(args)?;
+                self.emit(Instruction::CallFunctionObject {
+                    num_args: args.len(),
+                    use_result,
+                });
+            }
+            ExprKind::MethodCall {
+                ref target,
+                ref name,
+                ref args,
+            } => {
+                self.expr(target)?;
+                self.args(args)?;
+                self.emit(Instruction::CallMethod {
+                    name: Symbol::new(name),
+                    num_args: args.len(),
+                    use_result,
+                });
+            }
+            ExprKind::Binary {
+                op,
+                ref lhs,
+                ref rhs,
+            } => {
+                self.expr(lhs)?;
+                self.expr(rhs)?;
+                self.emit(Instruction::CallMethod {
+                    name: Symbol::new(op.method_name()),
+                    num_args: 1,
+                    use_result,
+                });
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Compiles an expression whose value isn't needed.
+    fn expr_discard(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        self.at(expr.span, |gen| gen.expr_discard_kind(expr))
+    }
+
+    fn expr_discard_kind(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr.kind {
+            ExprKind::Call { .. } | ExprKind::MethodCall { .. } | ExprKind::Binary { .. } => {
+                self.call(expr, false)
+            }
+            ExprKind::If {
+                ref cond,
+                ref then,
+                ref else_,
+            } => self.if_expr(cond, then, else_.as_ref(), expr.span, false),
+            ExprKind::Block(ref block) => self.block(block, false),
+            _ => {
+                self.expr(expr)?;
+                self.emit(Instruction::Pop);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles an expression that leaves exactly one value behind.
+    fn expr(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        self.at(expr.span, |gen| gen.expr_kind(expr))
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr.kind {
+            ExprKind::Int(value) => {
+                self.emit(Instruction::CreateInt { value });
+            }
+            ExprKind::Str(ref value) => {
+                self.emit(Instruction::CreateString {
+                    value: value.clone(),
+                });
+            }
+            ExprKind::Bool(value) => {
+                self.emit(Instruction::CreateBool { value });
+            }
+            ExprKind::Name(ref name) => self.name(name),
+            ExprKind::Path {
+                ref module,
+                ref name,
+            } => {
+                self.emit(Instruction::Import {
+                    name: Symbol::new(module),
+                });
+                self.emit(Instruction::GetMember {
+                    name: Symbol::new(name),
+                });
+            }
+            ExprKind::Member {
+                ref target,
+                ref name,
+            } => {
+                self.expr(target)?;
+                self.emit(Instruction::GetMember {
+                    name: Symbol::new(name),
+                });
+            }
+            ExprKind::Call { .. } | ExprKind::MethodCall { .. } | ExprKind::Binary { .. } => {
+                self.call(expr, true)?
+            }
+            ExprKind::New {
+                ref module,
+                ref type_,
+                ref args,
+            } => {
+                self.args(args)?;
+                let module = module.as_deref().unwrap_or(self.module);
+                self.emit(Instruction::CreateObject {
+                    type_spec: (Symbol::new(module), Symbol::new(type_)),
+                    num_args: args.len(),
+                });
+            }
+            ExprKind::If {
+                ref cond,
+                ref then,
+                ref else_,
+            } => self.if_expr(cond, then, else_.as_ref(), expr.span, true)?,
+            ExprKind::Block(ref block) => self.block(block, true)?,
+        }
+        Ok(())
+    }
+}
+
+fn function(
+    module: &str,
+    file: &str,
+    decl: &FunctionDecl,
+    context: Context,
+) -> Result<FunctionDef, CompileError> {
+    let mut gen = FunctionGen::new(module, file, context, decl.span);
+    gen.params(&decl.params);
+    // The interpreter insists that these return nothing
+    let returns_nothing = context == Context::Method
+        && (decl.name == consts::CREATE_METHOD_NAME || decl.name == consts::DROP_METHOD_NAME);
+    let want_value = decl.body.has_value() && !returns_nothing;
+    gen.block(&decl.body, want_value)?;
+    Ok(FunctionDef::Bytecode(gen.finish(decl.params.len())))
+}
+
+pub fn generate_module(name: &str, file: &str, module: &Module) -> Result<ModuleDef, CompileError> {
+    let mut free_functions = HashMap::new();
+    let mut types = HashMap::new();
+    let mut exports: Option<Vec<String>> = None;
+    let mut init = FunctionGen::new(name, file, Context::Init, Span::default());
+
+    for item in &module.items {
+        match *item {
+            Item::Function(ref decl) => {
+                let def = function(name, file, decl, Context::Function)?;
+                if free_functions.insert(decl.name.clone(), def).is_some() {
+                    return Err(CompileError::new(
+                        decl.span,
+                        format!("duplicate function `{}`", decl.name),
+                    ));
+                }
+            }
+            Item::Type(ref decl) => {
+                let mut methods = HashMap::new();
+                for method in &decl.methods {
+                    let def = function(name, file, method, Context::Method)?;
+                    if methods.insert(method.name.clone(), def).is_some() {
+                        return Err(CompileError::new(
+                            method.span,
+                            format!("duplicate method `{}`", method.name),
+                        ));
+                    }
+                }
+                if types
+                    .insert(decl.name.clone(), TypeDef { methods })
+                    .is_some()
+                {
+                    return Err(CompileError::new(
+                        decl.span,
+                        format!("duplicate type `{}`", decl.name),
+                    ));
+                }
+            }
+            Item::Export(ref names) => exports
+                .get_or_insert_with(Vec::new)
+                .extend(names.iter().map(|(name, _)| name.clone())),
+            Item::Stmt(ref stmt) => init.stmt(stmt)?,
+        }
+    }
+
+    let init = if init.code.is_empty() {
+        None
+    } else {
+        Some(init.finish(0))
+    };
+    Ok(ModuleDef {
+        name: name.to_owned(),
+        types,
+        free_functions,
+        init,
+        exports,
+        access: None,
+    })
+}
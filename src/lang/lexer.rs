@@ -0,0 +1,260 @@
+use lang::{CompileError, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    Fn,
+    Let,
+    Type,
+    If,
+    Else,
+    While,
+    Return,
+    New,
+    True,
+    False,
+    Import,
+    Export,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    Comma,
+    Semicolon,
+    Dot,
+    PathSep,
+    Assign,
+    Plus,
+    Minus,
+    Star,
+    Less,
+    Greater,
+    EqualEqual,
+    Eof,
+}
+
+impl TokenKind {
+    /// How the token is shown in error messages.
+    pub fn describe(&self) -> String {
+        let text = match *self {
+            TokenKind::Ident(ref name) => return format!("`{}`", name),
+            TokenKind::Int(value) => return format!("`{}`", value),
+            TokenKind::Str(_) => return "a string".to_owned(),
+            TokenKind::Eof => return "the end of the file".to_owned(),
+            TokenKind::Fn => "fn",
+            TokenKind::Let => "let",
+            TokenKind::Type => "type",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::While => "while",
+            TokenKind::Return => "return",
+            TokenKind::New => "new",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Import => "import",
+            TokenKind::Export => "export",
+            TokenKind::OpenParen => "(",
+            TokenKind::CloseParen => ")",
+            TokenKind::OpenBrace => "{",
+            TokenKind::CloseBrace => "}",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::Dot => ".",
+            TokenKind::PathSep => "::",
+            TokenKind::Assign => "=",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Less => "<",
+            TokenKind::Greater => ">",
+            TokenKind::EqualEqual => "==",
+        };
+        format!("`{}`", text)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+fn keyword(word: &str) -> Option<TokenKind> {
+    Some(match word {
+        "fn" => TokenKind::Fn,
+        "let" => TokenKind::Let,
+        "type" => TokenKind::Type,
+        "if" => TokenKind::If,
+        "else" => TokenKind::Else,
+        "while" => TokenKind::While,
+        "return" => TokenKind::Return,
+        "new" => TokenKind::New,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        "import" => TokenKind::Import,
+        "export" => TokenKind::Export,
+        _ => return None,
+    })
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn here(&self) -> Span {
+        Span {
+            start: self.pos,
+            end: self.pos,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') if self.peek_second() == Some('/') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn string(&mut self, start: Span) -> Result<TokenKind, CompileError> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(TokenKind::Str(value)),
+                Some('\\') => value.push(match self.bump() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    _ => return Err(CompileError::new(self.here(), "unknown escape")),
+                }),
+                Some(c) => value.push(c),
+                None => return Err(CompileError::new(start, "unterminated string")),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Token, CompileError> {
+        self.skip_whitespace_and_comments();
+        let start = self.here();
+        let c = match self.bump() {
+            Some(c) => c,
+            None => {
+                return Ok(Token {
+                    kind: TokenKind::Eof,
+                    span: start,
+                })
+            }
+        };
+
+        let kind = match c {
+            '(' => TokenKind::OpenParen,
+            ')' => TokenKind::CloseParen,
+            '{' => TokenKind::OpenBrace,
+            '}' => TokenKind::CloseBrace,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            '.' => TokenKind::Dot,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '<' => TokenKind::Less,
+            '>' => TokenKind::Greater,
+            ':' if self.peek() == Some(':') => {
+                self.bump();
+                TokenKind::PathSep
+            }
+            '=' if self.peek() == Some('=') => {
+                self.bump();
+                TokenKind::EqualEqual
+            }
+            '=' => TokenKind::Assign,
+            '"' => self.string(start)?,
+            c if c.is_ascii_digit() => {
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.bump();
+                }
+                let digits = &self.source[start.start..self.pos];
+                TokenKind::Int(digits.parse().map_err(|_| {
+                    CompileError::new(start, format!("integer `{}` is too large", digits))
+                })?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.bump();
+                }
+                let word = &self.source[start.start..self.pos];
+                keyword(word).unwrap_or_else(|| TokenKind::Ident(word.to_owned()))
+            }
+            other => {
+                return Err(CompileError::new(
+                    start,
+                    format!("unexpected character `{}`", other),
+                ))
+            }
+        };
+
+        Ok(Token {
+            kind,
+            span: Span {
+                end: self.pos,
+                ..start
+            },
+        })
+    }
+}
+
+/// Splits `source` into tokens, ending with `TokenKind::Eof`.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut lexer = Lexer {
+        source,
+        pos: 0,
+        line: 1,
+        column: 1,
+    };
+    let mut tokens = vec![];
+    loop {
+        let token = lexer.token()?;
+        let done = token.kind == TokenKind::Eof;
+        tokens.push(token);
+        if done {
+            return Ok(tokens);
+        }
+    }
+}
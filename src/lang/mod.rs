@@ -0,0 +1,94 @@
+//! A small expression-oriented language that compiles to tricone bytecode.
+//!
+//! ```text
+//! // module-level statements run in the module's init
+//! import other;
+//! let greeting = "hello";
+//!
+//! type Counter {
+//!     fn create(self, start) { self.value = start; }
+//!     fn bump(self) { self.value = self.value + 1; self.value }
+//! }
+//!
+//! fn count_to(n) {
+//!     let counter = new Counter(0);
+//!     while counter.value < n {
+//!         counter.bump();
+//!     }
+//!     if counter.value == n { greeting } else { other::complain(counter) }
+//! }
+//!
+//! export count_to;
+//! ```
+//!
+//! A block's value is its trailing expression, if it has one. `+`, `-`, `*`, `<`, `>` and `==`
+//! call the `add`, `sub`, `mul`, `lt`, `gt` and `eq` methods of their left operand, and
+//! `new Type(args)` or `new module::Type(args)` creates an object, passing `args` to its `create`
+//! method. `module::name` is a global of another module, which is imported if it isn't loaded
+//! yet; `import module;` binds the module's globals to a name instead. `let` bindings live until
+//! the end of their function, and only names bound by `let` (or parameters) can be assigned to.
+//! Inside methods, names that aren't local refer to the module's globals.
+
+use std::fmt;
+
+use moduledef::ModuleDef;
+
+pub mod ast;
+mod codegen;
+pub mod lexer;
+pub mod parser;
+
+pub const SOURCE_EXTENSION: &str = "tri";
+
+/// A range of bytes in the source, with the 1-based line and column where it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl CompileError {
+    pub fn new<S: Into<String>>(span: Span, message: S) -> CompileError {
+        CompileError {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// Compiles the source of a whole module. The debug info of its functions names the file
+/// `<name>.tri` until `ModuleDef::set_source_file` says otherwise.
+pub fn compile_module(name: &str, source: &str) -> Result<ModuleDef, CompileError> {
+    let tokens = lexer::tokenize(source)?;
+    let module = parser::parse_module(&tokens)?;
+    let file = format!("{}.{}", name, SOURCE_EXTENSION);
+    codegen::generate_module(name, &file, &module)
+}
@@ -0,0 +1,424 @@
+use lang::ast::*;
+use lang::lexer::{Token, TokenKind};
+use lang::{CompileError, Span};
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &'a TokenKind {
+        &self.tokens[self.pos].kind
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].span
+    }
+
+    fn prev_span(&self) -> Span {
+        self.tokens[self.pos.saturating_sub(1)].span
+    }
+
+    fn bump(&mut self) -> &'a Token {
+        let token = &self.tokens[self.pos];
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == kind {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, CompileError> {
+        Err(CompileError::new(
+            self.span(),
+            format!("expected {}, found {}", expected, self.peek().describe()),
+        ))
+    }
+
+    fn expect(&mut self, kind: &TokenKind, expected: &str) -> Result<Span, CompileError> {
+        if self.peek() == kind {
+            Ok(self.bump().span)
+        } else {
+            self.error(expected)
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Span), CompileError> {
+        match *self.peek() {
+            TokenKind::Ident(ref name) => Ok((name.clone(), self.bump().span)),
+            _ => self.error("a name"),
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, CompileError> {
+        match *self.peek() {
+            TokenKind::Fn => Ok(Item::Function(self.function()?)),
+            TokenKind::Type => {
+                let start = self.bump().span;
+                let (name, _) = self.ident()?;
+                self.expect(&TokenKind::OpenBrace, "`{`")?;
+                let mut methods = vec![];
+                while !self.eat(&TokenKind::CloseBrace) {
+                    if *self.peek() != TokenKind::Fn {
+                        return self.error("`fn` or `}`");
+                    }
+                    methods.push(self.function()?);
+                }
+                Ok(Item::Type(TypeDecl {
+                    name,
+                    methods,
+                    span: start.to(self.prev_span()),
+                }))
+            }
+            TokenKind::Export => {
+                self.bump();
+                let mut names = vec![self.ident()?];
+                while self.eat(&TokenKind::Comma) {
+                    names.push(self.ident()?);
+                }
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                Ok(Item::Export(names))
+            }
+            _ => match self.statement()? {
+                (stmt, None) => Ok(Item::Stmt(Box::new(stmt))),
+                (_, Some(expr)) => Err(CompileError::new(expr.span, "expected `;`")),
+            },
+        }
+    }
+
+    fn function(&mut self) -> Result<FunctionDecl, CompileError> {
+        let start = self.expect(&TokenKind::Fn, "`fn`")?;
+        let (name, _) = self.ident()?;
+        self.expect(&TokenKind::OpenParen, "`(`")?;
+        let mut params = vec![];
+        if !self.eat(&TokenKind::CloseParen) {
+            loop {
+                let (param, span) = self.ident()?;
+                if params.contains(&param) {
+                    return Err(CompileError::new(
+                        span,
+                        format!("duplicate parameter `{}`", param),
+                    ));
+                }
+                params.push(param);
+                if self.eat(&TokenKind::CloseParen) {
+                    break;
+                }
+                self.expect(&TokenKind::Comma, "`,` or `)`")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(FunctionDecl {
+            name,
+            params,
+            span: start.to(body.span),
+            body,
+        })
+    }
+
+    fn block(&mut self) -> Result<Block, CompileError> {
+        let start = self.expect(&TokenKind::OpenBrace, "`{`")?;
+        let mut stmts = vec![];
+        let mut tail = None;
+        while !self.eat(&TokenKind::CloseBrace) {
+            let (stmt, expr) = self.statement()?;
+            match expr {
+                Some(expr) => {
+                    tail = Some(Box::new(expr));
+                    self.expect(&TokenKind::CloseBrace, "`}`")?;
+                    break;
+                }
+                None => stmts.push(stmt),
+            }
+        }
+        Ok(Block {
+            stmts,
+            tail,
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    /// Parses a statement. An expression that ends a block and has a value is returned on its
+    /// own instead, to become the block's value.
+    fn statement(&mut self) -> Result<(Stmt, Option<Expr>), CompileError> {
+        let start = self.span();
+        let kind = match *self.peek() {
+            TokenKind::Let => {
+                self.bump();
+                let (name, _) = self.ident()?;
+                self.expect(&TokenKind::Assign, "`=`")?;
+                let value = self.expr()?;
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                StmtKind::Let { name, value }
+            }
+            TokenKind::Import => {
+                self.bump();
+                let (name, _) = self.ident()?;
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                StmtKind::Import { name }
+            }
+            TokenKind::While => {
+                self.bump();
+                let cond = self.expr()?;
+                let body = self.block()?;
+                StmtKind::While { cond, body }
+            }
+            TokenKind::Return => {
+                self.bump();
+                let value = if *self.peek() == TokenKind::Semicolon {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(&TokenKind::Semicolon, "`;`")?;
+                StmtKind::Return { value }
+            }
+            _ => {
+                let expr = self.expr()?;
+                if self.eat(&TokenKind::Assign) {
+                    match expr.kind {
+                        ExprKind::Name(_) | ExprKind::Member { .. } => {}
+                        _ => return Err(CompileError::new(expr.span, "cannot assign to this")),
+                    }
+                    let value = self.expr()?;
+                    self.expect(&TokenKind::Semicolon, "`;`")?;
+                    StmtKind::Assign {
+                        target: expr,
+                        value,
+                    }
+                } else if self.eat(&TokenKind::Semicolon) {
+                    StmtKind::Expr(expr)
+                } else if *self.peek() == TokenKind::CloseBrace && expr.has_value() {
+                    let stmt = Stmt {
+                        kind: StmtKind::Expr(expr.clone()),
+                        span: expr.span,
+                    };
+                    return Ok((stmt, Some(expr)));
+                } else if expr.is_block_like() {
+                    StmtKind::Expr(expr)
+                } else {
+                    return self.error("`;`");
+                }
+            }
+        };
+        Ok((
+            Stmt {
+                kind,
+                span: start.to(self.prev_span()),
+            },
+            None,
+        ))
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        let lhs = self.additive()?;
+        let op = match *self.peek() {
+            TokenKind::Less => BinaryOp::Less,
+            TokenKind::Greater => BinaryOp::Greater,
+            TokenKind::EqualEqual => BinaryOp::Equal,
+            _ => return Ok(lhs),
+        };
+        self.bump();
+        let rhs = self.additive()?;
+        Ok(binary(op, lhs, rhs))
+    }
+
+    fn additive(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match *self.peek() {
+                TokenKind::Plus => BinaryOp::Add,
+                TokenKind::Minus => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.bump();
+            let rhs = self.multiplicative()?;
+            lhs = binary(op, lhs, rhs);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.postfix()?;
+        while self.eat(&TokenKind::Star) {
+            let rhs = self.postfix()?;
+            lhs = binary(BinaryOp::Mul, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn args(&mut self) -> Result<Vec<Expr>, CompileError> {
+        self.expect(&TokenKind::OpenParen, "`(`")?;
+        let mut args = vec![];
+        if self.eat(&TokenKind::CloseParen) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat(&TokenKind::CloseParen) {
+                return Ok(args);
+            }
+            self.expect(&TokenKind::Comma, "`,` or `)`")?;
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, CompileError> {
+        let mut expr = self.primary()?;
+        loop {
+            let kind = match *self.peek() {
+                TokenKind::Dot => {
+                    self.bump();
+                    let (name, _) = self.ident()?;
+                    if *self.peek() == TokenKind::OpenParen {
+                        ExprKind::MethodCall {
+                            target: Box::new(expr),
+                            name,
+                            args: self.args()?,
+                        }
+                    } else {
+                        ExprKind::Member {
+                            target: Box::new(expr),
+                            name,
+                        }
+                    }
+                }
+                TokenKind::OpenParen => ExprKind::Call {
+                    callee: Box::new(expr),
+                    args: self.args()?,
+                },
+                _ => return Ok(expr),
+            };
+            let span = match kind {
+                ExprKind::MethodCall { ref target, .. } | ExprKind::Member { ref target, .. } => {
+                    target.span
+                }
+                ExprKind::Call { ref callee, .. } => callee.span,
+                _ => unreachable!(),
+            };
+            expr = Expr {
+                kind,
+                span: span.to(self.prev_span()),
+            };
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let start = self.span();
+        let kind = match *self.peek() {
+            TokenKind::Int(value) => {
+                self.bump();
+                ExprKind::Int(value)
+            }
+            TokenKind::Minus => {
+                self.bump();
+                match *self.peek() {
+                    TokenKind::Int(value) => {
+                        self.bump();
+                        ExprKind::Int(-value)
+                    }
+                    _ => return self.error("an integer"),
+                }
+            }
+            TokenKind::Str(ref value) => {
+                self.bump();
+                ExprKind::Str(value.clone())
+            }
+            TokenKind::True | TokenKind::False => {
+                ExprKind::Bool(self.bump().kind == TokenKind::True)
+            }
+            TokenKind::Ident(_) => {
+                let (name, _) = self.ident()?;
+                if self.eat(&TokenKind::PathSep) {
+                    ExprKind::Path {
+                        module: name,
+                        name: self.ident()?.0,
+                    }
+                } else {
+                    ExprKind::Name(name)
+                }
+            }
+            TokenKind::New => {
+                self.bump();
+                let (first, _) = self.ident()?;
+                let (module, type_) = if self.eat(&TokenKind::PathSep) {
+                    (Some(first), self.ident()?.0)
+                } else {
+                    (None, first)
+                };
+                ExprKind::New {
+                    module,
+                    type_,
+                    args: self.args()?,
+                }
+            }
+            TokenKind::OpenParen => {
+                self.bump();
+                let expr = self.expr()?;
+                self.expect(&TokenKind::CloseParen, "`)`")?;
+                return Ok(expr);
+            }
+            TokenKind::If => return self.if_expr(),
+            TokenKind::OpenBrace => ExprKind::Block(self.block()?),
+            _ => return self.error("an expression"),
+        };
+        Ok(Expr {
+            kind,
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    fn if_expr(&mut self) -> Result<Expr, CompileError> {
+        let start = self.expect(&TokenKind::If, "`if`")?;
+        let cond = self.expr()?;
+        let then = self.block()?;
+        let else_ = if !self.eat(&TokenKind::Else) {
+            None
+        } else if *self.peek() == TokenKind::If {
+            let nested = self.if_expr()?;
+            Some(Block {
+                stmts: vec![],
+                span: nested.span,
+                tail: Some(Box::new(nested)),
+            })
+        } else {
+            Some(self.block()?)
+        };
+        Ok(Expr {
+            kind: ExprKind::If {
+                cond: Box::new(cond),
+                then,
+                else_,
+            },
+            span: start.to(self.prev_span()),
+        })
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr {
+        span: lhs.span.to(rhs.span),
+        kind: ExprKind::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+    }
+}
+
+/// Parses the tokens of a whole module, which must end with `TokenKind::Eof`.
+pub fn parse_module(tokens: &[Token]) -> Result<Module, CompileError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut items = vec![];
+    while *parser.peek() != TokenKind::Eof {
+        items.push(parser.item()?);
+    }
+    Ok(Module { items })
+}
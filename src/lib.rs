@@ -1,14 +1,35 @@
 extern crate arrayvec;
 
+#[macro_use]
+pub mod symbol;
+pub mod asm;
+pub mod binary;
+pub mod debugger;
+pub mod debuginfo;
+pub mod fuel;
+pub mod fs_;
 pub mod function;
 pub mod interpreter;
 #[macro_use]
 pub mod generic;
 pub mod bool_;
+pub mod dap;
 pub mod hello;
+mod inline_cache;
 pub mod int;
+pub mod io_;
+pub mod lang;
+mod link;
+pub mod loader;
+pub mod memory;
 pub mod moduledef;
+mod optimize;
+pub mod output;
+pub mod profiler;
+pub mod repl;
+pub mod stats;
 pub mod string;
 pub mod builtins;
 
 pub use interpreter::Interpreter;
+pub use symbol::Symbol;
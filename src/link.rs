@@ -0,0 +1,233 @@
+//! Resolving the modules and types bytecode refers to, once when its module is registered.
+//!
+//! `CreateObject` and `GetModuleGlobals` name their target, and looking it up every time they
+//! run means scanning modules and types. Linking replaces them with `CreateObjectResolved` and
+//! `GetModuleGlobalsResolved`, which carry the indices instead, and a target that doesn't exist
+//! fails the registration rather than the code that uses it.
+//!
+//! Modules that aren't registered yet are left for the code to import when it first runs, if the
+//! module loader has them, so registering a module doesn't load everything it might use. Those
+//! instructions, and the ones that refer to modules the module's access policy keeps its code
+//! from, stay as they are: they look their target up when they run, and are refused then if
+//! they may not reach it.
+//!
+//! A `LookupName` of one of its function's locals becomes a `LoadLocal` of the local's slot,
+//! which falls back to looking the name up in scope while the slot isn't set, as the lookup
+//! would have. Lookups then never have to search the locals.
+//!
+//! Linking also charges the names the code uses against the interpreter's memory limit, as
+//! interned names are never freed.
+
+use interpreter::{
+    ErrorKind, Instruction, Interpreter, ModuleAccess, ModuleIndex, TriconeError, Type, TypeIndex,
+};
+use moduledef::{BytecodeFunctionDef, FunctionDef, ModuleDef};
+use symbol::{Symbol, SymbolMap};
+
+// The bytecode functions of a module, with the qualified names errors refer to them by
+pub(crate) fn bytecode_functions(def: &mut ModuleDef) -> Vec<(String, &mut BytecodeFunctionDef)> {
+    let module = &def.name;
+    let mut functions = vec![];
+    for (type_name, tydef) in &mut def.types {
+        for (name, function) in &mut tydef.methods {
+            if let FunctionDef::Bytecode(ref mut function) = *function {
+                functions.push((format!("{}::{}::{}", module, type_name, name), function));
+            }
+        }
+    }
+    for (name, function) in &mut def.free_functions {
+        if let FunctionDef::Bytecode(ref mut function) = *function {
+            functions.push((format!("{}::{}", module, name), function));
+        }
+    }
+    if let Some(ref mut init) = def.init {
+        functions.push((format!("{}::<init>", module), init));
+    }
+    functions
+}
+
+// Every name `def` defines or its code refers to
+fn names(def: &ModuleDef) -> Vec<Symbol> {
+    let mut names = vec![Symbol::new(&def.name)];
+    let mut functions = vec![];
+    for (type_name, tydef) in &def.types {
+        names.push(Symbol::new(type_name));
+        for (name, function) in &tydef.methods {
+            names.push(Symbol::new(name));
+            functions.push(function);
+        }
+    }
+    for (name, function) in &def.free_functions {
+        names.push(Symbol::new(name));
+        functions.push(function);
+    }
+    let code = functions
+        .into_iter()
+        .filter_map(|function| match *function {
+            FunctionDef::Bytecode(ref function) => Some(function),
+            FunctionDef::Native(_) => None,
+        })
+        .chain(def.init.as_ref());
+    for function in code {
+        names.extend(&function.locals);
+        for insn in &function.instructions {
+            match *insn {
+                Instruction::CreateObject {
+                    type_spec: (module, type_),
+                    ..
+                } => names.extend(&[module, type_]),
+                Instruction::Assign { name }
+                | Instruction::GetModuleGlobals { name }
+                | Instruction::Import { name }
+                | Instruction::CallMethod { name, .. }
+                | Instruction::GetMember { name }
+                | Instruction::LookupName { name }
+                | Instruction::SetMember { name } => names.push(name),
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+// The policy `def`'s code runs under once it is registered as module `index`
+fn access_policy(interpreter: &Interpreter, index: ModuleIndex, def: &ModuleDef) -> ModuleAccess {
+    def.access
+        .as_ref()
+        .or_else(|| interpreter.get_module(index).access.as_ref())
+        .unwrap_or_else(|| interpreter.default_module_access())
+        .clone()
+}
+
+/// Links `def`'s code as the code of the registered module `index`, whose types are registered
+/// as well, except for new ones a reload adds.
+pub(crate) fn link_module(
+    interpreter: &mut Interpreter,
+    index: ModuleIndex,
+    def: &mut ModuleDef,
+) -> Result<(), TriconeError> {
+    interpreter.charge_names(names(def))?;
+    let access = access_policy(interpreter, index, def);
+    let types = type_indices(index, &interpreter.get_module(index).types, def);
+    Linker {
+        module: Symbol::new(&def.name),
+        index,
+        types,
+        access,
+    }
+    .link(interpreter, def)
+}
+
+// The indices of a module's types: the ones it has keep theirs, and new ones follow in order of
+// their names
+fn type_indices(index: ModuleIndex, existing: &[Type], def: &ModuleDef) -> SymbolMap<TypeIndex> {
+    let mut indices = SymbolMap::default();
+    let mut new_types = vec![];
+    for name in def.types.keys() {
+        match existing.iter().position(|ty| ty.name() == name) {
+            Some(pos) => {
+                indices.insert(Symbol::new(name), TypeIndex::new(index, pos));
+            }
+            None => new_types.push(name),
+        }
+    }
+    new_types.sort();
+    for (offset, name) in new_types.into_iter().enumerate() {
+        let pos = existing.len() + offset;
+        indices.insert(Symbol::new(name), TypeIndex::new(index, pos));
+    }
+    indices
+}
+
+struct Linker {
+    module: Symbol,
+    index: ModuleIndex,
+    types: SymbolMap<TypeIndex>,
+    access: ModuleAccess,
+}
+
+impl Linker {
+    fn link(&self, interpreter: &Interpreter, def: &mut ModuleDef) -> Result<(), TriconeError> {
+        for (function, code) in bytecode_functions(def) {
+            let BytecodeFunctionDef {
+                ref mut instructions,
+                ref locals,
+                ..
+            } = *code;
+            for insn in instructions {
+                if let Some(resolved) = self.resolve(interpreter, insn, &function, locals)? {
+                    *insn = resolved;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn module_index(
+        &self,
+        interpreter: &Interpreter,
+        module: Symbol,
+        function: &str,
+    ) -> Result<Option<ModuleIndex>, TriconeError> {
+        if module == self.module {
+            return Ok(Some(self.index));
+        }
+        match interpreter.lookup_module_index(&module) {
+            Some(index) => Ok(Some(index)),
+            // Left to be refused when it runs
+            None if !self.access.permits(&module) => Ok(None),
+            // Left to be imported when it runs
+            None if interpreter.can_load_module(module.as_str()) => Ok(None),
+            None => Err(TriconeError::with_message(
+                ErrorKind::ImportError,
+                format!(
+                    "`{}` refers to module `{}`, which doesn't exist",
+                    function, module
+                ),
+            )),
+        }
+    }
+
+    fn resolve(
+        &self,
+        interpreter: &Interpreter,
+        insn: &Instruction,
+        function: &str,
+        locals: &[Symbol],
+    ) -> Result<Option<Instruction>, TriconeError> {
+        match *insn {
+            Instruction::CreateObject {
+                type_spec: (module, type_),
+                num_args,
+            } => {
+                let index = match self.module_index(interpreter, module, function)? {
+                    Some(index) => index,
+                    None => return Ok(None),
+                };
+                let resolved = if index == self.index {
+                    self.types.get(&type_).cloned()
+                } else {
+                    interpreter.lookup_type(index, &type_)
+                };
+                match resolved {
+                    Some(type_) => Ok(Some(Instruction::CreateObjectResolved { type_, num_args })),
+                    None => Err(TriconeError::with_message(
+                        ErrorKind::NameError,
+                        format!(
+                            "`{}` creates `{}::{}`, but there is no such type",
+                            function, module, type_
+                        ),
+                    )),
+                }
+            }
+            Instruction::GetModuleGlobals { name } => Ok(self
+                .module_index(interpreter, name, function)?
+                .map(|module| Instruction::GetModuleGlobalsResolved { module })),
+            Instruction::LookupName { name } => Ok(locals
+                .iter()
+                .position(|&local| local == name)
+                .map(|slot| Instruction::LoadLocal { slot })),
+            _ => Ok(None),
+        }
+    }
+}
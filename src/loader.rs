@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use asm;
+use binary;
+use interpreter::{ErrorKind, ModuleAccess, TriconeError};
+use lang;
+use moduledef::ModuleDef;
+
+pub const ASSEMBLY_EXTENSION: &str = "tasm";
+pub const BYTECODE_EXTENSION: &str = "tbc";
+
+/// Finds the definitions of modules that are imported but not registered yet.
+pub trait ModuleLoader {
+    /// Returns `Ok(None)` if the loader knows nothing about `name`.
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, TriconeError>;
+
+    /// Whether `load_module` would find `name`, without loading it. Linking asks, so that code
+    /// referring to a module nobody has fails its registration. Loaders that can't tell keep
+    /// this default, and the import fails when the code first runs instead.
+    fn has_module(&self, _name: &str) -> bool {
+        true
+    }
+}
+
+/// Loads `<name>.tri` (source), `<name>.tasm` (text assembly) or `<name>.tbc` (binary bytecode)
+/// from the first search path that has any of them.
+#[derive(Debug, Clone, Default)]
+pub struct FileModuleLoader {
+    search_paths: Vec<PathBuf>,
+    access: HashMap<String, ModuleAccess>,
+}
+
+fn import_error<S: Into<String>>(message: S) -> TriconeError {
+    TriconeError::with_message(ErrorKind::ImportError, message)
+}
+
+fn io_error(path: &Path, err: &io::Error) -> TriconeError {
+    import_error(format!("{}: {}", path.display(), err))
+}
+
+impl FileModuleLoader {
+    pub fn new() -> FileModuleLoader {
+        FileModuleLoader::default()
+    }
+
+    pub fn add_search_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.search_paths.push(path.into());
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Gives the module `name` its own access policy when it is loaded, before it is linked or
+    /// initialized.
+    pub fn set_module_access<S: Into<String>>(&mut self, name: S, access: ModuleAccess) {
+        self.access.insert(name.into(), access);
+    }
+
+    // The file `name` is loaded from
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        // Module names must not be able to name files outside of the search paths
+        if !is_valid_module_name(name) {
+            return None;
+        }
+        self.search_paths.iter().find_map(|dir| {
+            [
+                lang::SOURCE_EXTENSION,
+                ASSEMBLY_EXTENSION,
+                BYTECODE_EXTENSION,
+            ]
+            .iter()
+            .map(|ext| dir.join(format!("{}.{}", name, ext)))
+            .find(|path| path.is_file())
+        })
+    }
+
+    /// Reads a module from a source, assembly or bytecode file, going by its extension. Source
+    /// files are compiled into a module named after the file. The debug info of source and
+    /// assembly modules points at `path`; bytecode keeps whatever it was compiled with.
+    pub fn load_file(path: &Path) -> Result<ModuleDef, TriconeError> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if extension == Some(BYTECODE_EXTENSION) {
+            let file = fs::File::open(path).map_err(|err| io_error(path, &err))?;
+            binary::read_module(io::BufReader::new(file)).map_err(|err| io_error(path, &err))
+        } else if extension == Some(lang::SOURCE_EXTENSION) {
+            let source = fs::read_to_string(path).map_err(|err| io_error(path, &err))?;
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| import_error(format!("{}: bad file name", path.display())))?;
+            let mut def = lang::compile_module(name, &source)
+                .map_err(|err| import_error(format!("{}:{}", path.display(), err)))?;
+            def.set_source_file(&path.display().to_string());
+            Ok(def)
+        } else {
+            let source = fs::read_to_string(path).map_err(|err| io_error(path, &err))?;
+            let mut def = asm::parse_module(&source)
+                .map_err(|err| import_error(format!("{}:{}", path.display(), err)))?;
+            def.set_source_file(&path.display().to_string());
+            Ok(def)
+        }
+    }
+}
+
+fn is_valid_module_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+impl ModuleLoader for FileModuleLoader {
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, TriconeError> {
+        let path = match self.find(name) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut def = FileModuleLoader::load_file(&path)?;
+        if def.name != name {
+            return Err(import_error(format!(
+                "{} defines module `{}`, expected `{}`",
+                path.display(),
+                def.name,
+                name
+            )));
+        }
+        def.access = self.access.get(name).cloned();
+        Ok(Some(def))
+    }
+
+    fn has_module(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+}
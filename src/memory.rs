@@ -0,0 +1,48 @@
+use std::mem;
+
+use function::Function;
+use interpreter::{Object, ObjectToken};
+use symbol::Symbol;
+
+/// Approximate bytes an object costs before any members or payload are added.
+pub const OBJECT_SIZE: usize = mem::size_of::<Object>() + 2 * mem::size_of::<usize>();
+
+/// Approximate bytes a single entry in an object's members map costs. Names are interned and
+/// shared, and counted once in `MemoryUsage::name_bytes`.
+pub const MEMBER_SIZE: usize = mem::size_of::<(Symbol, ObjectToken)>();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Objects created through `Interpreter::create_object` that are still alive.
+    pub live_objects: usize,
+    /// Approximate bytes held by those objects: the objects themselves, their members maps
+    /// and their native payloads.
+    pub live_bytes: usize,
+    /// Bytes of the names the registered code uses. Names stay interned as long as the process
+    /// runs, so these are never given back.
+    pub name_bytes: usize,
+}
+
+impl MemoryUsage {
+    /// What the memory limit applies to.
+    pub fn total_bytes(&self) -> usize {
+        self.live_bytes + self.name_bytes
+    }
+}
+
+/// Heap memory owned by a native payload, on top of the object's `data` buffer.
+pub trait HeapSize {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for i64 {}
+impl HeapSize for bool {}
+impl HeapSize for Function {}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
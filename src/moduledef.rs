@@ -1,7 +1,12 @@
+use debuginfo::DebugInfo;
 use function::*;
 use interpreter::*;
+use link;
+use optimize;
+use symbol::Symbol;
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
 pub struct TypeDef {
     pub methods: HashMap<String, FunctionDef>,
@@ -10,11 +15,35 @@ pub struct TypeDef {
 pub struct BytecodeFunctionDef {
     pub arity: usize,
     pub instructions: Vec<Instruction>,
+    pub debug_info: Option<DebugInfo>,
+    /// The names of its local slots, by slot.
+    pub locals: Vec<Symbol>,
 }
 
 pub struct NativeFunctionDef {
-    pub arity: usize,
-    pub code: Box<Fn(&mut Interpreter, &[ObjectToken]) -> Option<ObjectToken> + 'static>,
+    pub(crate) arity: usize,
+    /// Takes any number of arguments past `arity`.
+    pub(crate) variadic: bool,
+    pub(crate) code: Box<NativeFn>,
+}
+
+impl NativeFunctionDef {
+    pub fn new<F>(arity: usize, code: F) -> NativeFunctionDef
+    where
+        F: Fn(&mut Interpreter, &[ObjectToken]) -> NativeResult + 'static,
+    {
+        NativeFunctionDef {
+            arity,
+            variadic: false,
+            code: Box::new(code),
+        }
+    }
+
+    /// Lets the function take any number of arguments past its arity.
+    pub fn variadic(mut self) -> NativeFunctionDef {
+        self.variadic = true;
+        self
+    }
 }
 
 pub enum FunctionDef {
@@ -23,44 +52,160 @@ pub enum FunctionDef {
 }
 
 impl FunctionDef {
-    fn into_function(self, scope: Scope) -> Function {
+    pub(crate) fn into_function(self, module: ModuleIndex, scope: Scope) -> Function {
         match self {
             FunctionDef::Bytecode(def) => Function::from_code(
-                Code::create(def.instructions),
+                Code::Bytecode(Rc::new(
+                    Bytecode::new(def.instructions, Some(module), def.debug_info)
+                        .with_locals(def.locals),
+                )),
                 def.arity,
                 scope,
             ),
-            FunctionDef::Native(def) => Function::from_boxed_fn(def.code, def.arity, scope),
+            FunctionDef::Native(def) => {
+                let function = Function::from_boxed_fn(def.code, def.arity, scope);
+                if def.variadic {
+                    function.with_variadic_args()
+                } else {
+                    function
+                }
+            }
         }
     }
 }
 
+pub(crate) fn check_init(
+    module: &str,
+    init: &Option<BytecodeFunctionDef>,
+) -> Result<(), TriconeError> {
+    match *init {
+        Some(ref init) if init.arity != 0 => Err(TriconeError::with_message(
+            ErrorKind::WrongArgumentCount,
+            format!(
+                "init function of module `{}` must take no arguments",
+                module
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
 pub struct ModuleDef {
     pub name: String,
     pub types: HashMap<String, TypeDef>,
     pub free_functions: HashMap<String, FunctionDef>,
+    /// Runs once after the types and free functions are registered, with the module's globals
+    /// as its scope.
+    pub init: Option<BytecodeFunctionDef>,
+    /// If set, code outside the module only sees these globals.
+    pub exports: Option<Vec<String>>,
+    /// The access policy for the module's own code, its initializer included, or `None` for the
+    /// interpreter's default. It is up to the host, so bytecode files don't store it.
+    pub access: Option<ModuleAccess>,
 }
 
 impl ModuleDef {
-    pub fn register(self, interpreter: &mut Interpreter) {
+    fn bytecode_functions_mut(&mut self) -> impl Iterator<Item = &mut BytecodeFunctionDef> {
+        self.types
+            .values_mut()
+            .flat_map(|tydef| tydef.methods.values_mut())
+            .chain(self.free_functions.values_mut())
+            .filter_map(|def| match *def {
+                FunctionDef::Bytecode(ref mut def) => Some(def),
+                FunctionDef::Native(_) => None,
+            })
+            .chain(self.init.as_mut())
+    }
+
+    /// The debug info of all the module's functions that have any.
+    pub fn debug_infos(&self) -> impl Iterator<Item = &DebugInfo> {
+        self.types
+            .values()
+            .flat_map(|tydef| tydef.methods.values())
+            .chain(self.free_functions.values())
+            .filter_map(|def| match *def {
+                FunctionDef::Bytecode(ref def) => Some(def),
+                FunctionDef::Native(_) => None,
+            })
+            .chain(self.init.as_ref())
+            .filter_map(|def| def.debug_info.as_ref())
+    }
+
+    /// Points the debug info of all the module's functions at `file`.
+    pub fn set_source_file(&mut self, file: &str) {
+        for def in self.bytecode_functions_mut() {
+            if let Some(ref mut debug_info) = def.debug_info {
+                debug_info.file = file.to_owned();
+            }
+        }
+    }
+
+    /// Registers the module. Its types come first, so that linking can resolve the code's
+    /// references to them. Then the code is linked and optimized, and only then are the methods
+    /// and free functions defined and the initializer run. Modules the code refers to that
+    /// aren't registered yet are imported when the code first uses them.
+    pub fn register(self, interpreter: &mut Interpreter) -> Result<ModuleIndex, TriconeError> {
+        check_init(&self.name, &self.init)?;
+        // Until it is initialized, importing the module again is a cycle
         let name = self.name.clone();
-        interpreter.create_module(&name, move |interpreter, module| {
-            for (name, tydef) in self.types {
-                module.create_type(interpreter, &name, move |_interpreter, _module, ty| {
-                    for (name, funcdef) in tydef.methods {
-                        let scope = ty.scope().dup();
-                        ty.register_method(&name, funcdef.into_function(scope));
-                    }
-                });
+        interpreter.with_module_importing(&name, |interpreter| self.register_importing(interpreter))
+    }
+
+    fn register_importing(
+        mut self,
+        interpreter: &mut Interpreter,
+    ) -> Result<ModuleIndex, TriconeError> {
+        let exports = self.exports.take();
+        let access = self.access.take();
+        let mut type_names: Vec<_> = self.types.keys().cloned().collect();
+        type_names.sort();
+        let (index, ()) = interpreter.create_module(&self.name, |interpreter, module| {
+            module.exports =
+                exports.map(|exports| exports.iter().map(|name| Symbol::new(name)).collect());
+            module.access = access;
+            for name in &type_names {
+                module.create_type(interpreter, name, |_, _, _| {});
             }
-            for (name, funcdef) in self.free_functions {
-                let globals = module.globals.dup();
-                module.globals.assign_member(
-                    name,
-                    function_object_from_function(funcdef.into_function(globals)),
-                    interpreter,
-                );
+        })?;
+        if let Err(err) = link::link_module(interpreter, index, &mut self) {
+            interpreter.unregister_module(index);
+            return Err(err);
+        }
+        optimize::optimize_module(&mut self, interpreter.strips_diagnostics());
+
+        let ModuleDef {
+            types,
+            free_functions,
+            init,
+            ..
+        } = self;
+        for (type_name, tydef) in types {
+            let tyidx = interpreter
+                .lookup_type(index, &type_name)
+                .expect("Types are registered before linking");
+            let ty = interpreter.get_type_mut(tyidx);
+            for (name, funcdef) in tydef.methods {
+                let scope = ty.scope().dup();
+                ty.register_method(&name, funcdef.into_function(index, scope));
             }
-        });
+        }
+        for (name, funcdef) in free_functions {
+            let symbol = Symbol::new(&name);
+            let module = interpreter.get_module_mut(index);
+            module.defined_names.insert(symbol);
+            let globals = module.globals.dup();
+            let function = funcdef
+                .into_function(index, globals.dup())
+                .with_name(format!("{}::{}", module.name, name));
+            let res =
+                globals.assign_member(symbol, function_object_from_function(function), interpreter);
+            interpreter.drop_token(globals.vars);
+            res?;
+        }
+
+        if let Some(init) = init {
+            interpreter.run_module_init(index, &init)?;
+        }
+        Ok(index)
     }
 }
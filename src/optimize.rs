@@ -0,0 +1,243 @@
+//! Peephole optimizations, run over a module's code when it is registered.
+//!
+//! - A `CreateInt` or `CreateString` pair followed by a `CallMethod` of `add` (or `sub` and
+//!   `mul` for ints) that keeps its result becomes the constant it computes. Only literals are folded, so the
+//!   receiver is always the core type: a user type that overrides `add` still gets called. Int
+//!   arithmetic that overflows is left to fail when it runs.
+//! - Jumps to jumps go straight to the final target, and jumps to the next instruction go away.
+//! - Instructions that no path reaches go away.
+//! - If the interpreter strips diagnostics, `Diag` goes away and `DebugPrintObject` only pops
+//!   its operand. A `Diag` the function ends on stays, as it makes the function's result
+//!   nothing.
+//!
+//! Jump targets and debug info follow the instructions that remain.
+
+use std::mem;
+
+use debuginfo::DebugInfo;
+use interpreter::Instruction;
+use link;
+use moduledef::{BytecodeFunctionDef, ModuleDef};
+use symbol::Symbol;
+
+pub(crate) fn optimize_module(def: &mut ModuleDef, strip_diagnostics: bool) {
+    for (_, function) in link::bytecode_functions(def) {
+        optimize(function, strip_diagnostics);
+    }
+}
+
+/// Optimizes `function` until none of the optimizations finds anything more to do.
+fn optimize(function: &mut BytecodeFunctionDef, strip: bool) {
+    loop {
+        let instructions = &mut function.instructions;
+        let mut removed = vec![false; instructions.len()];
+        let mut changed = thread_jumps(instructions);
+        changed |= fold_constants(instructions, &mut removed);
+        if strip {
+            changed |= strip_diagnostics(instructions, &mut removed);
+        }
+        remove_jumps_to_next(instructions, &mut removed);
+        remove_unreachable(instructions, &mut removed);
+        if removed.contains(&true) {
+            compact(function, &removed);
+        } else if !changed {
+            return;
+        }
+    }
+}
+
+fn jump_targets(instructions: &[Instruction]) -> Vec<bool> {
+    let mut targets = vec![false; instructions.len() + 1];
+    for to in instructions.iter().filter_map(Instruction::jump_target) {
+        if let Some(target) = targets.get_mut(to) {
+            *target = true;
+        }
+    }
+    targets
+}
+
+fn thread_jumps(instructions: &mut [Instruction]) -> bool {
+    let mut changed = false;
+    for pos in 0..instructions.len() {
+        let mut to = match instructions[pos].jump_target() {
+            Some(to) => to,
+            None => continue,
+        };
+        // A cycle of jumps is an infinite loop, it can go on running as it is
+        for _ in 0..instructions.len() {
+            match instructions.get(to) {
+                Some(&Instruction::Jump { to: next }) if next != to => to = next,
+                _ => break,
+            }
+        }
+        match instructions[pos] {
+            Instruction::Jump { to: ref mut target }
+            | Instruction::JumpIfFalse { to: ref mut target }
+                if *target != to =>
+            {
+                *target = to;
+                changed = true;
+            }
+            _ => {}
+        }
+    }
+    changed
+}
+
+// The constant `op` computes from the literals `lhs` and `rhs`, if it is one
+fn fold(lhs: &Instruction, rhs: &Instruction, op: Symbol) -> Option<Instruction> {
+    match (lhs, rhs) {
+        (&Instruction::CreateInt { value: lhs }, &Instruction::CreateInt { value: rhs }) => {
+            let value = match op.as_str() {
+                "add" => lhs.checked_add(rhs)?,
+                "sub" => lhs.checked_sub(rhs)?,
+                "mul" => lhs.checked_mul(rhs)?,
+                _ => return None,
+            };
+            Some(Instruction::CreateInt { value })
+        }
+        (Instruction::CreateString { value: lhs }, Instruction::CreateString { value: rhs })
+            if op == "add" =>
+        {
+            Some(Instruction::CreateString {
+                value: format!("{}{}", lhs, rhs),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn fold_constants(instructions: &mut [Instruction], removed: &mut [bool]) -> bool {
+    let targets = jump_targets(instructions);
+    let mut changed = false;
+    let mut pos = 0;
+    while pos + 2 < instructions.len() {
+        // Code that jumps into the middle of the sequence needs it as it is
+        if targets[pos + 1] || targets[pos + 2] {
+            pos += 1;
+            continue;
+        }
+        // Removing a call that discards its result would make the instruction before the
+        // literals the code's result, which matters at the end of a function
+        let name = match instructions[pos + 2] {
+            Instruction::CallMethod {
+                name,
+                num_args: 1,
+                use_result: true,
+            } => name,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+        match fold(&instructions[pos], &instructions[pos + 1], name) {
+            Some(constant) => {
+                instructions[pos] = constant;
+                removed[pos + 1] = true;
+                removed[pos + 2] = true;
+                changed = true;
+                pos += 3;
+            }
+            None => pos += 1,
+        }
+    }
+    changed
+}
+
+// Whether the function ends right after the instruction at `pos`, perhaps through jumps,
+// which leave the result as it is
+fn ends_after(instructions: &[Instruction], pos: usize) -> bool {
+    let mut next = pos + 1;
+    for _ in 0..instructions.len() {
+        match instructions.get(next) {
+            Some(&Instruction::Jump { to }) => next = to,
+            Some(_) => return false,
+            None => return true,
+        }
+    }
+    false
+}
+
+fn strip_diagnostics(instructions: &mut [Instruction], removed: &mut [bool]) -> bool {
+    let mut changed = false;
+    for pos in 0..instructions.len() {
+        match instructions[pos] {
+            Instruction::Diag if !ends_after(instructions, pos) => removed[pos] = true,
+            Instruction::DebugPrintObject => {
+                instructions[pos] = Instruction::Pop;
+                changed = true;
+            }
+            _ => {}
+        }
+    }
+    changed
+}
+
+fn remove_jumps_to_next(instructions: &[Instruction], removed: &mut [bool]) {
+    for (pos, insn) in instructions.iter().enumerate() {
+        if let Instruction::Jump { to } = *insn {
+            if to == pos + 1 {
+                removed[pos] = true;
+            }
+        }
+    }
+}
+
+fn remove_unreachable(instructions: &[Instruction], removed: &mut [bool]) {
+    let mut reachable = vec![false; instructions.len()];
+    let mut pending = vec![0];
+    while let Some(pos) = pending.pop() {
+        if pos >= instructions.len() || reachable[pos] {
+            continue;
+        }
+        reachable[pos] = true;
+        match instructions[pos] {
+            Instruction::Jump { to } => pending.push(to),
+            Instruction::JumpIfFalse { to } => pending.extend([to, pos + 1]),
+            _ => pending.push(pos + 1),
+        }
+    }
+    for (pos, reachable) in reachable.into_iter().enumerate() {
+        if !reachable {
+            removed[pos] = true;
+        }
+    }
+}
+
+// Drops the removed instructions. Jumps to one of them go to the next one that remains.
+fn compact(function: &mut BytecodeFunctionDef, removed: &[bool]) {
+    let mut new_pos = Vec::with_capacity(removed.len() + 1);
+    let mut kept = 0;
+    for &removed in removed {
+        new_pos.push(kept);
+        if !removed {
+            kept += 1;
+        }
+    }
+    new_pos.push(kept);
+
+    let instructions = mem::take(&mut function.instructions);
+    function.instructions = instructions
+        .into_iter()
+        .zip(removed)
+        .filter(|&(_, &removed)| !removed)
+        .map(|(mut insn, _)| {
+            if let Instruction::Jump { ref mut to } | Instruction::JumpIfFalse { ref mut to } = insn
+            {
+                *to = new_pos.get(*to).copied().unwrap_or(kept);
+            }
+            insn
+        })
+        .collect();
+
+    if let Some(ref mut debug_info) = function.debug_info {
+        let mut remapped = DebugInfo::new(mem::take(&mut debug_info.file));
+        for entry in &debug_info.lines {
+            match new_pos.get(entry.instruction) {
+                Some(&pos) if pos < kept => remapped.add(pos, entry.line, entry.column),
+                _ => {}
+            }
+        }
+        *debug_info = remapped;
+    }
+}
@@ -0,0 +1,93 @@
+//! Sinks for what programs print.
+//!
+//! Everything the interpreter prints goes to `Interpreter::stdout` or `Interpreter::stderr`,
+//! which are the process's streams unless the host sets others. Any `Write` will do; these
+//! cover the usual cases of keeping the output and of handing it on a line at a time.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+/// Keeps everything written to it. Clones share the buffer, so the host can keep one and give
+/// the interpreter another.
+#[derive(Debug, Clone, Default)]
+pub struct BufferSink(Rc<RefCell<Vec<u8>>>);
+
+impl BufferSink {
+    pub fn new() -> BufferSink {
+        BufferSink::default()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    /// The contents as text, with invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Empties the buffer, returning what it had.
+    pub fn take(&self) -> Vec<u8> {
+        mem::take(&mut *self.0.borrow_mut())
+    }
+}
+
+impl Write for BufferSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Calls a function with every complete line written to it, without the line break. Flushing
+/// (or dropping) the sink passes on an unfinished last line as well.
+pub struct LineSink<F: FnMut(&str)> {
+    callback: F,
+    pending: Vec<u8>,
+}
+
+impl<F: FnMut(&str)> LineSink<F> {
+    pub fn new(callback: F) -> LineSink<F> {
+        LineSink {
+            callback,
+            pending: vec![],
+        }
+    }
+
+    fn emit(&mut self, end: usize) {
+        let line: Vec<u8> = self.pending.drain(..end).collect();
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        (self.callback)(&String::from_utf8_lossy(line));
+    }
+}
+
+impl<F: FnMut(&str)> Write for LineSink<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(pos) = self.pending.iter().position(|&byte| byte == b'\n') {
+            self.emit(pos + 1);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let end = self.pending.len();
+            self.emit(end);
+        }
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&str)> Drop for LineSink<F> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,249 @@
+//! Counting where a program spends its time.
+//!
+//! A `Profiler` set with `Interpreter::start_profiling` counts the instructions run, by kind and
+//! by function, and times every function call. Every `sample_interval` instructions it also
+//! samples the active calls, which `Profile::write_folded` writes in the folded-stack format
+//! flamegraph tools read.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use interpreter::InstructionKind;
+
+const ANONYMOUS: &str = "<anonymous>";
+// Instructions run outside of any function call, like a repl line
+const TOP_LEVEL: &str = "<top>";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Instructions run by the function itself.
+    pub instructions: u64,
+    /// Instructions run by the function and everything it called.
+    pub inclusive_instructions: u64,
+    pub time: Duration,
+    pub inclusive_time: Duration,
+}
+
+struct ActiveCall {
+    function: Rc<str>,
+    start: Instant,
+    start_instructions: u64,
+    children_time: Duration,
+}
+
+pub struct Profiler {
+    sample_interval: u64,
+    until_sample: u64,
+    instructions: u64,
+    instruction_counts: [u64; InstructionKind::COUNT],
+    functions: HashMap<Rc<str>, FunctionProfile>,
+    calls: Vec<ActiveCall>,
+    samples: HashMap<Vec<Rc<str>>, u64>,
+    top_level: Rc<str>,
+    anonymous: Rc<str>,
+}
+
+impl Profiler {
+    /// Samples the active calls before every instruction.
+    pub fn new() -> Profiler {
+        Profiler::with_sample_interval(1)
+    }
+
+    pub fn with_sample_interval(sample_interval: u64) -> Profiler {
+        let sample_interval = sample_interval.max(1);
+        Profiler {
+            sample_interval,
+            until_sample: 1,
+            instructions: 0,
+            instruction_counts: [0; InstructionKind::COUNT],
+            functions: HashMap::new(),
+            calls: vec![],
+            samples: HashMap::new(),
+            top_level: TOP_LEVEL.into(),
+            anonymous: ANONYMOUS.into(),
+        }
+    }
+
+    fn current_function(&self) -> &Rc<str> {
+        self.calls
+            .last()
+            .map_or(&self.top_level, |call| &call.function)
+    }
+
+    pub(crate) fn instruction(&mut self, kind: InstructionKind) {
+        self.instructions += 1;
+        self.instruction_counts[kind as usize] += 1;
+        let function = self.current_function().clone();
+        self.functions
+            .entry(function.clone())
+            .or_insert_with(|| FunctionProfile {
+                name: function.to_string(),
+                ..FunctionProfile::default()
+            })
+            .instructions += 1;
+
+        self.until_sample -= 1;
+        if self.until_sample == 0 {
+            self.until_sample = self.sample_interval;
+            let stack = if self.calls.is_empty() {
+                vec![self.top_level.clone()]
+            } else {
+                self.calls
+                    .iter()
+                    .map(|call| call.function.clone())
+                    .collect()
+            };
+            *self.samples.entry(stack).or_insert(0) += self.sample_interval;
+        }
+    }
+
+    pub(crate) fn enter(&mut self, function: Option<&Rc<str>>) {
+        let function = function.unwrap_or(&self.anonymous).clone();
+        self.calls.push(ActiveCall {
+            function,
+            start: Instant::now(),
+            start_instructions: self.instructions,
+            children_time: Duration::default(),
+        });
+    }
+
+    pub(crate) fn exit(&mut self) {
+        let call = match self.calls.pop() {
+            Some(call) => call,
+            None => return,
+        };
+        let time = call.start.elapsed();
+        let instructions = self.instructions - call.start_instructions;
+        if let Some(caller) = self.calls.last_mut() {
+            caller.children_time += time;
+        }
+        // A recursive call is already counted by the outermost one
+        let recursive = self
+            .calls
+            .iter()
+            .any(|active| active.function == call.function);
+
+        let profile = self
+            .functions
+            .entry(call.function.clone())
+            .or_insert_with(|| FunctionProfile {
+                name: call.function.to_string(),
+                ..FunctionProfile::default()
+            });
+        profile.calls += 1;
+        profile.time += time.saturating_sub(call.children_time);
+        if !recursive {
+            profile.inclusive_time += time;
+            profile.inclusive_instructions += instructions;
+        }
+    }
+
+    /// Ends the calls still active and returns what was counted.
+    pub fn finish(mut self) -> Profile {
+        while !self.calls.is_empty() {
+            self.exit();
+        }
+        if let Some(top) = self.functions.get_mut(TOP_LEVEL) {
+            top.inclusive_instructions = self.instructions;
+        }
+
+        let mut functions: Vec<_> = self.functions.into_values().collect();
+        functions.sort_by(|a, b| {
+            b.time
+                .cmp(&a.time)
+                .then(b.instructions.cmp(&a.instructions))
+                .then(a.name.cmp(&b.name))
+        });
+        let mut samples: Vec<_> = self
+            .samples
+            .into_iter()
+            .map(|(stack, count)| (stack.join(";"), count))
+            .collect();
+        samples.sort();
+        Profile {
+            instructions: self.instructions,
+            instruction_counts: self.instruction_counts,
+            functions,
+            samples,
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+pub struct Profile {
+    pub instructions: u64,
+    instruction_counts: [u64; InstructionKind::COUNT],
+    /// By time spent in the function itself, the most first.
+    pub functions: Vec<FunctionProfile>,
+    /// Call stacks, outermost first and joined by `;`, with the instructions sampled in them.
+    pub samples: Vec<(String, u64)>,
+}
+
+impl Profile {
+    pub fn instruction_count(&self, kind: InstructionKind) -> u64 {
+        self.instruction_counts[kind as usize]
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Writes one `stack count` line per sampled call stack, for `flamegraph.pl` and the like.
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (stack, count) in &self.samples {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "{} instructions", self.instructions)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>8} {:>12} {:>12} {:>10} {:>10}  function",
+            "calls", "self insns", "total insns", "self ms", "total ms"
+        )?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "{:>8} {:>12} {:>12} {:>10.3} {:>10.3}  {}",
+                function.calls,
+                function.instructions,
+                function.inclusive_instructions,
+                millis(function.time),
+                millis(function.inclusive_time),
+                function.name
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:>12}  instruction", "count")?;
+        let mut kinds: Vec<_> = InstructionKind::ALL
+            .iter()
+            .map(|&kind| (self.instruction_count(kind), kind))
+            .filter(|&(count, _)| count > 0)
+            .collect();
+        kinds.sort_by_key(|&(count, _)| Reverse(count));
+        for (count, kind) in kinds {
+            writeln!(f, "{:>12}  {:?}", count, kind)?;
+        }
+        Ok(())
+    }
+}
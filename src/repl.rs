@@ -0,0 +1,354 @@
+//! An interactive prompt for tricone assembly.
+//!
+//! Every line is assembled and run right away in the globals of a `repl` module, so whatever it
+//! assigns is visible to later lines. The result of a line is printed with its `tostring` method
+//! and stored in `_`. A line ending in `\` continues on the next one, so several instructions
+//! can share the operation stack:
+//!
+//! ```text
+//! > create_int 1 \
+//! . create_int 2 \
+//! . call_method add 1 keep
+//! 3
+//! > lookup_name _
+//! 3
+//! ```
+//!
+//! `fn` and `type` blocks are added to the `repl` module, which is recompiled and reloaded with
+//! every new block; existing objects keep working with the new methods. Lines starting with `:`
+//! are meta-commands, see `:help`.
+
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use asm;
+use function::{self, Code};
+use interpreter::*;
+use moduledef::ModuleDef;
+use symbol::Symbol;
+
+pub const MODULE_NAME: &str = "repl";
+
+const HELP: &str = "\
+:modules            list registered modules
+:types <module>     list the types of a module
+:methods <type>     list the methods of `Type` or `module.Type`
+:scope              list the names defined at the prompt
+:disasm <fn>        disassemble `name`, `module.name` or `module.Type.method`
+:stats              show execution counters since the start or the last `:stats reset`
+:help               show this message
+:quit               leave the repl";
+
+pub struct Repl {
+    interpreter: Interpreter,
+    module: ModuleIndex,
+    // The latest source of every `fn` and `type` block, keyed by its first line's first two
+    // words so that redefining something replaces it
+    definitions: Vec<(String, String)>,
+    // Lines of an unfinished block or `\\`-continued line
+    pending: String,
+    depth: usize,
+}
+
+fn block_depth_change(line: &str) -> isize {
+    match line.split_whitespace().next() {
+        Some("fn") | Some("type") | Some("method") => 1,
+        Some("end") => -1,
+        _ => 0,
+    }
+}
+
+fn write_runtime_error<W: Write>(out: &mut W, err: &TriconeError) -> io::Result<()> {
+    writeln!(out, "error: {}", err)?;
+    for entry in &err.traceback {
+        writeln!(out, "  {}", entry)?;
+    }
+    Ok(())
+}
+
+impl Repl {
+    pub fn new(mut interpreter: Interpreter) -> Result<Repl, TriconeError> {
+        interpreter.set_tracing(false);
+        let module = ModuleDef {
+            name: MODULE_NAME.to_owned(),
+            types: Default::default(),
+            free_functions: Default::default(),
+            init: None,
+            exports: None,
+            access: None,
+        }
+        .register(&mut interpreter)?;
+        Ok(Repl {
+            interpreter,
+            module,
+            definitions: vec![],
+            pending: String::new(),
+            depth: 0,
+        })
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Whether the next line continues an unfinished block or line.
+    pub fn is_continuing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Reads lines from `input` until it ends or `:quit` is entered.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut out: W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(out, "{}", if self.is_continuing() { ". " } else { "> " })?;
+            out.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            if !self.handle_line(&line, &mut out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles one line of input, returning `false` if the repl should stop.
+    pub fn handle_line<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let trimmed = line.trim();
+        if !self.is_continuing() && trimmed.starts_with(':') {
+            return self.meta_command(trimmed, out);
+        }
+
+        if self.depth > 0 || (!self.is_continuing() && block_depth_change(trimmed) > 0) {
+            self.pending.push_str(line);
+            self.pending.push('\n');
+            self.depth = (self.depth as isize + block_depth_change(trimmed)) as usize;
+            if self.depth == 0 {
+                let block = mem::take(&mut self.pending);
+                self.define(&block, out)?;
+            }
+            return Ok(true);
+        }
+
+        match trimmed.strip_suffix('\\') {
+            Some(line) => {
+                self.pending.push_str(line);
+                self.pending.push('\n');
+            }
+            None => {
+                self.pending.push_str(trimmed);
+                let source = mem::take(&mut self.pending);
+                self.eval(&source, out)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn eval<W: Write>(&mut self, source: &str, out: &mut W) -> io::Result<()> {
+        let code = match asm::parse_instructions(source) {
+            Ok(code) => code,
+            Err(err) => return writeln!(out, "error: {}", err),
+        };
+        if code.is_empty() {
+            return Ok(());
+        }
+
+        let module = self.module;
+        let globals = self.interpreter.get_module(module).globals.dup();
+        let res = self.interpreter.with_module_context(Some(module), |i| {
+            i.with_frame_in_scope(globals, |i| {
+                i.set_frame_function(Some("<repl>".into()), 0);
+                i.run_code(&code)
+            })
+        });
+        let obj = match res {
+            Ok(Some(obj)) => obj,
+            Ok(None) => return Ok(()),
+            Err(err) => return write_runtime_error(out, &err),
+        };
+        if obj.type_index() == consts::UNIT_TYPE_ID {
+            self.interpreter.drop_token(obj);
+            return Ok(());
+        }
+
+        let text = self.interpreter.display_object(&obj);
+        let globals = self.interpreter.get_module(module).globals.dup();
+        let res = globals.assign_member(sym!("_"), obj, &mut self.interpreter);
+        self.interpreter.drop_token(globals.vars);
+        if let Err(err) = res {
+            return write_runtime_error(out, &err);
+        }
+        match text {
+            Ok(text) => writeln!(out, "{}", text),
+            Err(err) => write_runtime_error(out, &err),
+        }
+    }
+
+    fn define<W: Write>(&mut self, block: &str, out: &mut W) -> io::Result<()> {
+        let key = block
+            .split_whitespace()
+            .take(2)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut definitions: Vec<_> = self
+            .definitions
+            .iter()
+            .filter(|(other, _)| *other != key)
+            .cloned()
+            .collect();
+        definitions.push((key, block.to_owned()));
+
+        let mut source = format!("module {}\n", MODULE_NAME);
+        for (_, block) in &definitions {
+            source.push_str(block);
+        }
+        let def = match asm::parse_module(&source) {
+            Ok(def) => def,
+            Err(err) => {
+                // Report lines relative to the block, not the whole module
+                let first_line = source.lines().count() - block.lines().count() + 1;
+                return writeln!(
+                    out,
+                    "error: line {}: {}",
+                    (err.line + 1).saturating_sub(first_line),
+                    err.message
+                );
+            }
+        };
+
+        match self.interpreter.reload_module(def) {
+            Ok(report) => {
+                self.definitions = definitions;
+                for incompatibility in &report.incompatibilities {
+                    writeln!(out, "warning: {:?}", incompatibility)?;
+                }
+                Ok(())
+            }
+            Err(err) => write_runtime_error(out, &err),
+        }
+    }
+
+    fn meta_command<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arg = words.next();
+        match (command, arg) {
+            (":quit", None) | (":q", None) => return Ok(false),
+            (":help", None) => writeln!(out, "{}", HELP)?,
+            (":modules", None) => {
+                for module in self.interpreter.modules() {
+                    writeln!(
+                        out,
+                        "{:>3} {} ({} types)",
+                        module.index.0,
+                        module.name,
+                        module.types.iter().filter(|ty| !ty.is_removed()).count()
+                    )?;
+                }
+            }
+            (":types", Some(name)) => self.list_types(name, out)?,
+            (":methods", Some(name)) => self.list_methods(name, out)?,
+            (":scope", None) => self.list_scope(out)?,
+            (":disasm", Some(name)) => self.disassemble(name, out)?,
+            (":stats", None) => write!(out, "{}", self.interpreter.stats())?,
+            (":stats", Some("reset")) => self.interpreter.reset_stats(),
+            _ => writeln!(out, "error: unknown command `{}`, try :help", line)?,
+        }
+        Ok(true)
+    }
+
+    fn list_types<W: Write>(&self, module: &str, out: &mut W) -> io::Result<()> {
+        let idx = match self.interpreter.lookup_module_index(module) {
+            Some(idx) => idx,
+            None => return writeln!(out, "error: no module named `{}`", module),
+        };
+        for ty in &self.interpreter.get_module(idx).types {
+            if !ty.is_removed() {
+                writeln!(out, "{} ({} live)", ty.name(), ty.live_instances())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn find_type(&self, name: &str) -> Option<&Type> {
+        let modules = self.interpreter.modules();
+        match name.find('.') {
+            Some(dot) => {
+                let idx = self.interpreter.lookup_module_index(&name[..dot])?;
+                let tyidx = self.interpreter.lookup_type(idx, &name[dot + 1..])?;
+                Some(self.interpreter.get_type(tyidx))
+            }
+            None => modules
+                .iter()
+                .flat_map(|module| &module.types)
+                .find(|ty| !ty.is_removed() && ty.name() == name),
+        }
+    }
+
+    fn list_methods<W: Write>(&self, name: &str, out: &mut W) -> io::Result<()> {
+        let ty = match self.find_type(name) {
+            Some(ty) => ty,
+            None => return writeln!(out, "error: no type named `{}`", name),
+        };
+        let mut methods: Vec<_> = ty.methods().iter().collect();
+        methods.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, method) in methods {
+            let kind = if method.is_native() {
+                "native"
+            } else {
+                "bytecode"
+            };
+            writeln!(out, "{}/{} ({})", name, method.arity(), kind)?;
+        }
+        Ok(())
+    }
+
+    fn list_scope<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let globals = &self.interpreter.get_module(self.module).globals;
+        let obj = globals.obj();
+        let mut names: Vec<_> = obj
+            .members
+            .iter()
+            .filter(|&(name, _)| !name.starts_with('!'))
+            .map(|(name, value)| (name, value.type_index()))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, type_) in names {
+            writeln!(out, "{}: {}", name, self.interpreter.get_type(type_).name())?;
+        }
+        Ok(())
+    }
+
+    fn disassemble<W: Write>(&self, path: &str, out: &mut W) -> io::Result<()> {
+        let parts: Vec<_> = path.split('.').collect();
+        let found = match parts[..] {
+            [name] => self.global_code(self.module, name),
+            [module, name] => self
+                .interpreter
+                .lookup_module_index(module)
+                .and_then(|idx| self.global_code(idx, name)),
+            [module, ty, method] => self
+                .find_type(&format!("{}.{}", module, ty))
+                .and_then(|ty| ty.methods().get(&Symbol::lookup(method)?))
+                .map(|method| method.code().clone()),
+            _ => None,
+        };
+        match found {
+            Some(Code::Bytecode(bytecode)) => {
+                write!(out, "{}", asm::disassemble(&bytecode.instructions))
+            }
+            Some(Code::Native(_)) => writeln!(out, "`{}` is a native function", path),
+            None => writeln!(out, "error: no function named `{}`", path),
+        }
+    }
+
+    fn global_code(&self, module: ModuleIndex, name: &str) -> Option<Code> {
+        let globals = self.interpreter.get_module(module).globals.obj();
+        let member = globals.members.get(&Symbol::lookup(name)?)?;
+        let obj = member.obj();
+        if obj.type_ != consts::FUNCTION_TYPE_ID {
+            return None;
+        }
+        Some(function::function_from_function_object(&obj).code().clone())
+    }
+}
@@ -0,0 +1,110 @@
+//! Execution counters that are always on.
+//!
+//! They are cheap enough to keep counting in production: one increment per instruction, object
+//! and name lookup. `Interpreter::stats` takes a snapshot and `Interpreter::reset_stats` starts
+//! over, so a script can be measured on its own.
+//!
+//! Only objects that go through `Interpreter::create_object` are counted by type. `Int`, `Bool`
+//! and `Unit` values are immediates, which are never allocated or dropped, and the `Function`
+//! objects a module gets for its functions are made once when it is registered; programs that
+//! make many numbers show them as `CreateInt` instructions and method calls instead.
+
+use std::cmp::Reverse;
+use std::fmt;
+
+use interpreter::{InstructionKind, TypeIndex};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Counters {
+    pub instructions: [u64; InstructionKind::COUNT],
+    pub name_lookups: u64,
+    pub scope_misses: u64,
+    pub failed_lookups: u64,
+    pub inline_cache_hits: u64,
+    pub inline_cache_misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeStats {
+    pub index: TypeIndex,
+    /// Like `module::Type`.
+    pub name: String,
+    pub created: u64,
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    instructions: [u64; InstructionKind::COUNT],
+    /// The types that had objects created or dropped, in index order. Immediates and the
+    /// functions of modules aren't counted.
+    pub types: Vec<TypeStats>,
+    /// `LookupName` instructions run.
+    pub name_lookups: u64,
+    /// Scopes searched without finding the name, over all lookups. Each one is a step up the
+    /// parent chain.
+    pub scope_misses: u64,
+    /// Lookups of names that weren't defined at all.
+    pub failed_lookups: u64,
+    /// Inline caches of `LookupName`, `GetMember` and `CallMethod` that had the answer.
+    pub inline_cache_hits: u64,
+    pub inline_cache_misses: u64,
+}
+
+impl Stats {
+    pub(crate) fn new(counters: &Counters, types: Vec<TypeStats>) -> Stats {
+        Stats {
+            instructions: counters.instructions,
+            types,
+            name_lookups: counters.name_lookups,
+            scope_misses: counters.scope_misses,
+            failed_lookups: counters.failed_lookups,
+            inline_cache_hits: counters.inline_cache_hits,
+            inline_cache_misses: counters.inline_cache_misses,
+        }
+    }
+
+    pub fn instruction_count(&self, kind: InstructionKind) -> u64 {
+        self.instructions[kind as usize]
+    }
+
+    pub fn total_instructions(&self) -> u64 {
+        self.instructions.iter().sum()
+    }
+
+    pub fn type_stats(&self, index: TypeIndex) -> Option<&TypeStats> {
+        self.types.iter().find(|ty| ty.index == index)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "{:>12}  instruction", "count")?;
+        let mut kinds: Vec<_> = InstructionKind::ALL
+            .iter()
+            .map(|&kind| (self.instruction_count(kind), kind))
+            .filter(|&(count, _)| count > 0)
+            .collect();
+        kinds.sort_by_key(|&(count, _)| Reverse(count));
+        for (count, kind) in kinds {
+            writeln!(f, "{:>12}  {:?}", count, kind)?;
+        }
+        writeln!(f, "{:>12}  total", self.total_instructions())?;
+        writeln!(f)?;
+        writeln!(f, "{:>12} {:>12}  type", "created", "dropped")?;
+        for ty in &self.types {
+            writeln!(f, "{:>12} {:>12}  {}", ty.created, ty.dropped, ty.name)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} name lookups, {} scope misses, {} failed",
+            self.name_lookups, self.scope_misses, self.failed_lookups
+        )?;
+        writeln!(
+            f,
+            "{} inline cache hits, {} misses",
+            self.inline_cache_hits, self.inline_cache_misses
+        )
+    }
+}
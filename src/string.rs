@@ -1,14 +1,45 @@
+use std::cell::Ref;
+
 use generic;
 use interpreter::*;
 
 pub fn register_string_type(interpreter: &mut Interpreter, module: &mut Module) {
     generic::create_type_for::<String, _>(interpreter, module, "String", |_, _, ty| {
-        ty.register_native_method("println", 1, move |_itrp, args| {
+        assert_eq!(ty.index, consts::STRING_TYPE_ID);
+        ty.register_native_method("println", 1, move |itrp, args| {
             let target = args[0].obj();
-            println!("{}", unsafe { generic::get_unsafe_ref::<String>(&target) });
-            None
+            let text = unsafe { generic::get_unsafe_ref::<String>(&target) };
+            writeln!(itrp.stdout(), "{}", text)?;
+            Ok(None)
+        });
+        ty.register_native_method("tostring", 1, move |_itrp, args| {
+            Ok(Some(args[0].dup()))
+        });
+        ty.register_native_method("add", 2, move |itrp, args| {
+            let concatenated = {
+                let b = string_value(&args[1]).ok_or_else(|| {
+                    TriconeError::with_message(ErrorKind::TypeError, "expected a `String`")
+                })?;
+                let mut concatenated = string_value(&args[0]).unwrap().to_owned();
+                concatenated.push_str(&b);
+                concatenated
+            };
+            create_string(itrp, concatenated).map(Some)
         });
     });
 }
 
 define_core_creator!{create_string, String, "String"}
+
+pub fn string_value(token: &ObjectToken) -> Option<Ref<'_, str>> {
+    let obj = match *token {
+        ObjectToken::Heap(ref obj) => obj.borrow(),
+        ObjectToken::Immediate(_) => return None,
+    };
+    if obj.type_ != consts::STRING_TYPE_ID {
+        return None;
+    }
+    Some(Ref::map(obj, |obj| unsafe {
+        generic::get_unsafe_ref::<String>(obj).as_str()
+    }))
+}
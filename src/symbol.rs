@@ -0,0 +1,153 @@
+//! Interned names.
+//!
+//! Instructions, member maps and method tables name things with `Symbol`s, interned once when
+//! code is assembled, compiled or loaded. A symbol compares and hashes by address, so looking
+//! one up in a `SymbolMap` never touches its characters.
+//!
+//! The interner is shared by the whole process and interned strings are never freed. Code is
+//! usually built before there is an interpreter to run it, `sym!` keeps the symbols of literals
+//! in statics, and code may be registered with several interpreters, so a symbol has to mean the
+//! same name everywhere and for as long as anything might hold it. Freeing names would need
+//! every instruction, member map and method table to hold its symbols by reference count, which
+//! would cost every lookup. Instead, the table only grows with names that code uses: running
+//! code never interns anything, and registering code charges the names it uses to the
+//! interpreter's memory limit (see `MemoryUsage::name_bytes`), so a host that caps the memory
+//! of untrusted code caps the names it can add too. Only going from strings to symbols, when
+//! code is built or a host looks something up by name, takes the interner's lock.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+/// The symbol for a string literal, interned the first time the expression runs.
+#[macro_export]
+macro_rules! sym {
+    ($name:expr) => {{
+        static SYMBOL: ::std::sync::OnceLock<$crate::symbol::Symbol> = ::std::sync::OnceLock::new();
+        *SYMBOL.get_or_init(|| $crate::symbol::Symbol::new($name))
+    }};
+}
+
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+fn interner() -> MutexGuard<'static, HashSet<&'static str>> {
+    static INTERNER: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    INTERNER
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Symbol {
+    pub fn new(name: &str) -> Symbol {
+        let mut interner = interner();
+        if let Some(&name) = interner.get(name) {
+            return Symbol(name);
+        }
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        interner.insert(name);
+        Symbol(name)
+    }
+
+    /// The symbol for `name`, if it has been interned. Nothing can be named by a string that
+    /// never was, so lookups by string can stop here.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        interner().get(name).map(|&name| Symbol(name))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::new(name)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.0.as_ptr() == other.0.as_ptr()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.0.as_ptr() as usize)
+    }
+}
+
+/// By name, which agrees with equality as every name is interned once.
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        self.0.cmp(other.0)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(self.0, f)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+/// Hashes the address of a symbol with a single multiplication.
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(u64::from(byte));
+        }
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        // Addresses are aligned, fold the well-mixed high bits into the low ones tables index by
+        let mixed = (self.0 ^ value).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        self.0 = mixed ^ (mixed >> 32);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+pub type SymbolSet = HashSet<Symbol, BuildHasherDefault<SymbolHasher>>;
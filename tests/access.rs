@@ -0,0 +1,154 @@
+extern crate tricone;
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use common::{call, eval, eval_err, interpreter, register, run};
+use tricone::asm;
+use tricone::interpreter::{ErrorKind, ModuleAccess};
+use tricone::loader::FileModuleLoader;
+use tricone::Interpreter;
+
+const SECRET: &str = "
+module secret
+init
+    create_int 7
+    assign value
+end
+";
+
+const PEEK: &str = "
+module peek
+fn secret 0
+    get_module_globals secret
+    get_member value
+end
+fn own 0
+    get_module_globals peek
+    get_member secret
+end
+";
+
+fn with_secret() -> Interpreter {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, SECRET);
+    itrp
+}
+
+// Registers a module written in assembly under its own access policy
+fn register_with_access(
+    itrp: &mut Interpreter,
+    source: &str,
+    access: ModuleAccess,
+) -> Result<(), ErrorKind> {
+    let mut def = asm::parse_module(source).unwrap();
+    def.access = Some(access);
+    def.register(itrp).map(|_| ()).map_err(|err| err.kind)
+}
+
+#[test]
+fn the_default_policy_applies_to_host_code() {
+    let mut itrp = with_secret();
+    itrp.set_default_module_access(ModuleAccess::allow(vec!["builtins"]));
+    assert!(matches!(
+        eval_err(&mut itrp, "get_module_globals secret\nget_member value"),
+        ErrorKind::AccessDenied
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_object secret Nothing 0"),
+        ErrorKind::AccessDenied
+    ));
+    assert_eq!(
+        eval(&mut itrp, "get_module_globals builtins\nget_member if"),
+        "<Function>"
+    );
+}
+
+#[test]
+fn a_module_policy_restricts_its_own_code() {
+    let mut itrp = with_secret();
+    register_with_access(&mut itrp, PEEK, ModuleAccess::allow(Vec::<String>::new())).unwrap();
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "import peek\nget_member secret\ncall_function_object 0 keep"
+        ),
+        ErrorKind::AccessDenied
+    ));
+    // Its own module is always in reach
+    assert_eq!(call(&mut itrp, "peek", "own"), "<Function>");
+}
+
+#[test]
+fn a_module_policy_overrides_the_default() {
+    let mut itrp = with_secret();
+    itrp.set_default_module_access(ModuleAccess::allow(vec!["peek"]));
+    register_with_access(&mut itrp, PEEK, ModuleAccess::Unrestricted).unwrap();
+    assert_eq!(call(&mut itrp, "peek", "secret"), "7");
+    assert!(matches!(
+        eval_err(&mut itrp, "get_module_globals secret"),
+        ErrorKind::AccessDenied
+    ));
+}
+
+#[test]
+fn a_restricted_init_is_refused() {
+    let mut itrp = with_secret();
+    let res = register_with_access(
+        &mut itrp,
+        "
+module nosy
+init
+    get_module_globals secret
+    get_member value
+    assign stolen
+end
+",
+        ModuleAccess::allow(Vec::<String>::new()),
+    );
+    assert!(matches!(res, Err(ErrorKind::AccessDenied)));
+    assert!(itrp.lookup_module_index("nosy").is_none());
+
+    // The same module is fine when its policy allows it
+    register_with_access(
+        &mut itrp,
+        "
+module nosy
+init
+    get_module_globals secret
+    get_member value
+    assign stolen
+end
+",
+        ModuleAccess::allow(vec!["secret"]),
+    )
+    .unwrap();
+}
+
+#[test]
+fn a_restricted_module_does_not_import_what_it_may_not_reach() {
+    let dir = env::temp_dir().join(format!("tricone-access-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("secret.tasm"), SECRET).unwrap();
+    fs::write(dir.join("peek.tasm"), PEEK).unwrap();
+
+    let (mut itrp, _) = interpreter();
+    let mut loader = FileModuleLoader::new();
+    loader.add_search_path(&dir);
+    loader.set_module_access("peek", ModuleAccess::allow(Vec::<String>::new()));
+    itrp.set_module_loader(Box::new(loader));
+    let res = run(
+        &mut itrp,
+        "import peek\nget_member secret\ncall_function_object 0 keep",
+    );
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(matches!(res, Err(ref err) if matches!(err.kind, ErrorKind::AccessDenied)));
+    let peek = itrp.lookup_module_index("peek").unwrap();
+    assert!(itrp.get_module(peek).access.is_some());
+    assert!(itrp.lookup_module_index("secret").is_none());
+}
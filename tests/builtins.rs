@@ -0,0 +1,198 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, eval_err, interpreter, register};
+use tricone::interpreter::ErrorKind;
+use tricone::Interpreter;
+
+const CHOOSE: &str = "
+module choose
+fn yes 0
+    create_string \"yes\"
+end
+fn no 0
+    create_string \"no\"
+end
+fn pick_true 0
+    get_module_globals builtins
+    get_member if
+    create_bool true
+    lookup_name yes
+    lookup_name no
+    call_function_object 3 keep
+end
+fn pick_false 0
+    get_module_globals builtins
+    get_member if
+    create_bool false
+    lookup_name yes
+    lookup_name no
+    call_function_object 3 keep
+end
+";
+
+// Sums 0..n with `builtins.while`, keeping the state in the module's globals
+const SUM: &str = "
+module sum
+fn cond 0
+    get_module_globals sum
+    get_member i
+    get_module_globals sum
+    get_member n
+    call_method lt 1 keep
+end
+fn body 2
+    get_module_globals sum
+    get_module_globals sum
+    get_member total
+    get_module_globals sum
+    get_member i
+    call_method add 1 keep
+    set_member total
+    get_module_globals sum
+    get_module_globals sum
+    get_member i
+    create_int 1
+    call_method add 1 keep
+    set_member i
+end
+fn main 0
+    get_module_globals builtins
+    get_member while
+    lookup_name cond
+    lookup_name body
+    call_function_object 2 discard
+    get_module_globals sum
+    get_member total
+end
+init
+    create_int 0
+    assign i
+    create_int 0
+    assign total
+end
+";
+
+// Calls `if` and `while` with arguments they can't use
+const MISUSED: &str = "
+module misused
+fn yes 0
+    create_string \"yes\"
+end
+fn always 0
+    create_bool true
+end
+fn nothing 0
+end
+fn busy 2
+    create_int 1
+end
+fn rest 2
+end
+fn int_condition 0
+    get_module_globals builtins
+    get_member if
+    create_int 1
+    lookup_name yes
+    lookup_name yes
+    call_function_object 3 keep
+end
+fn int_branch 0
+    get_module_globals builtins
+    get_member if
+    create_bool false
+    lookup_name yes
+    create_int 1
+    call_function_object 3 keep
+end
+fn string_condition 0
+    get_module_globals builtins
+    get_member while
+    lookup_name yes
+    lookup_name rest
+    call_function_object 2 keep
+end
+fn empty_condition 0
+    get_module_globals builtins
+    get_member while
+    lookup_name nothing
+    lookup_name rest
+    call_function_object 2 keep
+end
+fn busy_body 0
+    get_module_globals builtins
+    get_member while
+    lookup_name always
+    lookup_name busy
+    call_function_object 2 keep
+end
+fn int_body 0
+    get_module_globals builtins
+    get_member while
+    lookup_name always
+    create_int 1
+    call_function_object 2 keep
+end
+";
+
+fn sum_to(n: i64) -> String {
+    let (mut itrp, _) = interpreter();
+    register(
+        &mut itrp,
+        &SUM.replace(
+            "init\n",
+            &format!("init\n    create_int {}\n    assign n\n", n),
+        ),
+    );
+    call(&mut itrp, "sum", "main")
+}
+
+#[test]
+fn builtin_if() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, CHOOSE);
+    assert_eq!(call(&mut itrp, "choose", "pick_true"), "yes");
+    assert_eq!(call(&mut itrp, "choose", "pick_false"), "no");
+}
+
+#[test]
+fn builtin_while() {
+    assert_eq!(sum_to(10), "45");
+    assert_eq!(sum_to(1000), "499500");
+}
+
+#[test]
+fn builtin_while_never_runs_the_body() {
+    assert_eq!(sum_to(0), "0");
+}
+
+#[test]
+fn builtins_reject_values_of_the_wrong_type() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MISUSED);
+    for function in &[
+        "int_condition",
+        "int_branch",
+        "string_condition",
+        "empty_condition",
+        "busy_body",
+        "int_body",
+    ] {
+        let source = format!(
+            "import misused\nget_member {}\ncall_function_object 0 keep",
+            function
+        );
+        assert!(
+            matches!(eval_err(&mut itrp, &source), ErrorKind::TypeError),
+            "{}",
+            function
+        );
+    }
+}
+
+#[test]
+fn builtins_are_registered() {
+    let itrp = Interpreter::new();
+    assert!(itrp.lookup_module_index("builtins").is_some());
+}
@@ -0,0 +1,78 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use tricone::asm;
+use tricone::function::NativeResult;
+use tricone::interpreter::{
+    ErrorKind, Instruction, Interpreter, ModuleIndex, ObjectToken, TriconeError,
+};
+use tricone::output::BufferSink;
+
+/// An interpreter that doesn't trace, with its stdout captured.
+pub fn interpreter() -> (Interpreter, BufferSink) {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_tracing(false);
+    let output = BufferSink::new();
+    interpreter.set_stdout(Box::new(output.clone()));
+    (interpreter, output)
+}
+
+pub fn instructions(source: &str) -> Vec<Instruction> {
+    asm::parse_instructions(source).unwrap_or_else(|err| panic!("bad assembly: {}", err))
+}
+
+/// Runs assembly as host code, in a frame of its own.
+pub fn run(interpreter: &mut Interpreter, source: &str) -> NativeResult {
+    let instructions = instructions(source);
+    let scope = interpreter.create_scope()?;
+    interpreter.with_frame_in_scope(scope, |interpreter| interpreter.run_code(&instructions))
+}
+
+/// The kind of error `res` failed with, if it did.
+pub fn kind<T>(res: Result<T, TriconeError>) -> Option<ErrorKind> {
+    res.err().map(|err| err.kind)
+}
+
+/// Displays `obj` with its `tostring` method, and drops it.
+pub fn display(interpreter: &mut Interpreter, obj: ObjectToken) -> String {
+    let text = interpreter.display_object(&obj);
+    interpreter.drop_token(obj);
+    text.unwrap_or_else(|err| panic!("displaying failed: {}", err))
+}
+
+/// Runs assembly as host code, and displays what it returns.
+pub fn eval(interpreter: &mut Interpreter, source: &str) -> String {
+    match run(interpreter, source) {
+        Ok(Some(obj)) => display(interpreter, obj),
+        Ok(None) => panic!("the code returned nothing"),
+        Err(err) => panic!("the code failed: {}", err),
+    }
+}
+
+/// Runs assembly as host code that's expected to fail, and returns the error's kind.
+pub fn eval_err(interpreter: &mut Interpreter, source: &str) -> ErrorKind {
+    match run(interpreter, source) {
+        Ok(res) => {
+            let text = res.map(|obj| display(interpreter, obj));
+            panic!("the code didn't fail, it returned {:?}", text)
+        }
+        Err(err) => err.kind,
+    }
+}
+
+/// Registers a module written in assembly.
+pub fn register(interpreter: &mut Interpreter, source: &str) -> ModuleIndex {
+    let def = asm::parse_module(source).unwrap_or_else(|err| panic!("bad assembly: {}", err));
+    def.register(interpreter)
+        .unwrap_or_else(|err| panic!("registering failed: {}", err))
+}
+
+/// Calls a free function of a registered module with no arguments, and displays its result.
+pub fn call(interpreter: &mut Interpreter, module: &str, function: &str) -> String {
+    let source = format!(
+        "import {}\nget_member {}\ncall_function_object 0 keep",
+        module, function
+    );
+    eval(interpreter, &source)
+}
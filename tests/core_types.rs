@@ -0,0 +1,193 @@
+extern crate tricone;
+
+mod common;
+
+use common::{display, eval, eval_err, interpreter, run};
+use tricone::interpreter::ErrorKind;
+
+fn int_op(a: i64, op: &str, b: i64) -> String {
+    let (mut itrp, _) = interpreter();
+    eval(
+        &mut itrp,
+        &format!(
+            "create_int {}\ncreate_int {}\ncall_method {} 1 keep",
+            a, b, op
+        ),
+    )
+}
+
+#[test]
+fn int_arithmetic() {
+    assert_eq!(int_op(2, "add", 40), "42");
+    assert_eq!(int_op(2, "sub", 40), "-38");
+    assert_eq!(int_op(-6, "mul", 7), "-42");
+    assert_eq!(int_op(i64::MAX, "sub", 1), (i64::MAX - 1).to_string());
+}
+
+#[test]
+fn int_overflow() {
+    let (mut itrp, _) = interpreter();
+    for &(a, op, b) in &[
+        (i64::MAX, "add", 1),
+        (i64::MIN, "sub", 1),
+        (i64::MAX, "mul", 2),
+        (i64::MIN, "mul", -1),
+    ] {
+        let source = format!(
+            "create_int {}\ncreate_int {}\ncall_method {} 1 keep",
+            a, b, op
+        );
+        assert!(matches!(eval_err(&mut itrp, &source), ErrorKind::Overflow));
+    }
+    let err = run(
+        &mut itrp,
+        "create_int 9223372036854775807\ncreate_int 1\ncall_method add 1 keep",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.message.as_deref(),
+        Some("`add` of 9223372036854775807 and 1 overflows")
+    );
+}
+
+#[test]
+fn int_comparisons() {
+    assert_eq!(int_op(3, "eq", 3), "true");
+    assert_eq!(int_op(3, "eq", 4), "false");
+    assert_eq!(int_op(3, "lt", 4), "true");
+    assert_eq!(int_op(4, "lt", 4), "false");
+    assert_eq!(int_op(5, "gt", 4), "true");
+    assert_eq!(int_op(4, "gt", 5), "false");
+}
+
+#[test]
+fn int_tostring() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "create_int -12\ncall_method tostring 0 keep"),
+        "-12"
+    );
+}
+
+#[test]
+fn int_type_errors() {
+    let (mut itrp, _) = interpreter();
+    for op in &["add", "sub", "mul", "eq", "lt", "gt"] {
+        let source = format!(
+            "create_int 1\ncreate_string \"1\"\ncall_method {} 1 keep",
+            op
+        );
+        assert!(matches!(eval_err(&mut itrp, &source), ErrorKind::TypeError));
+    }
+}
+
+#[test]
+fn string_add() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_string \"tri\"\ncreate_string \"cone\"\ncall_method add 1 keep"
+        ),
+        "tricone"
+    );
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "create_string \"a\"\ncreate_int 1\ncall_method add 1 keep"
+        ),
+        ErrorKind::TypeError
+    ));
+}
+
+#[test]
+fn string_tostring() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_string \"same\"\ncall_method tostring 0 keep"
+        ),
+        "same"
+    );
+}
+
+#[test]
+fn string_println() {
+    let (mut itrp, output) = interpreter();
+    let res = common::run(
+        &mut itrp,
+        "create_string \"first\"
+        call_method println 0 discard
+        create_string \"second line\"
+        call_method println 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(output.text(), "first\nsecond line\n");
+}
+
+#[test]
+fn bool_tostring() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "create_bool true\ncall_method tostring 0 keep"),
+        "true"
+    );
+}
+
+#[test]
+fn unit() {
+    let (mut itrp, _) = interpreter();
+    let unit = itrp.get_unit_object().unwrap();
+    assert_eq!(itrp.get_type(unit.type_index()).name(), "Unit");
+    assert_eq!(display(&mut itrp, unit), "<Unit>");
+}
+
+#[test]
+fn values_without_members() {
+    let (mut itrp, _) = interpreter();
+    for value in &["create_int 1", "create_bool true"] {
+        let source = format!("{}\ncreate_int 2\nset_member x", value);
+        assert!(matches!(eval_err(&mut itrp, &source), ErrorKind::TypeError));
+    }
+}
+
+#[test]
+fn create_and_drop_core_objects() {
+    let (mut itrp, _) = interpreter();
+    let core = itrp.lookup_module_index("core").unwrap();
+    let string = itrp.lookup_type(core, "String").unwrap();
+    let before = itrp.memory_usage().live_objects;
+    let obj = itrp.create_object(string, 0).unwrap();
+    assert_eq!(itrp.memory_usage().live_objects, before + 1);
+    assert_eq!(display(&mut itrp, obj), "");
+    assert_eq!(itrp.memory_usage().live_objects, before);
+
+    // Ints and Bools are values, not objects
+    let int = itrp.lookup_type(core, "Int").unwrap();
+    let obj = itrp.create_object(int, 0).unwrap();
+    assert_eq!(itrp.memory_usage().live_objects, before);
+    assert_eq!(display(&mut itrp, obj), "0");
+}
+
+#[test]
+fn functions() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "get_module_globals builtins\nget_member if"),
+        "<Function>"
+    );
+}
+
+#[test]
+fn immediates_have_no_create_or_drop() {
+    let (mut itrp, _) = interpreter();
+    for value in &["create_int 5", "create_bool true"] {
+        for method in &["create", "drop"] {
+            let source = format!("{}\ncall_method {} 0 keep", value, method);
+            let kind = eval_err(&mut itrp, &source);
+            assert!(matches!(kind, ErrorKind::NameError), "{:?}", kind);
+        }
+    }
+    assert_eq!(eval(&mut itrp, "create_object core Bool 0"), "false");
+}
@@ -0,0 +1,270 @@
+extern crate tricone;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use tricone::dap::json::Value;
+use tricone::dap::{self, read_message, write_message};
+
+const PROGRAM: &str = "type Counter {
+    fn create(self, start) { self.value = start; }
+    fn bump(self) { self.value = self.value + 1; }
+}
+fn main() {
+    let c = new Counter(5);
+    \"counting\".println();
+    c.bump();
+    c.value * 10
+}
+";
+
+/// One end of an in-memory pipe.
+struct PipeReader {
+    chunks: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+struct PipeWriter(Sender<Vec<u8>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the reader is gone"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, chunks) = mpsc::channel();
+    (
+        PipeWriter(sender),
+        PipeReader {
+            chunks,
+            chunk: vec![],
+            pos: 0,
+        },
+    )
+}
+
+/// Talks to a server running on a thread of its own, keeping every message it got.
+struct Client {
+    requests: Option<PipeWriter>,
+    messages: BufReader<PipeReader>,
+    seq: usize,
+    received: Vec<Value>,
+    server: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let (requests, input) = pipe();
+        let (output, messages) = pipe();
+        let server = thread::spawn(move || dap::serve(input, output));
+        Client {
+            requests: Some(requests),
+            messages: BufReader::new(messages),
+            seq: 0,
+            received: vec![],
+            server: Some(server),
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Vec<(&str, Value)>) {
+        self.seq += 1;
+        let request = Value::object(vec![
+            ("seq", self.seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", Value::object(arguments)),
+        ]);
+        write_message(self.requests.as_mut().unwrap(), &request).unwrap();
+    }
+
+    /// Reads messages until one matches, and returns it.
+    fn until<F: Fn(&Value) -> bool>(&mut self, what: &str, pred: F) -> Value {
+        loop {
+            let message = read_message(&mut self.messages)
+                .unwrap()
+                .unwrap_or_else(|| panic!("the server stopped before sending {}", what));
+            self.received.push(message.clone());
+            if pred(&message) {
+                return message;
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response, which must be a success.
+    fn request(&mut self, command: &str, arguments: Vec<(&str, Value)>) -> Value {
+        self.send(command, arguments);
+        let seq = self.seq;
+        let response = self.until(command, |message| {
+            message.field("type").as_str() == Some("response")
+                && message.field("request_seq").as_usize() == Some(seq)
+        });
+        assert_eq!(
+            response.field("success").as_bool(),
+            Some(true),
+            "{} failed: {}",
+            command,
+            response
+        );
+        assert_eq!(response.field("command").as_str(), Some(command));
+        response.field("body").clone()
+    }
+
+    fn event(&mut self, event: &str) -> Value {
+        self.until(event, |message| {
+            message.field("type").as_str() == Some("event")
+                && message.field("event").as_str() == Some(event)
+        })
+        .field("body")
+        .clone()
+    }
+
+    fn finish(mut self) -> Vec<Value> {
+        self.requests = None;
+        self.server.take().unwrap().join().unwrap().unwrap();
+        while let Some(message) = read_message(&mut self.messages).unwrap() {
+            self.received.push(message);
+        }
+        self.received
+    }
+}
+
+fn program_path() -> PathBuf {
+    let dir = env::temp_dir().join(format!("tricone-dap-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("counter.tri");
+    fs::write(&path, PROGRAM).unwrap();
+    path
+}
+
+fn frame_lines(body: &Value) -> Vec<(String, usize)> {
+    body.field("stackFrames")
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|frame| {
+            (
+                frame.field("name").as_str().unwrap().to_owned(),
+                frame.field("line").as_usize().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn a_scripted_session() {
+    let path = program_path();
+    let file = path.to_str().unwrap();
+    let mut client = Client::start();
+
+    let capabilities = client.request("initialize", vec![("adapterID", "tricone".into())]);
+    assert_eq!(
+        capabilities
+            .field("supportsConfigurationDoneRequest")
+            .as_bool(),
+        Some(true)
+    );
+    client.event("initialized");
+    client.request("launch", vec![("program", file.into())]);
+
+    let breakpoint = |line: usize| Value::object(vec![("line", line.into())]);
+    let body = client.request(
+        "setBreakpoints",
+        vec![
+            ("source", Value::object(vec![("path", file.into())])),
+            ("breakpoints", vec![breakpoint(3), breakpoint(100)].into()),
+        ],
+    );
+    let verified: Vec<_> = body
+        .field("breakpoints")
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|breakpoint| breakpoint.field("verified").as_bool())
+        .collect();
+    assert_eq!(verified, vec![Some(true), Some(false)]);
+
+    client.request("configurationDone", vec![]);
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.field("reason").as_str(), Some("breakpoint"));
+    let output = client
+        .received
+        .iter()
+        .find(|message| message.field("event").as_str() == Some("output"))
+        .map(|message| message.field("body").field("output").clone());
+    assert_eq!(output.as_ref().and_then(Value::as_str), Some("counting\n"));
+
+    let trace = client.request("stackTrace", vec![("threadId", 1usize.into())]);
+    let lines = frame_lines(&trace);
+    assert_eq!(
+        lines[..2],
+        [
+            ("counter::Counter::bump".to_owned(), 3),
+            ("counter::main".to_owned(), 8)
+        ]
+    );
+    let top = &trace.field("stackFrames").as_array().unwrap()[0];
+    assert_eq!(top.field("source").field("path").as_str(), Some(file));
+
+    client.request("continue", vec![("threadId", 1usize.into())]);
+    let exited = client.event("exited");
+    assert_eq!(exited.field("exitCode").as_usize(), Some(0));
+    client.event("terminated");
+    client.request("disconnect", vec![]);
+
+    let received = client.finish();
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+    let stops = received
+        .iter()
+        .filter(|message| message.field("event").as_str() == Some("stopped"))
+        .count();
+    assert_eq!(stops, 1);
+}
+
+#[test]
+fn requests_need_a_stopped_program() {
+    let mut client = Client::start();
+    client.request("initialize", vec![("adapterID", "tricone".into())]);
+    client.send("stackTrace", vec![("threadId", 1usize.into())]);
+    let response = client.until("the stackTrace response", |message| {
+        message.field("command").as_str() == Some("stackTrace")
+    });
+    assert_eq!(response.field("success").as_bool(), Some(false));
+    assert_eq!(
+        response.field("message").as_str(),
+        Some("the program is not stopped")
+    );
+    client.request("disconnect", vec![]);
+    client.finish();
+}
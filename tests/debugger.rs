@@ -0,0 +1,205 @@
+extern crate tricone;
+
+mod common;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+use common::{call, interpreter, register};
+use tricone::debugger::{Breakpoint, DebugHandler, Pause, PauseReason, Resume, SourceBreakpoint};
+use tricone::interpreter::ErrorKind;
+use tricone::lang;
+use tricone::Interpreter;
+
+const MODULE: &str = "
+module dbg
+fn inner 0
+    locals n
+    create_int 1
+    store_local 0
+    load_local 0
+    create_int 2
+    call_method add 1 keep
+end
+fn outer 0
+    get_module_globals dbg
+    get_member inner
+    call_function_object 0 keep
+    create_int 10
+    call_method mul 1 keep
+end
+";
+
+// Where the interpreter paused: the reason, the innermost function and the instruction
+type Stop = (PauseReason, Option<String>, usize);
+
+/// Records every pause and answers with the next of its resumes, then `Continue`.
+struct Script {
+    resumes: VecDeque<Resume>,
+    stops: Rc<RefCell<Vec<Stop>>>,
+}
+
+impl DebugHandler for Script {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> Resume {
+        let function = interpreter
+            .backtrace()
+            .pop()
+            .and_then(|entry| entry.function);
+        self.stops
+            .borrow_mut()
+            .push((pause.reason.clone(), function, pause.pos));
+        self.resumes.pop_front().unwrap_or(Resume::Continue)
+    }
+}
+
+fn debug(itrp: &mut Interpreter, resumes: &[Resume]) -> Rc<RefCell<Vec<Stop>>> {
+    let stops = Rc::new(RefCell::new(vec![]));
+    itrp.set_debug_handler(Some(Box::new(Script {
+        resumes: resumes.iter().cloned().collect(),
+        stops: stops.clone(),
+    })));
+    stops
+}
+
+fn positions(stops: &Rc<RefCell<Vec<Stop>>>) -> Vec<(String, usize)> {
+    stops
+        .borrow()
+        .iter()
+        .map(|(_, function, pos)| (function.clone().unwrap_or_default(), *pos))
+        .collect()
+}
+
+fn at(function: &str, pos: usize) -> (String, usize) {
+    (function.to_owned(), pos)
+}
+
+#[test]
+fn breakpoints_pause_before_their_instruction() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let stops = debug(&mut itrp, &[]);
+    let breakpoint = Breakpoint::new("dbg", "inner", 4);
+    itrp.add_breakpoint(breakpoint.clone());
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+    assert_eq!(
+        *stops.borrow(),
+        vec![(
+            PauseReason::Breakpoint(breakpoint.clone()),
+            Some("dbg::inner".to_owned()),
+            4
+        )]
+    );
+
+    assert!(itrp.remove_breakpoint(&breakpoint));
+    assert!(!itrp.remove_breakpoint(&breakpoint));
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+    assert_eq!(stops.borrow().len(), 1);
+}
+
+#[test]
+fn stepping_over_stays_in_the_frame() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let stops = debug(&mut itrp, &[Resume::StepOver, Resume::StepOver]);
+    itrp.add_breakpoint(Breakpoint::new("dbg", "outer", 1));
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+    assert_eq!(
+        positions(&stops),
+        vec![
+            at("dbg::outer", 1),
+            at("dbg::outer", 2),
+            at("dbg::outer", 3)
+        ]
+    );
+}
+
+#[test]
+fn stepping_into_enters_calls_and_stepping_out_leaves_them() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let stops = debug(
+        &mut itrp,
+        &[Resume::StepInto, Resume::StepInto, Resume::StepOut],
+    );
+    itrp.add_breakpoint(Breakpoint::new("dbg", "outer", 2));
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+    assert_eq!(
+        positions(&stops),
+        vec![
+            at("dbg::outer", 2),
+            at("dbg::inner", 0),
+            at("dbg::inner", 1),
+            at("dbg::outer", 3),
+        ]
+    );
+    assert_eq!(stops.borrow()[1].0, PauseReason::Step);
+}
+
+#[test]
+fn aborting_stops_the_program() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    debug(&mut itrp, &[Resume::Abort]);
+    itrp.add_breakpoint(Breakpoint::new("dbg", "inner", 0));
+    let kind = common::eval_err(
+        &mut itrp,
+        "import dbg\nget_member outer\ncall_function_object 0 keep",
+    );
+    assert!(matches!(kind, ErrorKind::Aborted));
+}
+
+#[test]
+fn requested_pauses_happen_at_the_next_instruction() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let stops = debug(&mut itrp, &[]);
+    itrp.debug_pause_handle().store(true, Ordering::SeqCst);
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+    assert_eq!(stops.borrow().len(), 1);
+    assert_eq!(stops.borrow()[0].0, PauseReason::Requested);
+    assert!(!itrp.debug_pause_handle().load(Ordering::SeqCst));
+}
+
+#[test]
+fn source_breakpoints_pause_at_their_line() {
+    let (mut itrp, _) = interpreter();
+    let source = "fn twice(x) {\n    let y = x + x;\n    y\n}\nfn main() { twice(21) }";
+    lang::compile_module("src", source)
+        .unwrap()
+        .register(&mut itrp)
+        .unwrap();
+    let breakpoint = SourceBreakpoint::new("src.tri", 3);
+    let resolved = itrp.resolve_source_breakpoint(&breakpoint);
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(
+        (resolved[0].module.as_str(), resolved[0].function.as_str()),
+        ("src", "twice")
+    );
+
+    let stops = debug(&mut itrp, &[]);
+    itrp.add_source_breakpoint(breakpoint.clone());
+    assert_eq!(call(&mut itrp, "src", "main"), "42");
+    assert_eq!(
+        positions(&stops),
+        vec![at("src::twice", resolved[0].instruction)]
+    );
+    assert_eq!(
+        stops.borrow()[0].0,
+        PauseReason::SourceBreakpoint(breakpoint)
+    );
+
+    itrp.clear_source_breakpoints("src.tri");
+    assert!(itrp.source_breakpoints().is_empty());
+    assert_eq!(call(&mut itrp, "src", "main"), "42");
+    assert_eq!(stops.borrow().len(), 1);
+}
+
+#[test]
+fn without_a_handler_breakpoints_are_ignored() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    itrp.add_breakpoint(Breakpoint::new("dbg", "inner", 0));
+    assert_eq!(call(&mut itrp, "dbg", "outer"), "30");
+}
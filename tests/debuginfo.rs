@@ -0,0 +1,150 @@
+extern crate tricone;
+
+mod common;
+
+use common::interpreter;
+use tricone::asm;
+use tricone::binary;
+use tricone::debuginfo::{DebugInfo, LineEntry, SourceLocation};
+use tricone::interpreter::TriconeError;
+use tricone::lang;
+use tricone::moduledef::{FunctionDef, ModuleDef};
+use tricone::Interpreter;
+
+const MODULE: &str = "
+module located
+fn fails 0
+    create_int 1
+    lookup_name nowhere
+end
+";
+
+fn function_info<'a>(def: &'a ModuleDef, name: &str) -> &'a DebugInfo {
+    match def.free_functions[name] {
+        FunctionDef::Bytecode(ref def) => def.debug_info.as_ref().expect("no debug info"),
+        FunctionDef::Native(_) => panic!("`{}` is native", name),
+    }
+}
+
+fn call_err(itrp: &mut Interpreter, module: &str, function: &str) -> TriconeError {
+    let source = format!(
+        "import {}\nget_member {}\ncall_function_object 0 keep",
+        module, function
+    );
+    match common::run(itrp, &source) {
+        Ok(res) => {
+            let text = res.map(|obj| common::display(itrp, obj));
+            panic!("the call didn't fail, it returned {:?}", text)
+        }
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn entries_cover_runs_of_instructions() {
+    let mut info = DebugInfo::new("f.tri");
+    info.add(0, 1, 1);
+    info.add(1, 1, 1);
+    info.add(2, 2, 5);
+    info.add(4, 2, 9);
+    info.add(4, 3, 1);
+    assert_eq!(info.lines.len(), 3);
+    assert_eq!(info.entry(1).map(|entry| entry.line), Some(1));
+    assert_eq!(
+        info.entry(3),
+        Some(&LineEntry {
+            instruction: 2,
+            line: 2,
+            column: 5,
+        })
+    );
+    assert_eq!(info.entry(9).map(|entry| entry.line), Some(3));
+    assert_eq!(
+        info.location(4),
+        Some(SourceLocation {
+            file: "f.tri".to_owned(),
+            line: 3,
+            column: 1,
+        })
+    );
+    assert_eq!(DebugInfo::new("empty.tri").location(0), None);
+}
+
+#[test]
+fn lines_start_at_their_first_instruction() {
+    let mut info = DebugInfo::new("f.tri");
+    info.add(0, 1, 1);
+    info.add(2, 1, 7);
+    info.add(3, 2, 1);
+    assert_eq!(info.starts_line(0), Some(1));
+    assert_eq!(info.starts_line(2), None);
+    assert_eq!(info.starts_line(3), Some(2));
+    assert_eq!(info.line_start(2), Some(3));
+    assert_eq!(info.line_start(5), None);
+}
+
+#[test]
+fn assembly_records_lines() {
+    let def = asm::parse_module(MODULE).unwrap();
+    let info = function_info(&def, "fails");
+    assert_eq!(info.file, "located.tasm");
+    assert_eq!(info.location(1).unwrap().to_string(), "located.tasm:5:5");
+}
+
+#[test]
+fn source_files_can_be_renamed() {
+    let mut def = lang::compile_module("renamed", "fn f() {\n  1\n}").unwrap();
+    assert_eq!(function_info(&def, "f").file, "renamed.tri");
+    def.set_source_file("dir/renamed.tri");
+    assert!(def.debug_infos().all(|info| info.file == "dir/renamed.tri"));
+}
+
+#[test]
+fn binary_files_keep_debug_info() {
+    let def = asm::parse_module(MODULE).unwrap();
+    let mut bytes = vec![];
+    binary::write_module(&def, &mut bytes).unwrap();
+    let read = binary::read_module(&bytes[..]).unwrap();
+    assert_eq!(function_info(&read, "fails"), function_info(&def, "fails"));
+}
+
+#[test]
+fn binary_files_of_other_versions_are_refused() {
+    let def = asm::parse_module(MODULE).unwrap();
+    let mut bytes = vec![];
+    binary::write_module(&def, &mut bytes).unwrap();
+    let other = binary::VERSION + 1;
+    bytes[4..6].copy_from_slice(&other.to_le_bytes());
+    let err = binary::read_module(&bytes[..]).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!("unsupported bytecode version {}", other)
+    );
+}
+
+#[test]
+fn errors_are_located_in_assembly() {
+    let (mut itrp, _) = interpreter();
+    common::register(&mut itrp, MODULE);
+    let err = call_err(&mut itrp, "located", "fails");
+    assert_eq!(err.location.unwrap().to_string(), "located.tasm:5:5");
+}
+
+#[test]
+fn errors_are_located_in_source() {
+    let (mut itrp, _) = interpreter();
+    let def =
+        lang::compile_module("src", "fn fails() {\n    let a = 1;\n    a + missing\n}").unwrap();
+    def.register(&mut itrp).unwrap();
+    let err = call_err(&mut itrp, "src", "fails");
+    assert!(err.to_string().contains(" at src.tri:3:"));
+    let location = err.location.unwrap();
+    assert_eq!((location.file.as_str(), location.line), ("src.tri", 3));
+}
+
+#[test]
+fn host_code_has_no_location() {
+    let (mut itrp, _) = interpreter();
+    let err = common::run(&mut itrp, "lookup_name nowhere").unwrap_err();
+    assert_eq!(err.location, None);
+}
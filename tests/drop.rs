@@ -0,0 +1,223 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, eval_err, interpreter, register, run};
+use tricone::interpreter::ErrorKind;
+use tricone::output::BufferSink;
+use tricone::Interpreter;
+
+// `Noisy` objects print their name when they are dropped
+const NOISY: &str = "
+module noisy
+type Noisy
+    method create 2
+        set_member name
+    end
+    method drop 1
+        get_member name
+        call_method println 0 discard
+    end
+end
+type Pair
+    method create 3
+        locals self first second
+        store_local 2
+        store_local 1
+        store_local 0
+        load_local 0
+        load_local 1
+        set_member first
+        load_local 0
+        load_local 2
+        set_member second
+    end
+    method drop 1
+        create_string \"pair\"
+        call_method println 0 discard
+    end
+end
+fn local_then_return 0
+    locals tmp
+    create_string \"local\"
+    create_object noisy Noisy 1
+    store_local 0
+    create_string \"returning\"
+    call_method println 0 discard
+end
+fn leave_on_stack 0
+    create_string \"left\"
+    create_object noisy Noisy 1
+    create_int 1
+end
+";
+
+fn noisy() -> (Interpreter, BufferSink) {
+    let (mut itrp, output) = interpreter();
+    register(&mut itrp, NOISY);
+    (itrp, output)
+}
+
+fn lines(output: &BufferSink) -> Vec<String> {
+    output.text().lines().map(str::to_owned).collect()
+}
+
+#[test]
+fn popped_objects_are_dropped() {
+    let (mut itrp, output) = noisy();
+    let res = run(
+        &mut itrp,
+        "create_string \"a\"
+        create_object noisy Noisy 1
+        pop
+        create_string \"after\"
+        call_method println 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(lines(&output), ["a", "after"]);
+}
+
+#[test]
+fn drop_waits_for_the_last_reference() {
+    let (mut itrp, output) = noisy();
+    let res = run(
+        &mut itrp,
+        "create_string \"shared\"
+        create_object noisy Noisy 1
+        assign x
+        lookup_name x
+        assign y
+        create_int 0
+        assign x
+        create_string \"x reassigned\"
+        call_method println 0 discard
+        create_int 0
+        assign y
+        create_string \"y reassigned\"
+        call_method println 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(lines(&output), ["x reassigned", "shared", "y reassigned"]);
+}
+
+#[test]
+fn objects_drop_before_their_members() {
+    let (mut itrp, output) = noisy();
+    let res = run(
+        &mut itrp,
+        "create_string \"first\"
+        create_object noisy Noisy 1
+        create_string \"second\"
+        create_object noisy Noisy 1
+        create_object noisy Pair 2
+        pop",
+    );
+    assert!(matches!(res, Ok(None)));
+    let mut lines = lines(&output);
+    assert_eq!(lines[0], "pair");
+    lines[1..].sort();
+    assert_eq!(lines, ["pair", "first", "second"]);
+}
+
+#[test]
+fn locals_are_dropped_when_the_function_returns() {
+    let (mut itrp, output) = noisy();
+    let res = run(
+        &mut itrp,
+        "import noisy\nget_member local_then_return\ncall_function_object 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(lines(&output), ["returning", "local"]);
+}
+
+#[test]
+fn leftover_operands_are_dropped_when_the_function_returns() {
+    let (mut itrp, output) = noisy();
+    assert_eq!(call(&mut itrp, "noisy", "leave_on_stack"), "1");
+    assert_eq!(lines(&output), ["left"]);
+}
+
+#[test]
+fn failing_code_drops_its_operands() {
+    let (mut itrp, output) = noisy();
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "create_string \"doomed\"\ncreate_object noisy Noisy 1\nlookup_name missing"
+        ),
+        ErrorKind::NameError
+    ));
+    assert_eq!(lines(&output), ["doomed"]);
+}
+
+#[test]
+fn globals_are_dropped_with_the_interpreter() {
+    let (mut itrp, output) = noisy();
+    register(
+        &mut itrp,
+        "
+module holder
+init
+    create_string \"global\"
+    create_object noisy Noisy 1
+    assign kept
+end
+",
+    );
+    assert!(output.text().is_empty());
+    drop(itrp);
+    assert_eq!(lines(&output), ["global"]);
+}
+
+#[test]
+fn every_created_object_is_dropped_once() {
+    let (mut itrp, output) = noisy();
+    itrp.reset_stats();
+    for name in &["one", "two", "three"] {
+        let source = format!(
+            "create_string \"{}\"\ncreate_object noisy Noisy 1\npop",
+            name
+        );
+        assert!(matches!(run(&mut itrp, &source), Ok(None)));
+    }
+    assert_eq!(lines(&output), ["one", "two", "three"]);
+    let module = itrp.lookup_module_index("noisy").unwrap();
+    let stats = itrp.stats();
+    let noisy = stats
+        .type_stats(itrp.lookup_type(module, "Noisy").unwrap())
+        .unwrap();
+    assert_eq!((noisy.created, noisy.dropped), (3, 3));
+}
+
+#[test]
+fn errors_in_drop_methods_are_reported() {
+    let (mut itrp, output) = interpreter();
+    let errors = BufferSink::new();
+    itrp.set_stderr(Box::new(errors.clone()));
+    register(
+        &mut itrp,
+        "
+module broken
+type Broken
+    method drop 1
+        lookup_name missing
+    end
+end
+",
+    );
+    let res = run(
+        &mut itrp,
+        "create_object broken Broken 0
+        pop
+        create_string \"after\"
+        call_method println 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(lines(&output), ["after"]);
+    let errors = errors.text();
+    assert!(
+        errors.starts_with("error in broken::Broken::drop: NameError"),
+        "{}",
+        errors
+    );
+}
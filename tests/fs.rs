@@ -0,0 +1,392 @@
+extern crate tricone;
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use common::{display, eval, eval_err, interpreter, run};
+use tricone::fs_::register_fs;
+use tricone::interpreter::ErrorKind;
+use tricone::io_::register_io;
+use tricone::Interpreter;
+
+/// A fresh directory for one test, removed when it ends.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("tricone-fs-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("root")).unwrap();
+        TempDir(path)
+    }
+
+    fn root(&self) -> PathBuf {
+        self.0.join("root")
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn fs_interpreter(dir: &TempDir) -> Interpreter {
+    let (mut itrp, _) = interpreter();
+    register_fs(&mut itrp, dir.root()).unwrap();
+    itrp
+}
+
+fn fs_call(function: &str, args: &[&str]) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| format!("create_string {:?}", arg))
+        .collect();
+    format!(
+        "get_module_globals fs\nget_member {}\n{}\ncall_function_object {} keep",
+        function,
+        args.join("\n"),
+        args.len()
+    )
+}
+
+#[test]
+fn fs_needs_the_host() {
+    let itrp = Interpreter::new();
+    assert!(itrp.lookup_module_index("fs").is_none());
+}
+
+#[test]
+fn whole_files() {
+    let dir = TempDir::new("whole");
+    let mut itrp = fs_interpreter(&dir);
+    assert_eq!(eval(&mut itrp, &fs_call("exists", &["notes"])), "false");
+    assert!(run(&mut itrp, &fs_call("write", &["notes", "one\n"])).is_ok());
+    assert!(run(&mut itrp, &fs_call("append", &["notes", "two\n"])).is_ok());
+    assert_eq!(eval(&mut itrp, &fs_call("exists", &["notes"])), "true");
+    assert_eq!(
+        eval(&mut itrp, &fs_call("read_to_string", &["./notes"])),
+        "one\ntwo\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.root().join("notes")).unwrap(),
+        "one\ntwo\n"
+    );
+    assert!(matches!(
+        eval_err(&mut itrp, &fs_call("read_to_string", &["missing"])),
+        ErrorKind::IoError
+    ));
+}
+
+#[test]
+fn directories() {
+    let dir = TempDir::new("dirs");
+    let mut itrp = fs_interpreter(&dir);
+    assert!(run(&mut itrp, &fs_call("mkdir", &["a/b"])).is_ok());
+    assert!(run(&mut itrp, &fs_call("write", &["a/z.txt", "zzz"])).is_ok());
+    assert_eq!(eval(&mut itrp, &fs_call("list_dir", &["a"])), "b\nz.txt");
+    assert_eq!(eval(&mut itrp, &fs_call("list_dir", &["a/b"])), "");
+
+    let metadata = |itrp: &mut Interpreter, path: &str, member: &str| {
+        eval(
+            itrp,
+            &format!("{}\nget_member {}", fs_call("metadata", &[path]), member),
+        )
+    };
+    assert_eq!(metadata(&mut itrp, "a/z.txt", "size"), "3");
+    assert_eq!(metadata(&mut itrp, "a/z.txt", "is_file"), "true");
+    assert_eq!(metadata(&mut itrp, "a", "is_dir"), "true");
+
+    assert!(run(&mut itrp, &fs_call("remove", &["a/z.txt"])).is_ok());
+    assert!(run(&mut itrp, &fs_call("remove", &["a"])).is_ok());
+    assert_eq!(eval(&mut itrp, &fs_call("list_dir", &["."])), "");
+    assert!(matches!(
+        eval_err(&mut itrp, &fs_call("remove", &["."])),
+        ErrorKind::AccessDenied
+    ));
+}
+
+#[test]
+fn scripts_stay_under_the_root() {
+    let dir = TempDir::new("escape");
+    fs::write(dir.0.join("secret"), "hidden").unwrap();
+    let mut itrp = fs_interpreter(&dir);
+    let outside = dir.0.join("secret").to_string_lossy().into_owned();
+    for path in &["../secret", "a/../../secret", &outside] {
+        assert!(matches!(
+            eval_err(&mut itrp, &fs_call("read_to_string", &[path])),
+            ErrorKind::AccessDenied
+        ));
+        assert!(matches!(
+            eval_err(&mut itrp, &fs_call("write", &[path, "overwritten"])),
+            ErrorKind::AccessDenied
+        ));
+    }
+    assert!(matches!(
+        eval_err(&mut itrp, &fs_call("list_dir", &[".."])),
+        ErrorKind::AccessDenied
+    ));
+    // Going up is fine as long as the path stays inside
+    assert!(run(&mut itrp, &fs_call("mkdir", &["a"])).is_ok());
+    assert_eq!(eval(&mut itrp, &fs_call("exists", &["a/../a"])), "true");
+    assert_eq!(fs::read_to_string(dir.0.join("secret")).unwrap(), "hidden");
+}
+
+#[cfg(unix)]
+#[test]
+fn links_out_of_the_root_are_refused() {
+    use std::os::unix::fs::symlink;
+
+    let dir = TempDir::new("links");
+    fs::create_dir(dir.0.join("outside")).unwrap();
+    symlink(dir.0.join("outside"), dir.root().join("out")).unwrap();
+    symlink(dir.0.join("nowhere"), dir.root().join("dangling")).unwrap();
+    fs::write(dir.root().join("inside"), "ok").unwrap();
+    symlink(dir.root().join("inside"), dir.root().join("in")).unwrap();
+    let mut itrp = fs_interpreter(&dir);
+    for path in &["out", "out/new", "dangling"] {
+        assert!(matches!(
+            eval_err(&mut itrp, &fs_call("write", &[path, "x"])),
+            ErrorKind::AccessDenied
+        ));
+    }
+    assert!(!dir.0.join("nowhere").exists());
+    assert_eq!(eval(&mut itrp, &fs_call("read_to_string", &["in"])), "ok");
+}
+
+#[cfg(unix)]
+#[test]
+fn links_swapped_while_opening_are_refused() {
+    use std::os::unix::fs::symlink;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    let dir = TempDir::new("swaps");
+    fs::write(dir.0.join("secret"), "hidden").unwrap();
+    fs::write(dir.root().join("inside"), "ok").unwrap();
+    symlink(dir.root().join("inside"), dir.root().join("link")).unwrap();
+    let mut itrp = fs_interpreter(&dir);
+
+    // Flips `link` between the file inside and the one outside, each time in one rename
+    let done = Arc::new(AtomicBool::new(false));
+    let swapper = {
+        let (done, root, secret) = (done.clone(), dir.root(), dir.0.join("secret"));
+        thread::spawn(move || {
+            let targets = [secret, root.join("inside")];
+            let mut swaps = 0;
+            while !done.load(Ordering::SeqCst) {
+                let next = root.join("next");
+                symlink(&targets[swaps % 2], &next).unwrap();
+                fs::rename(&next, root.join("link")).unwrap();
+                swaps += 1;
+            }
+        })
+    };
+    let mut read = 0;
+    for _ in 0..2000 {
+        match run(&mut itrp, &fs_call("read_to_string", &["link"])) {
+            Ok(Some(obj)) => {
+                assert_eq!(display(&mut itrp, obj), "ok");
+                read += 1;
+            }
+            Ok(None) => panic!("read_to_string returned nothing"),
+            Err(err) => assert!(matches!(err.kind, ErrorKind::AccessDenied)),
+        }
+        if let Err(err) = run(&mut itrp, &fs_call("write", &["link", "ok"])) {
+            assert!(matches!(err.kind, ErrorKind::AccessDenied));
+        }
+    }
+    done.store(true, Ordering::SeqCst);
+    swapper.join().unwrap();
+    assert_eq!(fs::read_to_string(dir.0.join("secret")).unwrap(), "hidden");
+    assert!(read > 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn links_swapped_under_directory_operations_are_refused() {
+    use std::os::unix::fs::symlink;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    let dir = TempDir::new("dir-swaps");
+    fs::create_dir(dir.0.join("secrets")).unwrap();
+    fs::write(dir.0.join("secrets").join("hidden"), "hidden").unwrap();
+    fs::create_dir(dir.root().join("public")).unwrap();
+    symlink(dir.root().join("public"), dir.root().join("link")).unwrap();
+    let mut itrp = fs_interpreter(&dir);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let swapper = {
+        let (done, root, secrets) = (done.clone(), dir.root(), dir.0.join("secrets"));
+        thread::spawn(move || {
+            let targets = [secrets, root.join("public")];
+            let mut swaps = 0;
+            while !done.load(Ordering::SeqCst) {
+                let next = root.join("next");
+                symlink(&targets[swaps % 2], &next).unwrap();
+                fs::rename(&next, root.join("link")).unwrap();
+                swaps += 1;
+            }
+        })
+    };
+    for _ in 0..500 {
+        for (function, path) in &[
+            ("list_dir", "link"),
+            ("exists", "link/hidden"),
+            ("mkdir", "link/made"),
+            ("remove", "link/hidden"),
+        ] {
+            match run(&mut itrp, &fs_call(function, &[path])) {
+                Ok(Some(obj)) => assert_ne!(display(&mut itrp, obj), "hidden"),
+                Ok(None) => {}
+                Err(err) => assert!(!matches!(err.kind, ErrorKind::TypeError)),
+            }
+        }
+    }
+    done.store(true, Ordering::SeqCst);
+    swapper.join().unwrap();
+    assert!(dir.0.join("secrets").join("hidden").exists());
+    assert!(!dir.0.join("secrets").join("made").exists());
+}
+
+#[test]
+fn files() {
+    let dir = TempDir::new("files");
+    let (mut itrp, output) = interpreter();
+    register_fs(&mut itrp, dir.root()).unwrap();
+    register_io(&mut itrp).unwrap();
+    let res = run(
+        &mut itrp,
+        "create_string \"log\"
+        create_string \"w\"
+        create_object fs File 2
+        assign f
+        lookup_name f
+        create_string \"first\\n\"
+        call_method write 1 discard
+        lookup_name f
+        create_string \"second\"
+        call_method write 1 discard
+        lookup_name f
+        call_method close 0 discard",
+    );
+    assert!(res.is_ok());
+    let open = "create_string \"log\"\ncreate_string \"r\"\ncreate_object fs File 2\n";
+    let res = run(
+        &mut itrp,
+        &format!(
+            "{}assign f
+            lookup_name f
+            call_method read_line 0 keep
+            call_method println 0 discard
+            lookup_name f
+            call_method read_line 0 keep
+            call_method println 0 discard
+            get_module_globals io
+            get_member println
+            lookup_name f
+            call_method read_line 0 keep
+            call_function_object 1 discard",
+            open
+        ),
+    );
+    assert!(res.is_ok());
+    assert_eq!(output.text(), "first\nsecond\n<Unit>\n");
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            &format!("{}create_string \"x\"\ncall_method write 1 keep", open)
+        ),
+        ErrorKind::IoError
+    ));
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            &format!(
+                "{}assign f
+                lookup_name f
+                call_method close 0 discard
+                lookup_name f
+                call_method read_line 0 keep",
+                open
+            )
+        ),
+        ErrorKind::IoError
+    ));
+}
+
+#[test]
+fn dropping_a_file_closes_it() {
+    let dir = TempDir::new("drop");
+    let mut itrp = fs_interpreter(&dir);
+    let res = run(
+        &mut itrp,
+        "create_string \"log\"
+        create_string \"a\"
+        create_object fs File 2
+        create_string \"kept\"
+        call_method write 1 discard",
+    );
+    assert!(res.is_ok());
+    assert_eq!(fs::read_to_string(dir.root().join("log")).unwrap(), "kept");
+}
+
+#[test]
+fn files_dropped_by_scripts_stay_closed() {
+    let dir = TempDir::new("drop-twice");
+    let mut itrp = fs_interpreter(&dir);
+    let err = run(
+        &mut itrp,
+        "create_string \"log\"
+        create_string \"w\"
+        create_object fs File 2
+        assign f
+        lookup_name f
+        create_string \"before\"
+        call_method write 1 discard
+        lookup_name f
+        call_method drop 0 discard
+        lookup_name f
+        create_string \"after\"
+        call_method write 1 discard",
+    )
+    .unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::IoError));
+    // Letting go of the object then closes nothing more
+    drop(itrp);
+    assert_eq!(
+        fs::read_to_string(dir.root().join("log")).unwrap(),
+        "before"
+    );
+}
+
+#[test]
+fn opening_files_can_fail() {
+    let dir = TempDir::new("open");
+    let mut itrp = fs_interpreter(&dir);
+    let open = |mode: &str, path: &str| {
+        format!(
+            "create_string {:?}\ncreate_string {:?}\ncreate_object fs File 2",
+            path, mode
+        )
+    };
+    assert!(matches!(
+        eval_err(&mut itrp, &open("r", "missing")),
+        ErrorKind::IoError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, &open("rw", "log")),
+        ErrorKind::IoError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, &open("w", "../log")),
+        ErrorKind::AccessDenied
+    ));
+}
@@ -0,0 +1,124 @@
+extern crate tricone;
+
+mod common;
+
+use std::collections::HashMap;
+
+use common::{interpreter, kind, register, run};
+use tricone::fuel::FuelCosts;
+use tricone::interpreter::{ErrorKind, InstructionKind};
+use tricone::moduledef::{FunctionDef, ModuleDef, NativeFunctionDef};
+
+const LOOP: &str = "
+module spin
+fn forever 0
+again:
+    jump again
+end
+";
+
+#[test]
+fn unmetered_code_has_no_fuel() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(itrp.fuel(), None);
+    assert!(itrp.consume_fuel(1_000_000).is_ok());
+}
+
+#[test]
+fn instructions_are_charged_their_costs() {
+    let (mut itrp, _) = interpreter();
+    // The scope `run` creates, two ints, a method call and the native `add` it runs
+    let (res, left) = itrp.with_fuel(100, |itrp| {
+        run(
+            itrp,
+            "create_int 1\ncreate_int 2\ncall_method add 1 discard",
+        )
+    });
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(left, 100 - (1 + 1 + 1 + 2 + 1));
+    assert_eq!(itrp.fuel(), None);
+}
+
+#[test]
+fn costs_can_be_changed() {
+    let (mut itrp, _) = interpreter();
+    let mut costs = FuelCosts::default();
+    costs.set_instruction_cost(InstructionKind::CreateInt, 10);
+    itrp.set_fuel_costs(costs);
+    let (res, left) = itrp.with_fuel(100, |itrp| run(itrp, "create_int 1\npop"));
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(left, 100 - (1 + 10 + 1));
+}
+
+#[test]
+fn an_endless_loop_runs_out_of_fuel() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, LOOP);
+    let (res, left) = itrp.with_fuel(1000, |itrp| {
+        run(
+            itrp,
+            "import spin\nget_member forever\ncall_function_object 0 discard",
+        )
+    });
+    assert!(matches!(kind(res), Some(ErrorKind::OutOfFuel)));
+    assert_eq!(left, 0);
+
+    // The interpreter is still usable afterwards
+    let (res, _) = itrp.with_fuel(1000, |itrp| run(itrp, "create_int 1\npop"));
+    assert!(matches!(res, Ok(None)));
+}
+
+#[test]
+fn nested_budgets_are_charged_to_the_outer_one() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, LOOP);
+    let ((inner, inner_left), outer_left) = itrp.with_fuel(50, |itrp| {
+        // Asking for more than the outer budget has doesn't get it
+        itrp.with_fuel(1000, |itrp| {
+            run(
+                itrp,
+                "import spin\nget_member forever\ncall_function_object 0 discard",
+            )
+        })
+    });
+    assert!(matches!(kind(inner), Some(ErrorKind::OutOfFuel)));
+    assert_eq!((inner_left, outer_left), (0, 0));
+
+    let ((inner, inner_left), outer_left) = itrp.with_fuel(50, |itrp| {
+        itrp.with_fuel(10, |itrp| run(itrp, "create_int 1\npop"))
+    });
+    assert!(matches!(inner, Ok(None)));
+    assert_eq!(inner_left, 7);
+    assert_eq!(outer_left, 47);
+}
+
+#[test]
+fn native_functions_can_charge_for_their_work() {
+    let (mut itrp, _) = interpreter();
+    let mut free_functions = HashMap::new();
+    free_functions.insert(
+        "work".to_owned(),
+        FunctionDef::Native(NativeFunctionDef::new(0, |itrp, _args| {
+            itrp.consume_fuel(40)?;
+            Ok(None)
+        })),
+    );
+    ModuleDef {
+        name: "costly".to_owned(),
+        types: HashMap::new(),
+        free_functions,
+        init: None,
+        exports: None,
+        access: None,
+    }
+    .register(&mut itrp)
+    .unwrap();
+    let call = "import costly\nget_member work\ncall_function_object 0 discard";
+
+    let (res, left) = itrp.with_fuel(100, |itrp| run(itrp, call));
+    assert!(matches!(res, Ok(None)));
+    assert!(left < 60);
+    let (res, left) = itrp.with_fuel(30, |itrp| run(itrp, call));
+    assert!(matches!(kind(res), Some(ErrorKind::OutOfFuel)));
+    assert_eq!(left, 0);
+}
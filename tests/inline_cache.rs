@@ -0,0 +1,159 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, interpreter, register, run};
+use tricone::asm;
+use tricone::Interpreter;
+
+const MODULE: &str = "
+module cached
+type Box
+    method get 1
+        create_int 1
+    end
+end
+fn make 0
+    create_object cached Box 0
+end
+fn read 0
+    get_module_globals cached
+    get_member value
+end
+fn unbox 0
+    get_module_globals cached
+    get_member box
+    call_method get 0 keep
+end
+; Sums `answer` twice, shadowing the global with a local of 7 after the first time
+fn shadow 0
+    locals round total
+    create_int 0
+    store_local 0
+    create_int 0
+    store_local 1
+    create_int 0
+    assign own_scope
+again:
+    load_local 1
+    lookup_name answer
+    call_method add 1 keep
+    store_local 1
+    create_int 7
+    assign answer
+    load_local 0
+    create_int 1
+    call_method add 1 keep
+    store_local 0
+    load_local 0
+    create_int 2
+    call_method lt 1 keep
+    jump_if_false done
+    jump again
+done:
+    load_local 1
+end
+init
+    create_int 1
+    assign value
+    create_int 42
+    assign answer
+    create_object cached Box 0
+    assign box
+end
+";
+
+fn hits_and_misses(itrp: &Interpreter) -> (u64, u64) {
+    let stats = itrp.stats();
+    (stats.inline_cache_hits, stats.inline_cache_misses)
+}
+
+fn set_global(itrp: &mut Interpreter, name: &str, value: i64) {
+    let source = format!(
+        "get_module_globals cached\ncreate_int {}\nset_member {}",
+        value, name
+    );
+    assert!(matches!(run(itrp, &source), Ok(None)));
+}
+
+#[test]
+fn warm_caches_hit() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    assert_eq!(call(&mut itrp, "cached", "unbox"), "1");
+    let (hits, misses) = hits_and_misses(&itrp);
+    assert_eq!(call(&mut itrp, "cached", "unbox"), "1");
+    // `get_member box` and `call_method get`
+    assert_eq!(hits_and_misses(&itrp), (hits + 2, misses));
+}
+
+#[test]
+fn members_are_read_again_once_they_change() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    assert_eq!(call(&mut itrp, "cached", "read"), "1");
+    assert_eq!(call(&mut itrp, "cached", "read"), "1");
+    set_global(&mut itrp, "value", 2);
+    let (_, misses) = hits_and_misses(&itrp);
+    assert_eq!(call(&mut itrp, "cached", "read"), "2");
+    assert_eq!(hits_and_misses(&itrp).1, misses + 1);
+}
+
+#[test]
+fn members_of_other_objects_are_not_mixed_up() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let source = "
+module pick
+fn first 1
+    get_member x
+end
+";
+    register(&mut itrp, source);
+    let make = |x: i64| {
+        format!(
+            "create_object cached Box 0\nassign o\nlookup_name o\ncreate_int {}\nset_member x\n",
+            x
+        )
+    };
+    let source = format!(
+        "{}import pick\nget_member first\nlookup_name o\ncall_function_object 1 keep\n\
+         {}import pick\nget_member first\nlookup_name o\ncall_function_object 1 keep\n\
+         call_method add 1 keep",
+        make(1),
+        make(10)
+    );
+    assert_eq!(common::eval(&mut itrp, &source), "11");
+}
+
+#[test]
+fn methods_are_found_again_once_they_are_redefined() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    assert_eq!(call(&mut itrp, "cached", "unbox"), "1");
+    assert_eq!(call(&mut itrp, "cached", "unbox"), "1");
+
+    let reloaded = MODULE.replace(
+        "method get 1\n        create_int 1",
+        "method get 1\n        create_int 2",
+    );
+    itrp.reload_module(asm::parse_module(&reloaded).unwrap())
+        .unwrap();
+    assert_eq!(call(&mut itrp, "cached", "unbox"), "2");
+}
+
+#[test]
+fn names_are_looked_up_again_once_they_are_shadowed() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    assert_eq!(call(&mut itrp, "cached", "shadow"), "49");
+}
+
+#[test]
+fn names_are_looked_up_again_once_they_change() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    assert_eq!(call(&mut itrp, "cached", "shadow"), "49");
+    set_global(&mut itrp, "answer", 100);
+    assert_eq!(call(&mut itrp, "cached", "shadow"), "107");
+}
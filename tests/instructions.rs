@@ -0,0 +1,549 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, eval, eval_err, interpreter, register, run};
+use tricone::function::{function_from_function_object, Code};
+use tricone::interpreter::{ErrorKind, Instruction, InstructionKind, Interpreter};
+
+const POINT: &str = "
+module geometry
+type Point
+    method create 3
+        locals self x y
+        store_local 2
+        store_local 1
+        store_local 0
+        load_local 0
+        load_local 1
+        set_member x
+        load_local 0
+        load_local 2
+        set_member y
+    end
+    method sum 1
+        locals self
+        store_local 0
+        load_local 0
+        get_member x
+        load_local 0
+        get_member y
+        call_method add 1 keep
+    end
+end
+fn origin 0
+    create_object geometry Point 2
+end
+fn make 0
+    create_int 3
+    create_int 4
+    create_object geometry Point 2
+end
+fn twice 1
+    create_int 2
+    call_method mul 1 keep
+end
+init
+    create_int 42
+    assign answer
+end
+";
+
+fn geometry(interpreter: &mut Interpreter) {
+    register(interpreter, POINT);
+}
+
+fn code(interpreter: &mut Interpreter, module: &str, function: &str) -> Vec<Instruction> {
+    let module = interpreter.lookup_module_index(module).unwrap();
+    let globals = interpreter.get_module(module).globals.vars.dup();
+    let function = globals.obj().members[&interpreter.intern(function)].dup();
+    let instructions = match *function_from_function_object(&function.obj()).code() {
+        Code::Bytecode(ref bytecode) => bytecode.instructions.clone(),
+        Code::Native(_) => panic!("not bytecode"),
+    };
+    interpreter.drop_token(function);
+    interpreter.drop_token(globals);
+    instructions
+}
+
+#[test]
+fn create_object() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1\ncreate_int 2\ncreate_object geometry Point 2\ncall_method sum 0 keep"
+        ),
+        "3"
+    );
+    assert_eq!(eval(&mut itrp, "create_object core String 0"), "");
+    assert_eq!(eval(&mut itrp, "create_object core Int 0"), "0");
+    assert_eq!(eval(&mut itrp, "create_object core Bool 0"), "false");
+}
+
+#[test]
+fn create_object_errors() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert!(matches!(
+        eval_err(&mut itrp, "create_object geometry Line 0"),
+        ErrorKind::NameError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\ncreate_object geometry Point 1"),
+        ErrorKind::WrongArgumentCount
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_object geometry Point 2"),
+        ErrorKind::StackUnderflow
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\ncreate_object core Int 1"),
+        ErrorKind::WrongArgumentCount
+    ));
+}
+
+#[test]
+fn assign() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "create_int 5\nassign x\nlookup_name x"),
+        "5"
+    );
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 5\nassign x\ncreate_string \"six\"\nassign x\nlookup_name x"
+        ),
+        "six"
+    );
+    assert!(matches!(
+        eval_err(&mut itrp, "assign x"),
+        ErrorKind::StackUnderflow
+    ));
+}
+
+#[test]
+fn get_top_scope() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "get_top_scope\ncreate_int 7\nset_member y\nlookup_name y"
+        ),
+        "7"
+    );
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 8\nassign z\nget_top_scope\nget_member z"
+        ),
+        "8"
+    );
+}
+
+#[test]
+fn get_module_globals() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(
+        eval(&mut itrp, "get_module_globals geometry\nget_member answer"),
+        "42"
+    );
+    assert!(matches!(
+        eval_err(&mut itrp, "get_module_globals nowhere"),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn import() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(eval(&mut itrp, "import geometry\nget_member answer"), "42");
+    assert!(matches!(
+        eval_err(&mut itrp, "import nowhere"),
+        ErrorKind::ImportError
+    ));
+}
+
+#[test]
+fn call_method() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 2\ncreate_int 3\ncall_method add 1 keep"
+        ),
+        "5"
+    );
+    let res = run(
+        &mut itrp,
+        "create_int 2\ncreate_int 3\ncall_method add 1 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 2\ncall_method frobnicate 0 keep"),
+        ErrorKind::NameError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 2\ncall_method add 0 keep"),
+        ErrorKind::WrongArgumentCount
+    ));
+}
+
+#[test]
+fn get_member() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1\ncreate_int 2\ncreate_object geometry Point 2\nget_member y"
+        ),
+        "2"
+    );
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "create_int 1\ncreate_int 2\ncreate_object geometry Point 2\nget_member z"
+        ),
+        ErrorKind::NameError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\nget_member x"),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn lookup_name() {
+    let (mut itrp, _) = interpreter();
+    assert!(matches!(
+        eval_err(&mut itrp, "lookup_name missing"),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn call_function_object() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "import geometry\nget_member twice\ncreate_int 21\ncall_function_object 1 keep"
+        ),
+        "42"
+    );
+    assert_eq!(call(&mut itrp, "geometry", "make"), "<Point>");
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "import geometry\nget_member twice\ncall_function_object 0 keep"
+        ),
+        ErrorKind::WrongArgumentCount
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\ncall_function_object 0 keep"),
+        ErrorKind::TypeError
+    ));
+}
+
+#[test]
+fn create_string() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "create_string \"tab\\there \\\"quoted\\\"\""),
+        "tab\there \"quoted\""
+    );
+}
+
+#[test]
+fn create_int() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(eval(&mut itrp, "create_int -17"), "-17");
+    assert_eq!(
+        eval(&mut itrp, &format!("create_int {}", i64::MAX)),
+        i64::MAX.to_string()
+    );
+}
+
+#[test]
+fn create_bool() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(eval(&mut itrp, "create_bool true"), "true");
+    assert_eq!(eval(&mut itrp, "create_bool false"), "false");
+}
+
+#[test]
+fn jump() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1
+            assign x
+            jump done
+            create_int 2
+            assign x
+        done:
+            lookup_name x"
+        ),
+        "1"
+    );
+}
+
+#[test]
+fn jump_if_false() {
+    let (mut itrp, _) = interpreter();
+    let source = |cond: &str| {
+        format!(
+            "create_bool {}
+            jump_if_false otherwise
+            create_string \"then\"
+            assign res
+            jump done
+        otherwise:
+            create_string \"else\"
+            assign res
+        done:
+            lookup_name res",
+            cond
+        )
+    };
+    assert_eq!(eval(&mut itrp, &source("true")), "then");
+    assert_eq!(eval(&mut itrp, &source("false")), "else");
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 0\njump_if_false 0"),
+        ErrorKind::TypeError
+    ));
+}
+
+#[test]
+fn loop_with_jumps() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 0
+            assign i
+        again:
+            lookup_name i
+            create_int 10
+            call_method lt 1 keep
+            jump_if_false done
+            lookup_name i
+            create_int 1
+            call_method add 1 keep
+            assign i
+            jump again
+        done:
+            lookup_name i"
+        ),
+        "10"
+    );
+}
+
+#[test]
+fn set_member() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1
+            create_int 2
+            create_object geometry Point 2
+            assign p
+            lookup_name p
+            create_int 10
+            set_member x
+            lookup_name p
+            call_method sum 0 keep"
+        ),
+        "12"
+    );
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\ncreate_int 2\nset_member x"),
+        ErrorKind::TypeError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_int 1\nset_member x"),
+        ErrorKind::StackUnderflow
+    ));
+}
+
+#[test]
+fn pop() {
+    let (mut itrp, _) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1\ncreate_int 2\npop\nassign x\nlookup_name x"
+        ),
+        "1"
+    );
+    assert!(matches!(
+        eval_err(&mut itrp, "pop"),
+        ErrorKind::StackUnderflow
+    ));
+}
+
+#[test]
+fn diag() {
+    let (mut itrp, output) = interpreter();
+    assert_eq!(
+        eval(&mut itrp, "create_int 4\ndiag\nassign x\nlookup_name x"),
+        "4"
+    );
+    assert_eq!(output.text(), "[Int(4)]\n");
+}
+
+#[test]
+fn debug_print_object() {
+    let (mut itrp, output) = interpreter();
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 1\ncreate_int 2\ndebug_print_object\nassign x\nlookup_name x"
+        ),
+        "1"
+    );
+    assert_eq!(output.text(), "Int(2)\n");
+    assert!(matches!(
+        eval_err(&mut itrp, "debug_print_object"),
+        ErrorKind::StackUnderflow
+    ));
+}
+
+#[test]
+fn create_object_resolved() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    let core = itrp.lookup_module_index("core").unwrap();
+    let string = itrp.lookup_type(core, "String").unwrap();
+    let res = itrp
+        .run_code(&[Instruction::CreateObjectResolved {
+            type_: string,
+            num_args: 0,
+        }])
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.type_index(), string);
+    itrp.drop_token(res);
+
+    // Registering links the module's own code
+    let point = itrp
+        .lookup_type(itrp.lookup_module_index("geometry").unwrap(), "Point")
+        .unwrap();
+    let origin = code(&mut itrp, "geometry", "origin");
+    assert!(matches!(
+        origin[..],
+        [Instruction::CreateObjectResolved { type_, num_args: 2 }] if type_ == point
+    ));
+}
+
+#[test]
+fn get_module_globals_resolved() {
+    let (mut itrp, _) = interpreter();
+    geometry(&mut itrp);
+    let module = itrp.lookup_module_index("geometry").unwrap();
+    let res = itrp
+        .run_code(&[
+            Instruction::GetModuleGlobalsResolved { module },
+            Instruction::GetMember {
+                name: itrp.intern("answer"),
+            },
+        ])
+        .unwrap()
+        .unwrap();
+    assert_eq!(tricone::int::int_value(&res), Some(42));
+    itrp.drop_token(res);
+}
+
+#[test]
+fn locals() {
+    let (mut itrp, _) = interpreter();
+    register(
+        &mut itrp,
+        "
+module slots
+fn swap 2
+    locals a b
+    store_local 1
+    store_local 0
+    load_local 1
+    load_local 0
+    call_method sub 1 keep
+end
+fn unset 0
+    locals outer
+    load_local 0
+end
+fn missing 0
+    load_local 3
+end
+init
+    create_string \"global\"
+    assign outer
+end
+",
+    );
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "import slots\nget_member swap\ncreate_int 10\ncreate_int 3\ncall_function_object 2 keep"
+        ),
+        "-7"
+    );
+    // A slot that wasn't stored to yet finds its name further out
+    assert_eq!(call(&mut itrp, "slots", "unset"), "global");
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "import slots\nget_member missing\ncall_function_object 0 keep"
+        ),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn lookups_of_locals_are_linked_to_their_slots() {
+    let (mut itrp, _) = interpreter();
+    register(
+        &mut itrp,
+        "
+module named
+fn before 0
+    locals outer
+    lookup_name outer
+end
+fn after 0
+    locals outer
+    create_int 1
+    store_local 0
+    lookup_name outer
+end
+init
+    create_string \"global\"
+    assign outer
+end
+",
+    );
+    let after = code(&mut itrp, "named", "after");
+    assert!(matches!(
+        after[..],
+        [.., Instruction::LoadLocal { slot: 0 }]
+    ));
+    // Like the lookup, the slot finds the name further out until it is stored to
+    assert_eq!(call(&mut itrp, "named", "before"), "global");
+    assert_eq!(call(&mut itrp, "named", "after"), "1");
+}
+
+#[test]
+fn every_instruction_is_covered() {
+    // Adding an instruction should come with a test above
+    assert_eq!(InstructionKind::ALL.len(), 22);
+}
@@ -0,0 +1,162 @@
+extern crate tricone;
+
+mod common;
+
+use std::io::Cursor;
+use std::mem;
+
+use common::{eval, eval_err, run};
+use tricone::interpreter::ErrorKind;
+use tricone::io_::register_io;
+use tricone::output::BufferSink;
+use tricone::Interpreter;
+
+fn interpreter() -> (Interpreter, BufferSink) {
+    let (mut itrp, output) = common::interpreter();
+    register_io(&mut itrp).unwrap();
+    (itrp, output)
+}
+
+fn with_stdin(input: &str) -> (Interpreter, BufferSink) {
+    let (mut itrp, output) = interpreter();
+    itrp.set_stdin(Box::new(Cursor::new(input.as_bytes().to_vec())));
+    (itrp, output)
+}
+
+fn io_call(function: &str, args: &[&str]) -> String {
+    format!(
+        "get_module_globals io\nget_member {}\n{}\ncall_function_object {} keep",
+        function,
+        args.join("\n"),
+        args.len()
+    )
+}
+
+#[test]
+fn hosts_opt_in_to_io() {
+    let (mut itrp, _) = common::interpreter();
+    assert!(matches!(
+        eval_err(&mut itrp, &io_call("println", &["create_int 1"])),
+        ErrorKind::NameError
+    ));
+    register_io(&mut itrp).unwrap();
+    assert!(run(&mut itrp, &io_call("println", &["create_int 1"])).is_ok());
+    assert!(matches!(
+        register_io(&mut itrp).map_err(|err| err.kind),
+        Err(ErrorKind::NameError)
+    ));
+}
+
+#[test]
+fn print_any_object() {
+    let (mut itrp, output) = interpreter();
+    for source in &[
+        io_call("print", &["create_int 4"]),
+        io_call("print", &["create_string \" and \""]),
+        io_call("println", &["create_bool true"]),
+    ] {
+        assert!(run(&mut itrp, source).is_ok());
+    }
+    assert_eq!(output.text(), "4 and true\n");
+}
+
+#[test]
+fn eprintln_writes_to_stderr() {
+    let (mut itrp, output) = interpreter();
+    let errors = BufferSink::new();
+    itrp.set_stderr(Box::new(errors.clone()));
+    assert!(run(&mut itrp, &io_call("eprintln", &["create_int 7"])).is_ok());
+    assert_eq!(errors.text(), "7\n");
+    assert!(output.text().is_empty());
+}
+
+#[test]
+fn read_lines_until_the_end() {
+    let (mut itrp, _) = with_stdin("first\r\nsecond\nlast");
+    let read_line = io_call("read_line", &[]);
+    assert_eq!(eval(&mut itrp, &read_line), "first");
+    assert_eq!(eval(&mut itrp, &read_line), "second");
+    assert_eq!(eval(&mut itrp, &read_line), "last");
+    assert_eq!(eval(&mut itrp, &read_line), "<Unit>");
+}
+
+#[test]
+fn read_all() {
+    let (mut itrp, _) = with_stdin("one\ntwo\nthree\n");
+    assert_eq!(eval(&mut itrp, &io_call("read_line", &[])), "one");
+    assert_eq!(eval(&mut itrp, &io_call("read_all", &[])), "two\nthree\n");
+    assert_eq!(eval(&mut itrp, &io_call("read_all", &[])), "");
+}
+
+#[test]
+fn format() {
+    let (mut itrp, _) = interpreter();
+    let source = io_call(
+        "format",
+        &[
+            "create_string \"%s=%d, [%5d] [%-4s] [%03d] 100%%\"",
+            "create_string \"x\"",
+            "create_int -3",
+            "create_int 42",
+            "create_bool true",
+            "create_int 7",
+        ],
+    );
+    assert_eq!(eval(&mut itrp, &source), "x=-3, [   42] [true] [007] 100%");
+}
+
+#[test]
+fn printf_adds_no_line_break() {
+    let (mut itrp, output) = interpreter();
+    let source = io_call("printf", &["create_string \"%d|\"", "create_int 1"]);
+    assert!(run(&mut itrp, &source).is_ok());
+    assert!(run(&mut itrp, &source).is_ok());
+    assert_eq!(output.text(), "1|1|");
+}
+
+#[test]
+fn format_errors() {
+    let (mut itrp, _) = interpreter();
+    let cases = [
+        (vec!["create_string \"%d\""], ErrorKind::WrongArgumentCount),
+        (
+            vec!["create_string \"\"", "create_int 1"],
+            ErrorKind::WrongArgumentCount,
+        ),
+        (
+            vec!["create_string \"%d\"", "create_string \"1\""],
+            ErrorKind::TypeError,
+        ),
+        (
+            vec!["create_string \"%x\"", "create_int 1"],
+            ErrorKind::TypeError,
+        ),
+        (vec!["create_string \"50%\""], ErrorKind::TypeError),
+        (vec!["create_int 1"], ErrorKind::TypeError),
+    ];
+    for (args, kind) in cases.iter() {
+        let err = eval_err(&mut itrp, &io_call("format", args));
+        assert_eq!(
+            mem::discriminant(&err),
+            mem::discriminant(kind),
+            "{:?}",
+            args
+        );
+    }
+}
+
+#[test]
+fn variadic_functions_still_need_their_arguments() {
+    let (mut itrp, _) = interpreter();
+    assert!(matches!(
+        eval_err(&mut itrp, &io_call("format", &[])),
+        ErrorKind::WrongArgumentCount
+    ));
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            &io_call("println", &["create_int 1", "create_int 2"])
+        ),
+        ErrorKind::WrongArgumentCount
+    ));
+}
@@ -0,0 +1,234 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, interpreter};
+use tricone::lang::ast::{BinaryOp, ExprKind, Item, StmtKind};
+use tricone::lang::lexer::{tokenize, TokenKind};
+use tricone::lang::{compile_module, parser, CompileError};
+use tricone::Interpreter;
+
+fn kinds(source: &str) -> Vec<TokenKind> {
+    tokenize(source)
+        .unwrap_or_else(|err| panic!("lexing failed: {}", err))
+        .into_iter()
+        .map(|token| token.kind)
+        .collect()
+}
+
+fn parse_err(source: &str) -> CompileError {
+    let tokens = tokenize(source).unwrap();
+    match parser::parse_module(&tokens) {
+        Ok(_) => panic!("parsing succeeded"),
+        Err(err) => err,
+    }
+}
+
+fn compile_err(source: &str) -> String {
+    match compile_module("test", source) {
+        Ok(_) => panic!("compiling succeeded"),
+        Err(err) => err.to_string(),
+    }
+}
+
+fn load(itrp: &mut Interpreter, name: &str, source: &str) {
+    let def = compile_module(name, source).unwrap_or_else(|err| panic!("{}", err));
+    def.register(itrp)
+        .unwrap_or_else(|err| panic!("registering failed: {}", err));
+}
+
+fn run_main(source: &str) -> String {
+    let (mut itrp, _) = interpreter();
+    load(&mut itrp, "test", source);
+    call(&mut itrp, "test", "main")
+}
+
+#[test]
+fn lexer_produces_tokens() {
+    assert_eq!(
+        kinds("let x = foo::bar(1, \"a\\n\"); // comment\nx.y == 20"),
+        vec![
+            TokenKind::Let,
+            TokenKind::Ident("x".to_owned()),
+            TokenKind::Assign,
+            TokenKind::Ident("foo".to_owned()),
+            TokenKind::PathSep,
+            TokenKind::Ident("bar".to_owned()),
+            TokenKind::OpenParen,
+            TokenKind::Int(1),
+            TokenKind::Comma,
+            TokenKind::Str("a\n".to_owned()),
+            TokenKind::CloseParen,
+            TokenKind::Semicolon,
+            TokenKind::Ident("x".to_owned()),
+            TokenKind::Dot,
+            TokenKind::Ident("y".to_owned()),
+            TokenKind::EqualEqual,
+            TokenKind::Int(20),
+            TokenKind::Eof,
+        ]
+    );
+}
+
+#[test]
+fn lexer_tracks_lines_and_columns() {
+    let tokens = tokenize("fn\n  main").unwrap();
+    assert_eq!((tokens[0].span.line, tokens[0].span.column), (1, 1));
+    assert_eq!((tokens[1].span.line, tokens[1].span.column), (2, 3));
+    assert_eq!((tokens[1].span.start, tokens[1].span.end), (5, 9));
+}
+
+#[test]
+fn lexer_errors() {
+    let err = tokenize("let s = \"open").unwrap_err();
+    assert_eq!(err.message, "unterminated string");
+    assert_eq!(err.span.column, 9);
+    assert_eq!(
+        tokenize("\n  #").unwrap_err().to_string(),
+        "2:3: unexpected character `#`"
+    );
+    assert!(tokenize("99999999999999999999")
+        .unwrap_err()
+        .message
+        .contains("too large"));
+}
+
+#[test]
+fn parser_builds_items() {
+    let tokens = tokenize("type T { fn get(self) { 1 } }\nfn f(a, b) { a }\nexport f;").unwrap();
+    let module = parser::parse_module(&tokens).unwrap();
+    assert_eq!(module.items.len(), 3);
+    match module.items[0] {
+        Item::Type(ref decl) => {
+            assert_eq!(decl.name, "T");
+            assert_eq!(decl.methods[0].name, "get");
+        }
+        ref other => panic!("expected a type, got {:?}", other),
+    }
+    match module.items[1] {
+        Item::Function(ref decl) => assert_eq!(decl.params, vec!["a", "b"]),
+        ref other => panic!("expected a function, got {:?}", other),
+    }
+    assert!(matches!(module.items[2], Item::Export(ref names) if names[0].0 == "f"));
+}
+
+#[test]
+fn parser_respects_precedence() {
+    let tokens = tokenize("1 + 2 * 3 < 10;").unwrap();
+    let module = parser::parse_module(&tokens).unwrap();
+    let stmt = match module.items[0] {
+        Item::Stmt(ref stmt) => stmt,
+        ref other => panic!("expected a statement, got {:?}", other),
+    };
+    let expr = match stmt.kind {
+        StmtKind::Expr(ref expr) => expr,
+        ref other => panic!("expected an expression, got {:?}", other),
+    };
+    match expr.kind {
+        ExprKind::Binary {
+            op: BinaryOp::Less,
+            ref lhs,
+            ..
+        } => match lhs.kind {
+            ExprKind::Binary {
+                op: BinaryOp::Add,
+                ref rhs,
+                ..
+            } => assert!(matches!(
+                rhs.kind,
+                ExprKind::Binary {
+                    op: BinaryOp::Mul,
+                    ..
+                }
+            )),
+            ref other => panic!("expected an add, got {:?}", other),
+        },
+        ref other => panic!("expected a comparison, got {:?}", other),
+    }
+}
+
+#[test]
+fn parser_errors() {
+    let err = parse_err("fn f() {\n  let = 1;\n}");
+    assert_eq!(err.to_string(), "2:7: expected a name, found `=`");
+    assert_eq!(
+        parse_err("fn f(a, a) {}").message,
+        "duplicate parameter `a`"
+    );
+    assert_eq!(parse_err("1 = 2;").message, "cannot assign to this");
+}
+
+#[test]
+fn compiles_arithmetic_and_control_flow() {
+    let source = "
+        fn main() {
+            let total = 0;
+            let i = 0;
+            while i < 5 {
+                i = i + 1;
+                if i == 3 { total = total + 100; } else { total = total + i; }
+            }
+            total * 2 - 1
+        }
+    ";
+    assert_eq!(run_main(source), "223");
+}
+
+#[test]
+fn compiles_types_and_methods() {
+    let source = "
+        type Counter {
+            fn create(self, start) { self.value = start; }
+            fn bump(self) { self.value = self.value + 1; self.value }
+        }
+        fn main() {
+            let c = new Counter(5);
+            c.bump();
+            c.bump()
+        }
+    ";
+    assert_eq!(run_main(source), "7");
+}
+
+#[test]
+fn compiles_early_returns_and_init() {
+    let source = "
+        let base = 40;
+        fn pick(flag) {
+            if flag { return base; }
+            0
+        }
+        fn main() { pick(true) + 2 }
+    ";
+    assert_eq!(run_main(source), "42");
+}
+
+#[test]
+fn compiles_references_to_other_modules() {
+    let (mut itrp, _) = interpreter();
+    load(
+        &mut itrp,
+        "other",
+        "type Box { fn create(self, v) { self.v = v; } fn get(self) { self.v } }\n\
+         fn two() { 2 }\n\
+         export Box, two;",
+    );
+    load(
+        &mut itrp,
+        "test",
+        "import other;\n\
+         fn main() { new other::Box(40).get() + other::two() }",
+    );
+    assert_eq!(call(&mut itrp, "test", "main"), "42");
+}
+
+#[test]
+fn compile_errors() {
+    assert_eq!(
+        compile_err("fn main() {\n  x = 1;\n}"),
+        "2:3: `x` is not a local, declare it with `let`"
+    );
+    assert!(compile_err("return 1;").contains("`return` outside of a function"));
+    assert!(compile_err("fn f() {}\nfn f() {}").contains("duplicate function `f`"));
+    assert!(compile_err("fn f() { let x = if true { 1 }; }").contains("has no value"));
+}
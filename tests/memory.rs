@@ -0,0 +1,161 @@
+extern crate tricone;
+
+mod common;
+
+use common::{eval, interpreter, kind, register, run};
+use tricone::asm;
+use tricone::interpreter::{ErrorKind, Immediate};
+use tricone::memory::{MEMBER_SIZE, OBJECT_SIZE};
+use tricone::Interpreter;
+
+const BOXES: &str = "
+module boxes
+type Box
+end
+init
+    create_object boxes Box 0
+    assign kept
+end
+";
+
+fn boxes() -> Interpreter {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, BOXES);
+    itrp
+}
+
+#[test]
+fn objects_are_counted_until_they_are_dropped() {
+    let mut itrp = boxes();
+    let module = itrp.lookup_module_index("boxes").unwrap();
+    let ty = itrp.lookup_type(module, "Box").unwrap();
+    let before = itrp.memory_usage();
+    let obj = itrp.create_object(ty, 0).unwrap();
+    let during = itrp.memory_usage();
+    assert_eq!(during.live_objects, before.live_objects + 1);
+    assert_eq!(during.live_bytes, before.live_bytes + OBJECT_SIZE);
+    itrp.drop_token(obj);
+    assert_eq!(itrp.memory_usage(), before);
+}
+
+#[test]
+fn members_count_towards_the_object() {
+    let mut itrp = boxes();
+    let module = itrp.lookup_module_index("boxes").unwrap();
+    let ty = itrp.lookup_type(module, "Box").unwrap();
+    let obj = itrp.create_object(ty, 0).unwrap();
+    let before = itrp.memory_usage().live_bytes;
+    let name = itrp.intern("x");
+    obj.assign_member(name, Immediate::Int(1).into(), &mut itrp)
+        .unwrap();
+    assert_eq!(itrp.memory_usage().live_bytes, before + MEMBER_SIZE);
+    // Replacing a member adds nothing
+    obj.assign_member(name, Immediate::Int(2).into(), &mut itrp)
+        .unwrap();
+    assert_eq!(itrp.memory_usage().live_bytes, before + MEMBER_SIZE);
+    itrp.drop_token(obj);
+    assert_eq!(itrp.memory_usage().live_bytes, before - OBJECT_SIZE);
+}
+
+#[test]
+fn creating_objects_past_the_limit_fails() {
+    let mut itrp = boxes();
+    let used = itrp.memory_usage().total_bytes();
+    // Room for the scope `run` creates and one box
+    itrp.set_memory_limit(Some(used + 2 * OBJECT_SIZE));
+    assert!(matches!(
+        run(&mut itrp, "create_object boxes Box 0\npop"),
+        Ok(None)
+    ));
+    assert!(matches!(
+        kind(run(
+            &mut itrp,
+            "create_object boxes Box 0\ncreate_object boxes Box 0"
+        )),
+        Some(ErrorKind::MemoryError)
+    ));
+    // Everything the failed run made was released again
+    assert_eq!(itrp.memory_usage().total_bytes(), used);
+    itrp.set_memory_limit(None);
+    assert_eq!(eval(&mut itrp, "create_int 1"), "1");
+}
+
+#[test]
+fn adding_members_past_the_limit_fails() {
+    let mut itrp = boxes();
+    let used = itrp.memory_usage().total_bytes();
+    // Room for the scope `run` creates, but not for a member in it
+    itrp.set_memory_limit(Some(used + OBJECT_SIZE));
+    assert!(matches!(
+        kind(run(&mut itrp, "create_int 1\nassign x")),
+        Some(ErrorKind::MemoryError)
+    ));
+    assert!(matches!(
+        kind(run(
+            &mut itrp,
+            "get_module_globals boxes\nget_member kept\ncreate_int 1\nset_member x"
+        )),
+        Some(ErrorKind::MemoryError)
+    ));
+    assert_eq!(itrp.memory_usage().total_bytes(), used);
+
+    itrp.set_memory_limit(Some(used + OBJECT_SIZE + MEMBER_SIZE));
+    assert!(matches!(
+        run(
+            &mut itrp,
+            "get_module_globals boxes\nget_member kept\ncreate_int 1\nset_member x"
+        ),
+        Ok(None)
+    ));
+    // Replacing the member needs no more room
+    assert!(matches!(
+        run(
+            &mut itrp,
+            "get_module_globals boxes\nget_member kept\ncreate_int 2\nset_member x"
+        ),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn string_payloads_count_towards_the_limit() {
+    let mut itrp = boxes();
+    let used = itrp.memory_usage().total_bytes();
+    itrp.set_memory_limit(Some(used + 4 * OBJECT_SIZE));
+    let long = "x".repeat(4 * OBJECT_SIZE);
+    let source = format!("create_string \"{}\"\npop", long);
+    assert!(matches!(
+        kind(run(&mut itrp, &source)),
+        Some(ErrorKind::MemoryError)
+    ));
+    assert!(matches!(
+        run(&mut itrp, "create_string \"short\"\npop"),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn names_count_towards_the_limit() {
+    let (mut itrp, _) = interpreter();
+    let builtins = itrp.memory_usage().name_bytes;
+    register(&mut itrp, BOXES);
+    // `boxes`, `Box` and `kept`
+    assert_eq!(itrp.memory_usage().name_bytes, builtins + 12);
+    register(
+        &mut itrp,
+        "module more\nfn kept 0\n    create_object boxes Box 0\nend\n",
+    );
+    assert_eq!(itrp.memory_usage().name_bytes, builtins + 12 + "more".len());
+
+    let used = itrp.memory_usage().total_bytes();
+    itrp.set_memory_limit(Some(used + 10));
+    let long = "n".repeat(20);
+    let source = format!("module names\nfn f 0\n    lookup_name {}\nend\n", long);
+    let def = asm::parse_module(&source).unwrap();
+    assert!(matches!(
+        kind(def.register(&mut itrp)),
+        Some(ErrorKind::MemoryError)
+    ));
+    assert!(itrp.lookup_module_index("names").is_none());
+    assert_eq!(itrp.memory_usage().total_bytes(), used);
+}
@@ -0,0 +1,417 @@
+extern crate tricone;
+
+mod common;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use common::{call, eval, eval_err, interpreter, register};
+use tricone::asm;
+use tricone::interpreter::{ErrorKind, Instruction, TriconeError};
+use tricone::loader::ModuleLoader;
+use tricone::moduledef::{BytecodeFunctionDef, FunctionDef, ModuleDef, NativeFunctionDef};
+
+fn register_err(source: &str) -> (tricone::Interpreter, ErrorKind) {
+    let (mut itrp, _) = interpreter();
+    let def = asm::parse_module(source).unwrap();
+    let kind = match def.register(&mut itrp) {
+        Ok(_) => panic!("registering didn't fail"),
+        Err(err) => err.kind,
+    };
+    (itrp, kind)
+}
+
+#[test]
+fn register_types_functions_and_init() {
+    let (mut itrp, _) = interpreter();
+    let index = register(
+        &mut itrp,
+        "
+module shapes
+type Square
+    method create 2
+        set_member side
+    end
+    method area 1
+        get_member side
+        assign side
+        lookup_name side
+        lookup_name side
+        call_method mul 1 keep
+    end
+end
+fn unit_area 0
+    lookup_name unit
+    call_method area 0 keep
+end
+init
+    create_int 1
+    create_object shapes Square 1
+    assign unit
+end
+",
+    );
+    assert_eq!(itrp.lookup_module_index("shapes"), Some(index));
+    assert!(itrp.lookup_type(index, "Square").is_some());
+    assert_eq!(call(&mut itrp, "shapes", "unit_area"), "1");
+    assert_eq!(
+        eval(
+            &mut itrp,
+            "create_int 5\ncreate_object shapes Square 1\ncall_method area 0 keep"
+        ),
+        "25"
+    );
+}
+
+#[test]
+fn register_native_functions() {
+    let (mut itrp, _) = interpreter();
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    let mut free_functions = HashMap::new();
+    free_functions.insert(
+        "count".to_owned(),
+        FunctionDef::Native(NativeFunctionDef::new(0, move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(None)
+        })),
+    );
+    ModuleDef {
+        name: "native".to_owned(),
+        types: HashMap::new(),
+        free_functions,
+        init: None,
+        exports: None,
+        access: None,
+    }
+    .register(&mut itrp)
+    .unwrap();
+
+    let res = common::run(
+        &mut itrp,
+        "import native
+        get_member count
+        call_function_object 0 discard
+        import native
+        get_member count
+        call_function_object 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn exports() {
+    let (mut itrp, _) = interpreter();
+    register(
+        &mut itrp,
+        "
+module secret
+fn reveal 0
+    lookup_name hidden
+end
+init
+    create_string \"psst\"
+    assign hidden
+end
+export reveal
+",
+    );
+    assert_eq!(call(&mut itrp, "secret", "reveal"), "psst");
+    assert!(matches!(
+        eval_err(&mut itrp, "import secret\nget_member hidden"),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn init_must_take_no_arguments() {
+    let (mut itrp, _) = interpreter();
+    let def = ModuleDef {
+        name: "bad_init".to_owned(),
+        types: HashMap::new(),
+        free_functions: HashMap::new(),
+        init: Some(BytecodeFunctionDef {
+            arity: 1,
+            instructions: vec![Instruction::Pop],
+            debug_info: None,
+            locals: vec![],
+        }),
+        exports: None,
+        access: None,
+    };
+    assert!(matches!(
+        def.register(&mut itrp).map_err(|err| err.kind),
+        Err(ErrorKind::WrongArgumentCount)
+    ));
+}
+
+#[test]
+fn init_errors_fail_the_registration() {
+    let (_, kind) = register_err(
+        "
+module failing
+init
+    lookup_name missing
+end
+",
+    );
+    assert!(matches!(kind, ErrorKind::NameError));
+}
+
+#[test]
+fn missing_types_fail_the_registration() {
+    let (itrp, kind) = register_err(
+        "
+module broken
+fn make 0
+    create_object broken Nothing 0
+end
+",
+    );
+    assert!(matches!(kind, ErrorKind::NameError));
+    assert!(itrp.lookup_module_index("broken").is_none());
+}
+
+#[test]
+fn missing_modules_fail_the_registration() {
+    let (itrp, kind) = register_err(
+        "
+module lonely
+fn friend 0
+    get_module_globals nobody
+end
+",
+    );
+    assert!(matches!(kind, ErrorKind::ImportError));
+    assert!(itrp.lookup_module_index("lonely").is_none());
+}
+
+#[test]
+fn module_names_are_unique() {
+    let (mut itrp, _) = interpreter();
+    let first = register(
+        &mut itrp,
+        "module twice\nfn which 0\n    create_int 1\nend\n",
+    );
+    let def = asm::parse_module("module twice\nfn which 0\n    create_int 2\nend\n").unwrap();
+    assert!(matches!(
+        def.register(&mut itrp).map_err(|err| err.kind),
+        Err(ErrorKind::NameError)
+    ));
+    assert_eq!(itrp.lookup_module_index("twice"), Some(first));
+    assert_eq!(call(&mut itrp, "twice", "which"), "1");
+    // Nor can a module take the name of a built-in one
+    let def = asm::parse_module("module builtins\n").unwrap();
+    assert!(def.register(&mut itrp).is_err());
+}
+
+struct Loader;
+
+impl Loader {
+    fn source(name: &str) -> Option<&'static str> {
+        Some(match name {
+            "first" => "module first\ninit\n    import second\n    get_member value\n    assign value\nend\n",
+            "second" => "module second\ninit\n    create_int 2\n    assign value\nend\n",
+            "loop_a" => "module loop_a\ninit\n    import loop_b\n    pop\nend\n",
+            "loop_b" => "module loop_b\ninit\n    import loop_a\n    pop\nend\n",
+            "noisy_init" => "module noisy_init\ninit\n    create_string \"noisy_init\"\n    call_method println 0 discard\nend\n",
+            "back_to_host" => "module back_to_host\ninit\n    import host\n    pop\nend\n",
+            "boxes" => "module boxes\ntype Box\nend\n",
+            _ => return None,
+        })
+    }
+}
+
+impl ModuleLoader for Loader {
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, TriconeError> {
+        Ok(Loader::source(name).map(|source| asm::parse_module(source).unwrap()))
+    }
+
+    fn has_module(&self, name: &str) -> bool {
+        Loader::source(name).is_some()
+    }
+}
+
+#[test]
+fn modules_are_imported_through_the_loader() {
+    let (mut itrp, _) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    assert_eq!(eval(&mut itrp, "import first\nget_member value"), "2");
+    assert!(itrp.lookup_module_index("second").is_some());
+    assert!(matches!(
+        eval_err(&mut itrp, "import third"),
+        ErrorKind::ImportError
+    ));
+}
+
+#[test]
+fn linked_modules_are_imported_when_first_used() {
+    let (mut itrp, _) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    register(
+        &mut itrp,
+        "
+module lazy
+fn peek 0
+    get_module_globals second
+    get_member value
+end
+fn make 0
+    create_object boxes Box 0
+end
+fn make_missing 0
+    create_object boxes Nothing 0
+end
+",
+    );
+    assert!(itrp.lookup_module_index("second").is_none());
+    assert!(itrp.lookup_module_index("boxes").is_none());
+
+    assert_eq!(call(&mut itrp, "lazy", "peek"), "2");
+    assert!(itrp.lookup_module_index("second").is_some());
+    assert_eq!(call(&mut itrp, "lazy", "make"), "<Box>");
+    assert!(itrp.lookup_module_index("boxes").is_some());
+    // The type is only looked for once the module is loaded
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "import lazy\nget_member make_missing\ncall_function_object 0 keep"
+        ),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn modules_the_loader_lacks_fail_the_registration() {
+    let (mut itrp, _) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    let def =
+        asm::parse_module("module hopeful\nfn f 0\n    get_module_globals third\nend\n").unwrap();
+    assert!(matches!(
+        def.register(&mut itrp).map_err(|err| err.kind),
+        Err(ErrorKind::ImportError)
+    ));
+}
+
+#[test]
+fn import_cycles_are_errors() {
+    let (mut itrp, _) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    assert!(matches!(
+        eval_err(&mut itrp, "import loop_a"),
+        ErrorKind::ImportCycle
+    ));
+}
+
+#[test]
+fn import_cycles_through_host_registered_modules_are_errors() {
+    let (mut itrp, _) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    let def =
+        asm::parse_module("module host\ninit\n    import back_to_host\n    pop\nend\n").unwrap();
+    let err = match def.register(&mut itrp) {
+        Ok(_) => panic!("registering didn't fail"),
+        Err(err) => err,
+    };
+    assert!(matches!(err.kind, ErrorKind::ImportCycle));
+    assert_eq!(err.message.as_deref(), Some("host -> back_to_host -> host"));
+    assert!(itrp.lookup_module_index("host").is_none());
+}
+
+#[test]
+fn initializers_run_in_import_order() {
+    let (mut itrp, output) = interpreter();
+    itrp.set_module_loader(Box::new(Loader));
+    register(
+        &mut itrp,
+        "
+module outer
+fn say 1
+    call_method println 0 discard
+end
+init
+    lookup_name say
+    create_string \"outer starts\"
+    call_function_object 1 discard
+    import noisy_init
+    pop
+    lookup_name say
+    create_string \"outer ends\"
+    call_function_object 1 discard
+end
+",
+    );
+    assert_eq!(
+        output.text().lines().collect::<Vec<_>>(),
+        ["outer starts", "noisy_init", "outer ends"]
+    );
+    // Already initialized, so importing it again runs nothing
+    assert_eq!(eval(&mut itrp, "import noisy_init\npop\ncreate_int 1"), "1");
+    assert_eq!(output.text().lines().count(), 3);
+}
+
+const COUNTER: &str = "
+module counter
+fn bump 0
+    get_module_globals counter
+    lookup_name value
+    create_int 1
+    call_method add 1 keep
+    set_member value
+end
+init
+    create_int 0
+    assign value
+    create_string \"hidden\"
+    assign hidden
+end
+export value bump
+";
+
+#[test]
+fn exports_follow_the_module_globals() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, COUNTER);
+    let source = "import counter
+        assign view
+        import counter
+        get_member bump
+        call_function_object 0 discard
+        lookup_name view
+        get_member value";
+    assert_eq!(eval(&mut itrp, source), "1");
+    assert_eq!(call(&mut itrp, "counter", "bump"), "<Unit>");
+    assert_eq!(eval(&mut itrp, "import counter\nget_member value"), "2");
+}
+
+#[test]
+fn exports_are_read_only() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, COUNTER);
+    assert!(matches!(
+        eval_err(&mut itrp, "import counter\ncreate_int 5\nset_member value"),
+        ErrorKind::TypeError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "import counter\ncreate_int 5\nset_member hidden"),
+        ErrorKind::TypeError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "import counter\nget_member hidden"),
+        ErrorKind::NameError
+    ));
+    assert_eq!(eval(&mut itrp, "import counter\nget_member value"), "0");
+}
+
+#[test]
+fn exports_are_made_once_and_accounted_for() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, COUNTER);
+    let before = itrp.memory_usage().live_objects;
+    assert_eq!(eval(&mut itrp, "import counter\nget_member value"), "0");
+    assert_eq!(itrp.memory_usage().live_objects, before + 1);
+    assert_eq!(eval(&mut itrp, "import counter\nget_member value"), "0");
+    assert_eq!(itrp.memory_usage().live_objects, before + 1);
+}
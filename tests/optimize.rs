@@ -0,0 +1,161 @@
+extern crate tricone;
+
+mod common;
+
+use common::{display, instructions, interpreter, register, run};
+use tricone::asm;
+use tricone::function::{self, Code, Function};
+use tricone::interpreter::Instruction;
+use tricone::Interpreter;
+
+/// Runs `instructions` as the code of a function of their own, and describes the outcome.
+fn outcome(itrp: &mut Interpreter, instructions: Vec<Instruction>) -> String {
+    let scope = itrp.create_scope().unwrap();
+    let function = Function::from_code(Code::create(instructions), 0, scope);
+    let res = function.call(itrp, &[]);
+    itrp.drop_token(function.closure.vars);
+    match res {
+        Ok(Some(obj)) => display(itrp, obj),
+        Ok(None) => "nothing".to_owned(),
+        Err(err) => format!("{:?}", err.kind),
+    }
+}
+
+/// The code of the free function `name` of module `opt`, as registering left it.
+fn registered_code(itrp: &mut Interpreter, name: &str) -> Vec<Instruction> {
+    let source = format!("import opt\nget_member {}", name);
+    let obj = run(itrp, &source).unwrap().unwrap();
+    let code = match *function::function_from_function_object(&obj.obj()).code() {
+        Code::Bytecode(ref bytecode) => bytecode.instructions.clone(),
+        Code::Native(_) => panic!("`{}` is native", name),
+    };
+    itrp.drop_token(obj);
+    code
+}
+
+/// Registers `body` as a function, and returns its code after optimizing.
+fn optimized(itrp: &mut Interpreter, body: &str) -> Vec<Instruction> {
+    register(itrp, &format!("module opt\nfn f 0\n{}\nend\n", body));
+    registered_code(itrp, "f")
+}
+
+fn listing(code: &[Instruction]) -> String {
+    let lines: Vec<_> = code.iter().map(asm::format_instruction).collect();
+    lines.join("\n")
+}
+
+/// What `body` does as it is written and once optimized, and the optimized code.
+fn before_and_after(body: &str) -> (String, String, String) {
+    let (mut itrp, _) = interpreter();
+    let before = outcome(&mut itrp, instructions(body));
+    let code = optimized(&mut itrp, body);
+    let listing = listing(&code);
+    let after = outcome(&mut itrp, code);
+    (before, after, listing)
+}
+
+fn assert_same(body: &str) -> String {
+    let (before, after, listing) = before_and_after(body);
+    assert_eq!(before, after, "optimized to:\n{}", listing);
+    listing
+}
+
+#[test]
+fn constants_are_folded() {
+    let listing = assert_same("create_int 2\ncreate_int 40\ncall_method add 1 keep");
+    assert_eq!(listing, "create_int 42");
+    let listing = assert_same(
+        "create_int 6\ncreate_int 7\ncall_method mul 1 keep\ncreate_int 2\ncall_method sub 1 keep",
+    );
+    assert_eq!(listing, "create_int 40");
+    let listing =
+        assert_same("create_string \"tri\"\ncreate_string \"cone\"\ncall_method add 1 keep");
+    assert_eq!(listing, "create_string \"tricone\"");
+}
+
+#[test]
+fn folds_keep_the_result_at_the_end_of_a_function() {
+    let body = "create_int 5\ncreate_int 1\ncreate_int 2\ncall_method add 1 discard";
+    let (before, _, _) = before_and_after(body);
+    assert_eq!(before, "nothing");
+    assert_same(body);
+    // Also when a jump gets there
+    assert_same(
+        "create_int 5\ncreate_int 1\ncreate_int 2\ncall_method add 1 discard\njump out\ncreate_int 9\nout:",
+    );
+}
+
+#[test]
+fn overflowing_arithmetic_is_not_folded() {
+    for op in &["add", "mul"] {
+        let body = format!(
+            "create_int 9223372036854775807\ncreate_int 2\ncall_method {} 1 keep",
+            op
+        );
+        let (before, after, listing) = before_and_after(&body);
+        assert_eq!((before.as_str(), after.as_str()), ("Overflow", "Overflow"));
+        assert_eq!(listing.lines().count(), 3);
+    }
+    let (_, after, _) =
+        before_and_after("create_int -9223372036854775808\ncreate_int 1\ncall_method sub 1 keep");
+    assert_eq!(after, "Overflow");
+}
+
+#[test]
+fn jumps_are_cleaned_up() {
+    let body = "
+    create_bool false
+    jump_if_false first
+    create_int 1
+first:
+    jump second
+    create_int 2
+second:
+    jump third
+third:
+    create_int 3
+";
+    let listing = assert_same(body);
+    assert_eq!(
+        listing,
+        "create_bool false\njump_if_false L3\ncreate_int 1\ncreate_int 3"
+    );
+}
+
+#[test]
+fn diagnostics_stay_unless_the_interpreter_strips_them() {
+    let body = "create_int 1\ndebug_print_object\ndiag\ncreate_int 2";
+    let (mut itrp, output) = interpreter();
+    let code = optimized(&mut itrp, body);
+    assert_eq!(listing(&code), body);
+    assert_eq!(outcome(&mut itrp, code), "2");
+    assert!(!output.text().is_empty());
+
+    let (mut itrp, output) = interpreter();
+    itrp.set_strip_diagnostics(true);
+    let code = optimized(&mut itrp, body);
+    assert_eq!(listing(&code), "create_int 1\npop\ncreate_int 2");
+    assert_eq!(outcome(&mut itrp, code), "2");
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn stripping_keeps_a_diag_the_function_ends_on() {
+    for body in [
+        "create_int 1\ndiag",
+        "create_int 1\ncreate_bool true\njump_if_false 5\ndiag\njump 6\ncreate_int 2",
+    ] {
+        let (mut itrp, _) = interpreter();
+        itrp.set_strip_diagnostics(true);
+        let before = outcome(&mut itrp, instructions(body));
+        let code = optimized(&mut itrp, body);
+        let listing = listing(&code);
+        assert_eq!(before, "nothing");
+        assert_eq!(
+            outcome(&mut itrp, code),
+            before,
+            "optimized to:\n{}",
+            listing
+        );
+    }
+}
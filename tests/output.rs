@@ -0,0 +1,100 @@
+extern crate tricone;
+
+mod common;
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use common::{eval, interpreter, run};
+use tricone::output::{BufferSink, LineSink};
+
+#[test]
+fn buffer_sink_clones_share_the_buffer() {
+    let sink = BufferSink::new();
+    let mut writer = sink.clone();
+    write!(writer, "one ").unwrap();
+    write!(writer, "two").unwrap();
+    assert_eq!(sink.text(), "one two");
+    assert_eq!(sink.take(), b"one two");
+    assert!(sink.contents().is_empty());
+}
+
+#[test]
+fn line_sink_passes_on_complete_lines() {
+    let lines = Rc::new(RefCell::new(vec![]));
+    let seen = lines.clone();
+    let mut sink = LineSink::new(move |line| seen.borrow_mut().push(line.to_owned()));
+    write!(sink, "first\nsec").unwrap();
+    assert_eq!(*lines.borrow(), ["first"]);
+    write!(sink, "ond\r\n\nthird").unwrap();
+    assert_eq!(*lines.borrow(), ["first", "second", ""]);
+    sink.flush().unwrap();
+    assert_eq!(*lines.borrow(), ["first", "second", "", "third"]);
+    write!(sink, "last").unwrap();
+    drop(sink);
+    assert_eq!(*lines.borrow(), ["first", "second", "", "third", "last"]);
+}
+
+#[test]
+fn println_goes_to_a_line_sink() {
+    let (mut itrp, _) = interpreter();
+    let lines = Rc::new(RefCell::new(vec![]));
+    let seen = lines.clone();
+    itrp.set_stdout(Box::new(LineSink::new(move |line| {
+        seen.borrow_mut().push(line.to_owned())
+    })));
+    let res = run(
+        &mut itrp,
+        "create_string \"a\"\ncall_method println 0 discard\ncreate_string \"b\"\ncall_method println 0 discard",
+    );
+    assert!(matches!(res, Ok(None)));
+    assert_eq!(*lines.borrow(), ["a", "b"]);
+}
+
+#[test]
+fn tracing_goes_to_stdout() {
+    let (mut itrp, output) = interpreter();
+    itrp.set_tracing(true);
+    assert_eq!(
+        eval(&mut itrp, "create_int 1\nassign x\nlookup_name x"),
+        "1"
+    );
+    itrp.set_tracing(false);
+    let text = output.text();
+    assert!(
+        text.contains("running CreateInt { value: 1 }\n"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("running LookupName { name: \"x\" }\n"),
+        "{}",
+        text
+    );
+    assert!(text.contains("found x!\n"), "{}", text);
+}
+
+#[test]
+fn replacing_stdout_flushes_the_old_one() {
+    let (mut itrp, _) = interpreter();
+    let lines = Rc::new(RefCell::new(vec![]));
+    let seen = lines.clone();
+    itrp.set_stdout(Box::new(LineSink::new(move |line| {
+        seen.borrow_mut().push(line.to_owned())
+    })));
+    write!(itrp.stdout(), "unfinished").unwrap();
+    assert!(lines.borrow().is_empty());
+    itrp.set_stdout(Box::new(BufferSink::new()));
+    assert_eq!(*lines.borrow(), ["unfinished"]);
+}
+
+#[test]
+fn stderr_can_be_captured() {
+    let (mut itrp, output) = interpreter();
+    let errors = BufferSink::new();
+    itrp.set_stderr(Box::new(errors.clone()));
+    writeln!(itrp.stderr(), "oops").unwrap();
+    assert_eq!(errors.text(), "oops\n");
+    assert!(output.text().is_empty());
+}
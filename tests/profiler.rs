@@ -0,0 +1,150 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, interpreter, register};
+use tricone::interpreter::InstructionKind;
+use tricone::lang;
+use tricone::profiler::{Profile, Profiler};
+use tricone::Interpreter;
+
+const MODULE: &str = "
+module prof
+fn inner 0
+    locals n
+    create_int 1
+    store_local 0
+    load_local 0
+    create_int 2
+    call_method add 1 keep
+end
+fn outer 0
+    get_module_globals prof
+    get_member inner
+    call_function_object 0 keep
+    get_module_globals prof
+    get_member inner
+    call_function_object 0 keep
+    call_method mul 1 keep
+end
+";
+
+fn profile(itrp: &mut Interpreter, profiler: Profiler, function: &str) -> Profile {
+    itrp.start_profiling(profiler);
+    call(itrp, "prof", function);
+    itrp.stop_profiling().expect("the profiler is gone")
+}
+
+#[test]
+fn counts_instructions_by_kind() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let profile = profile(&mut itrp, Profiler::new(), "outer");
+    // `call` runs three instructions of its own
+    assert_eq!(profile.instructions, 3 + 7 + 2 * 5);
+    assert_eq!(profile.instruction_count(InstructionKind::CreateInt), 4);
+    assert_eq!(profile.instruction_count(InstructionKind::CallMethod), 3);
+    assert_eq!(
+        profile.instruction_count(InstructionKind::CallFunctionObject),
+        3
+    );
+    assert_eq!(profile.instruction_count(InstructionKind::Jump), 0);
+}
+
+#[test]
+fn counts_calls_and_instructions_by_function() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let profile = profile(&mut itrp, Profiler::new(), "outer");
+
+    let outer = profile.function("prof::outer").unwrap();
+    assert_eq!(
+        (
+            outer.calls,
+            outer.instructions,
+            outer.inclusive_instructions
+        ),
+        (1, 7, 17)
+    );
+    let inner = profile.function("prof::inner").unwrap();
+    assert_eq!(
+        (
+            inner.calls,
+            inner.instructions,
+            inner.inclusive_instructions
+        ),
+        (2, 10, 10)
+    );
+    assert_eq!(profile.function("core::Int::add").unwrap().calls, 2);
+    assert_eq!(profile.function("core::Int::mul").unwrap().calls, 1);
+    assert_eq!(profile.function("<top>").unwrap().instructions, 3);
+    assert!(outer.inclusive_time >= outer.time);
+}
+
+#[test]
+fn recursion_is_counted_once_inclusively() {
+    let (mut itrp, _) = interpreter();
+    let source = "fn down(n) { if n == 0 { 0 } else { down(n - 1) } }\nfn main() { down(3) }";
+    lang::compile_module("rec", source)
+        .unwrap()
+        .register(&mut itrp)
+        .unwrap();
+    itrp.start_profiling(Profiler::new());
+    call(&mut itrp, "rec", "main");
+    let profile = itrp.stop_profiling().unwrap();
+
+    let down = profile.function("rec::down").unwrap();
+    assert_eq!(down.calls, 4);
+    let main = profile.function("rec::main").unwrap();
+    assert_eq!(
+        main.inclusive_instructions,
+        main.instructions + down.inclusive_instructions
+    );
+    assert!(down.inclusive_instructions >= down.instructions);
+    assert!(down.inclusive_instructions <= profile.instructions);
+}
+
+#[test]
+fn samples_fold_call_stacks() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let profile = profile(&mut itrp, Profiler::new(), "outer");
+    let samples: Vec<_> = profile
+        .samples
+        .iter()
+        .map(|(stack, count)| (stack.as_str(), *count))
+        .collect();
+    assert_eq!(
+        samples,
+        vec![
+            ("<top>", 3),
+            ("prof::outer", 7),
+            ("prof::outer;prof::inner", 10),
+        ]
+    );
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "<top> 3\nprof::outer 7\nprof::outer;prof::inner 10\n"
+    );
+}
+
+#[test]
+fn sample_intervals_spread_the_count() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    let profile = profile(&mut itrp, Profiler::with_sample_interval(4), "outer");
+    let sampled: u64 = profile.samples.iter().map(|(_, count)| count).sum();
+    assert_eq!(sampled, profile.instructions / 4 * 4);
+}
+
+#[test]
+fn nothing_is_counted_once_stopped() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    profile(&mut itrp, Profiler::new(), "outer");
+    assert!(itrp.stop_profiling().is_none());
+    assert_eq!(call(&mut itrp, "prof", "outer"), "9");
+}
@@ -0,0 +1,192 @@
+extern crate tricone;
+
+mod common;
+
+use common::{call, eval, eval_err, interpreter, register, run};
+use tricone::asm;
+use tricone::interpreter::{ErrorKind, ReloadIncompatibility, ReloadReport};
+use tricone::Interpreter;
+
+const V1: &str = "
+module greet
+type Greeter
+    method hi 1
+        create_string \"v1\"
+    end
+    method wave 1
+        create_string \"wave\"
+    end
+end
+type Spare
+end
+fn version 0
+    create_string \"v1\"
+end
+fn old_only 0
+    create_int 1
+end
+";
+
+const V2: &str = "
+module greet
+type Greeter
+    method hi 1
+        create_string \"v2\"
+    end
+end
+type Added
+end
+fn version 0
+    create_string \"v2\"
+end
+";
+
+fn reload(itrp: &mut Interpreter, source: &str) -> ReloadReport {
+    let def = asm::parse_module(source).unwrap();
+    itrp.reload_module(def)
+        .unwrap_or_else(|err| panic!("reloading failed: {}", err))
+}
+
+// Keeps a greeter alive in a global the definition doesn't create
+fn keep_greeter(itrp: &mut Interpreter) {
+    let res = run(
+        itrp,
+        "get_module_globals greet\ncreate_object greet Greeter 0\nset_member held",
+    );
+    assert!(matches!(res, Ok(None)));
+}
+
+#[test]
+fn functions_and_methods_are_replaced() {
+    let (mut itrp, _) = interpreter();
+    let index = register(&mut itrp, V1);
+    keep_greeter(&mut itrp);
+    let report = reload(&mut itrp, V2);
+
+    assert_eq!(itrp.lookup_module_index("greet"), Some(index));
+    assert_eq!(call(&mut itrp, "greet", "version"), "v2");
+    // Existing objects pick up the new methods
+    let hi = "get_module_globals greet\nget_member held\ncall_method hi 0 keep";
+    assert_eq!(eval(&mut itrp, hi), "v2");
+    assert_eq!(report.added_types, ["Added"]);
+    assert_eq!(report.removed_types, ["Spare"]);
+    assert_eq!(report.removed_globals, ["old_only"]);
+    assert!(matches!(
+        eval_err(&mut itrp, "get_module_globals greet\nget_member old_only"),
+        ErrorKind::NameError
+    ));
+    assert!(matches!(
+        eval_err(&mut itrp, "create_object greet Spare 0"),
+        ErrorKind::NameError
+    ));
+    assert!(matches!(
+        run(&mut itrp, "create_object greet Added 0\npop"),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn removed_methods_in_use_are_kept() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, V1);
+    keep_greeter(&mut itrp);
+    let report = reload(&mut itrp, V2);
+
+    assert!(!report.is_compatible());
+    assert!(report
+        .incompatibilities
+        .contains(&ReloadIncompatibility::RemovedMethodInUse {
+            type_name: "Greeter".to_owned(),
+            method: "wave".to_owned(),
+            live_instances: 1,
+        }));
+    let wave = "get_module_globals greet\nget_member held\ncall_method wave 0 keep";
+    assert_eq!(eval(&mut itrp, wave), "wave");
+}
+
+#[test]
+fn removed_methods_go_when_nothing_uses_them() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, V1);
+    let report = reload(&mut itrp, V2);
+
+    assert!(report.is_compatible());
+    assert!(matches!(
+        eval_err(
+            &mut itrp,
+            "create_object greet Greeter 0\ncall_method wave 0 keep"
+        ),
+        ErrorKind::NameError
+    ));
+}
+
+#[test]
+fn removed_types_in_use_keep_their_methods() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, V1);
+    keep_greeter(&mut itrp);
+    let report = reload(&mut itrp, "module greet\n");
+
+    assert!(report
+        .incompatibilities
+        .contains(&ReloadIncompatibility::RemovedTypeInUse {
+            type_name: "Greeter".to_owned(),
+            live_instances: 1,
+        }));
+    let hi = "get_module_globals greet\nget_member held\ncall_method hi 0 keep";
+    assert_eq!(eval(&mut itrp, hi), "v1");
+}
+
+#[test]
+fn arity_changes_are_reported() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, V1);
+    let report = reload(
+        &mut itrp,
+        "module greet\ntype Greeter\n    method hi 2\n        pop\n        create_string \"v3\"\n    end\nend\n",
+    );
+    assert!(report
+        .incompatibilities
+        .contains(&ReloadIncompatibility::ArityChanged {
+            name: "Greeter.hi".to_owned(),
+            old: 1,
+            new: 2,
+        }));
+}
+
+#[test]
+fn the_initializer_runs_again() {
+    let (mut itrp, _) = interpreter();
+    let source = |value: i64| {
+        format!(
+            "module config\ninit\n    create_int {}\n    assign value\nend\n",
+            value
+        )
+    };
+    register(&mut itrp, &source(1));
+    assert_eq!(eval(&mut itrp, "import config\nget_member value"), "1");
+    reload(&mut itrp, &source(2));
+    assert_eq!(eval(&mut itrp, "import config\nget_member value"), "2");
+}
+
+#[test]
+fn reloading_releases_the_old_code() {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, V1);
+    reload(&mut itrp, V1);
+    let usage = itrp.memory_usage();
+    for _ in 0..5 {
+        reload(&mut itrp, V1);
+    }
+    assert_eq!(itrp.memory_usage(), usage);
+}
+
+#[test]
+fn only_registered_modules_can_be_reloaded() {
+    let (mut itrp, _) = interpreter();
+    let def = asm::parse_module(V1).unwrap();
+    assert!(matches!(
+        itrp.reload_module(def).map_err(|err| err.kind),
+        Err(ErrorKind::NameError)
+    ));
+}
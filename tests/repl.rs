@@ -0,0 +1,126 @@
+extern crate tricone;
+
+mod common;
+
+use common::interpreter;
+use tricone::output::BufferSink;
+use tricone::repl::Repl;
+
+/// Feeds `input` to a new repl, and returns what it wrote, prompts included.
+fn session(input: &str) -> String {
+    let (itrp, _) = interpreter();
+    let mut repl = Repl::new(itrp).unwrap();
+    let out = BufferSink::new();
+    repl.run(input.as_bytes(), out.clone()).unwrap();
+    out.text()
+}
+
+const PAIR: &str = "type Pair
+    method sum 1
+        create_int 3
+    end
+end
+fn two 0
+    create_int 2
+end
+create_object repl Pair 0 \\
+assign p
+";
+
+#[test]
+fn lines_print_their_result_and_keep_it() {
+    let out = session("create_int 1 \\\ncreate_int 2 \\\ncall_method add 1 keep\nlookup_name _\n");
+    assert_eq!(out, "> . . 3\n> 3\n> ");
+}
+
+#[test]
+fn blocks_span_lines_until_their_end() {
+    let out = session(&format!(
+        "{}lookup_name two \\\ncall_function_object 0 keep\nlookup_name p \\\ncall_method sum 0 keep\n",
+        PAIR
+    ));
+    assert_eq!(out, "> . . . . > . . > . > . 2\n> . 3\n> ");
+}
+
+#[test]
+fn blocks_can_be_redefined() {
+    let out = session(
+        "fn two 0\n    create_int 2\nend\nfn two 0\n    create_int 22\nend\nlookup_name two \\\ncall_function_object 0 keep\n",
+    );
+    assert!(out.ends_with("22\n> "), "{}", out);
+}
+
+#[test]
+fn commands_describe_the_interpreter() {
+    let out = session(&format!(
+        "{}:modules\n:types repl\n:methods Pair\n:methods repl.Pair\n:scope\n:disasm two\n:disasm repl.Pair.sum\n",
+        PAIR
+    ));
+    let expected = "\
+>   0 core (7 types)
+  1 builtins (0 types)
+  2 repl (1 types)
+> Pair (1 live)
+> sum/1 (bytecode)
+> sum/1 (bytecode)
+> p: Pair
+two: Function
+> ";
+    assert!(out.contains(expected), "{}", out);
+    assert!(out.ends_with(
+        ">     create_int 2                             ; 0\n>     create_int 3                             ; 0\n> "
+    ), "{}", out);
+}
+
+#[test]
+fn commands_report_what_they_can_not_find() {
+    let out = session(":types nope\n:methods Nope\n:disasm nope\n:bogus\n");
+    assert_eq!(
+        out,
+        "> error: no module named `nope`
+> error: no type named `Nope`
+> error: no function named `nope`
+> error: unknown command `:bogus`, try :help
+> "
+    );
+    assert!(session(":help\n").contains(":disasm <fn>"));
+}
+
+#[test]
+fn stats_count_since_the_last_reset() {
+    let out = session("create_int 1\n:stats reset\ncreate_bool true\n:stats\n");
+    let stats = out.rsplit("> ").nth(1).unwrap();
+    assert!(stats.contains("1  CreateBool\n"), "{}", stats);
+    assert!(!stats.contains("CreateInt"), "{}", stats);
+}
+
+#[test]
+fn the_repl_stays_usable_after_errors() {
+    let out = session(
+        "create_thing 1
+lookup_name missing
+fn two 0
+    create_int 2
+end
+fn two 0
+    bogus_instruction
+end
+lookup_name two \\
+call_function_object 0 keep
+",
+    );
+    assert_eq!(
+        out,
+        "> error: line 1: unknown instruction `create_thing`
+> error: NameError: name `missing` is not defined
+  in <repl> with 0 arguments, instruction 0
+> . . > . . error: line 2: unknown instruction `bogus_instruction`
+> . 2
+> "
+    );
+}
+
+#[test]
+fn quitting_stops_reading() {
+    assert_eq!(session("create_int 5\n:quit\ncreate_int 6\n"), "> 5\n> ");
+}
@@ -0,0 +1,120 @@
+extern crate tricone;
+
+mod common;
+
+use common::{interpreter, register, run};
+use tricone::interpreter::InstructionKind;
+use tricone::Interpreter;
+
+const MODULE: &str = "
+module counted
+type Box
+end
+fn fill 0
+    create_object counted Box 0
+    assign kept
+    create_object counted Box 0
+    pop
+    create_int 1
+    create_bool true
+    pop
+    lookup_name kept
+    pop
+end
+fn find 0
+    create_int 1
+    assign local
+    lookup_name answer
+    lookup_name missing
+end
+init
+    create_int 42
+    assign answer
+end
+";
+
+fn counted() -> Interpreter {
+    let (mut itrp, _) = interpreter();
+    register(&mut itrp, MODULE);
+    itrp.reset_stats();
+    itrp
+}
+
+fn call(itrp: &mut Interpreter, function: &str) -> bool {
+    let source = format!(
+        "import counted\nget_member {}\ncall_function_object 0 discard",
+        function
+    );
+    run(itrp, &source).is_ok()
+}
+
+fn type_counts(itrp: &Interpreter) -> Vec<(String, u64, u64)> {
+    itrp.stats()
+        .types
+        .iter()
+        .map(|ty| (ty.name.clone(), ty.created, ty.dropped))
+        .collect()
+}
+
+#[test]
+fn instructions_are_counted_by_kind() {
+    let mut itrp = counted();
+    assert!(call(&mut itrp, "fill"));
+    let stats = itrp.stats();
+    let counts: Vec<_> = [
+        InstructionKind::Pop,
+        InstructionKind::CreateObjectResolved,
+        InstructionKind::CreateInt,
+        InstructionKind::CreateBool,
+        InstructionKind::LookupName,
+        InstructionKind::Jump,
+    ]
+    .iter()
+    .map(|&kind| stats.instruction_count(kind))
+    .collect();
+    assert_eq!(counts, vec![3, 2, 1, 1, 1, 0]);
+    // With the `import`, `get_member` and call of the host code
+    assert_eq!(stats.total_instructions(), 9 + 3);
+}
+
+#[test]
+fn objects_are_counted_by_type() {
+    let mut itrp = counted();
+    assert!(call(&mut itrp, "fill"));
+    // `assign` gave the frame a scope of its own; the `Int` and the `Bool` are immediates
+    assert_eq!(
+        type_counts(&itrp),
+        vec![
+            ("core::Scope".to_owned(), 1, 1),
+            ("counted::Box".to_owned(), 2, 2),
+        ]
+    );
+    let module = itrp.lookup_module_index("counted").unwrap();
+    let ty = itrp.lookup_type(module, "Box").unwrap();
+    assert_eq!(itrp.stats().type_stats(ty).map(|ty| ty.created), Some(2));
+}
+
+#[test]
+fn name_lookups_count_their_misses() {
+    let mut itrp = counted();
+    assert!(!call(&mut itrp, "find"));
+    let stats = itrp.stats();
+    // `answer` is one scope up, `missing` is in neither
+    assert_eq!(
+        (stats.name_lookups, stats.scope_misses, stats.failed_lookups),
+        (2, 3, 1)
+    );
+}
+
+#[test]
+fn resetting_starts_over() {
+    let mut itrp = counted();
+    assert!(call(&mut itrp, "fill"));
+    itrp.reset_stats();
+    let stats = itrp.stats();
+    assert_eq!(stats.total_instructions(), 0);
+    assert!(stats.types.is_empty());
+    assert_eq!((stats.name_lookups, stats.inline_cache_misses), (0, 0));
+    assert!(call(&mut itrp, "fill"));
+    assert_eq!(itrp.stats().total_instructions(), 12);
+}
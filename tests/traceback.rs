@@ -0,0 +1,118 @@
+extern crate tricone;
+
+mod common;
+
+use common::interpreter;
+use tricone::interpreter::{TracebackEntry, TriconeError};
+use tricone::lang;
+use tricone::repl::Repl;
+use tricone::Interpreter;
+
+const SOURCE: &str = "type T {
+    fn create(self) { }
+    fn boom(self, x) { x + missing }
+}
+fn helper(a, b) {
+    let t = new T();
+    t.boom(a)
+}
+fn main() {
+    helper(1, 2)
+}";
+
+fn fail_main(itrp: &mut Interpreter) -> TriconeError {
+    let def = lang::compile_module("tb", SOURCE).unwrap();
+    def.register(itrp).unwrap();
+    match common::run(
+        itrp,
+        "import tb\nget_member main\ncall_function_object 0 keep",
+    ) {
+        Ok(_) => panic!("main didn't fail"),
+        Err(err) => err,
+    }
+}
+
+fn functions(traceback: &[TracebackEntry]) -> Vec<Option<&str>> {
+    traceback
+        .iter()
+        .map(|entry| entry.function.as_deref())
+        .collect()
+}
+
+#[test]
+fn captures_the_active_frames() {
+    let (mut itrp, _) = interpreter();
+    let err = fail_main(&mut itrp);
+    assert_eq!(
+        functions(&err.traceback),
+        vec![
+            None,
+            Some("tb::main"),
+            Some("tb::helper"),
+            Some("tb::T::boom")
+        ]
+    );
+    let args: Vec<_> = err.traceback.iter().map(|entry| entry.num_args).collect();
+    assert_eq!(args, vec![0, 0, 2, 2]);
+}
+
+#[test]
+fn entries_are_located() {
+    let (mut itrp, _) = interpreter();
+    let err = fail_main(&mut itrp);
+    let lines: Vec<_> = err
+        .traceback
+        .iter()
+        .map(|entry| entry.location.as_ref().map(|location| location.line))
+        .collect();
+    assert_eq!(lines, vec![None, Some(10), Some(7), Some(3)]);
+    assert_eq!(err.location, err.traceback[3].location);
+    let innermost = err.traceback[3].to_string();
+    assert!(
+        innermost.starts_with("in tb::T::boom with 2 arguments, instruction "),
+        "{}",
+        innermost
+    );
+    assert!(innermost.ends_with(" at tb.tri:3:28"), "{}", innermost);
+}
+
+#[test]
+fn frames_are_gone_once_the_error_is_returned() {
+    let (mut itrp, _) = interpreter();
+    fail_main(&mut itrp);
+    assert!(itrp.backtrace().is_empty());
+}
+
+#[test]
+fn native_errors_get_the_callers_frames() {
+    let (mut itrp, _) = interpreter();
+    let err = common::run(
+        &mut itrp,
+        "create_int 1\ncreate_string \"a\"\ncall_method add 1 keep",
+    )
+    .unwrap_err();
+    assert!(!err.traceback.is_empty());
+    assert_eq!(err.traceback[0].function, None);
+    assert_eq!(
+        err.traceback.last().unwrap().function.as_deref(),
+        Some("core::Int::add")
+    );
+}
+
+#[test]
+fn the_repl_prints_tracebacks() {
+    let (itrp, _) = interpreter();
+    let mut repl = Repl::new(itrp).unwrap();
+    let input = "fn inner 0\n    lookup_name missing\nend\nlookup_name inner \\\ncall_function_object 0 keep\n";
+    let mut out = vec![];
+    repl.run(input.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("error: NameError"), "{}", out);
+    assert!(
+        out.contains(
+            "\n  in <repl> with 0 arguments, instruction 1\n  in repl::inner with 0 arguments"
+        ),
+        "{}",
+        out
+    );
+}